@@ -0,0 +1,39 @@
+/* C interface of libschedule24, implemented in src/ffi.rs. Build the library with
+ * `cargo rustc --release --features ffi --crate-type cdylib` (or `staticlib`).
+ *
+ * Strings are NUL terminated UTF-8. Returned strings belong to the caller and are released
+ * with ls24_string_free. On failure functions return NULL and ls24_last_error describes
+ * why. Fetching blocks the calling thread, results are cached on disk like in Rust. */
+
+#ifndef LIBSCHEDULE24_H
+#define LIBSCHEDULE24_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The week's lessons of a class as JSON, see json::SimpleSchedule */
+char *ls24_week_json(const char *domain, const char *school, const char *class_name, int week);
+
+/* The week's lessons of a class as an iCalendar document, needs the ics feature */
+char *ls24_week_ics(const char *domain, const char *school, const char *class_name, int week);
+
+/* The week, or for day 1 (Monday) to 7 one day, of a class drawn as SVG */
+char *ls24_render_svg(const char *domain, const char *school, const char *class_name, int week, int day, uint32_t width, uint32_t height);
+
+/* Draws a schema JSON document fetched earlier as SVG */
+char *ls24_render_schema_svg(const char *schema_json, uint32_t width, uint32_t height);
+
+/* Why the last call on this thread failed, NULL if none did. Owned by the library. */
+const char *ls24_last_error(void);
+
+/* Releases a string returned by the functions above, NULL is ignored */
+void ls24_string_free(char *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
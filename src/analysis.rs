@@ -0,0 +1,294 @@
+use chrono::{Duration, NaiveDate, NaiveTime, Weekday};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+use crate::data::{BoxType, Response, Schema};
+use crate::model::{Lesson, TimeRange, WeekSchedule};
+
+// Lesson times merged into non-overlapping ranges, earliest first
+pub(crate) fn busy_ranges<'a, I>(lessons: I) -> Vec<TimeRange>
+where
+    I: IntoIterator<Item = &'a Lesson>,
+{
+    let mut ranges: Vec<TimeRange> = lessons.into_iter().map(Lesson::time_range).collect();
+    ranges.sort();
+
+    let mut merged: Vec<TimeRange> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => {
+                last.end = last.end.max(range.end);
+            },
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+// Gaps between the lessons of a day, ignoring transitions shorter than five minutes
+pub fn free_periods(day: &[Lesson]) -> Vec<TimeRange> {
+    free_periods_longer_than(day, Duration::minutes(5))
+}
+
+pub fn free_periods_longer_than(day: &[Lesson], min_gap: Duration) -> Vec<TimeRange> {
+    busy_ranges(day)
+        .windows(2)
+        .map(|pair| TimeRange::new(pair[0].end, pair[1].start))
+        .filter(|gap| gap.duration() >= min_gap)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LunchOptions {
+    // The lunch break has to overlap this window
+    pub window: TimeRange,
+    pub min_duration: Duration,
+}
+
+impl Default for LunchOptions {
+    fn default() -> Self {
+        LunchOptions {
+            window: TimeRange::new(NaiveTime::from_hms_opt(10, 30, 0).unwrap(), NaiveTime::from_hms_opt(13, 30, 0).unwrap()),
+            min_duration: Duration::minutes(20),
+        }
+    }
+}
+
+pub fn lunch_break(day: &[Lesson]) -> Option<TimeRange> {
+    lunch_break_with(day, &LunchOptions::default())
+}
+
+// A scheduled "Lunch" entry if there is one, otherwise the free period with the most overlap with the window
+pub fn lunch_break_with(day: &[Lesson], options: &LunchOptions) -> Option<TimeRange> {
+    let scheduled = day.iter()
+        .find(|lesson| lesson.subject.trim().eq_ignore_ascii_case("lunch"))
+        .map(Lesson::time_range);
+    if scheduled.is_some() {
+        return scheduled;
+    }
+
+    free_periods_longer_than(day, options.min_duration)
+        .into_iter()
+        .filter(|gap| gap.overlaps(&options.window))
+        .max_by_key(|gap| gap.end.min(options.window.end) - gap.start.max(options.window.start))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaySummary {
+    pub first_start: Option<NaiveTime>,
+    pub last_end: Option<NaiveTime>,
+    pub lesson_count: usize,
+    // Overlapping lessons are only counted once
+    pub total_teaching_time: Duration,
+    pub longest_gap: Option<TimeRange>,
+}
+
+pub fn day_summary(day: &[Lesson]) -> DaySummary {
+    let busy = busy_ranges(day);
+
+    DaySummary {
+        first_start: busy.first().map(|range| range.start),
+        last_end: busy.last().map(|range| range.end),
+        lesson_count: day.len(),
+        total_teaching_time: busy.iter().fold(Duration::zero(), |total, range| total + range.duration()),
+        longest_gap: free_periods_longer_than(day, Duration::zero())
+            .into_iter()
+            .max_by_key(TimeRange::duration),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeekStats {
+    pub time_per_subject: BTreeMap<String, Duration>,
+    pub busiest_day: Option<Weekday>,
+    pub earliest_start: Option<NaiveTime>,
+    pub latest_end: Option<NaiveTime>,
+}
+
+impl WeekStats {
+    pub fn hours_per_subject(&self) -> BTreeMap<&str, f64> {
+        self.time_per_subject.iter()
+            .map(|(subject, time)| (subject.as_str(), time.num_minutes() as f64 / 60.0))
+            .collect()
+    }
+}
+
+pub fn week_stats(week: &WeekSchedule) -> WeekStats {
+    let mut time_per_subject = BTreeMap::new();
+    for lesson in week.lessons().filter(|lesson| !lesson.subject.is_empty()) {
+        let total = time_per_subject.entry(lesson.subject.clone()).or_insert_with(Duration::zero);
+        *total += lesson.duration();
+    }
+
+    let days: Vec<(Weekday, DaySummary)> = week.days.iter()
+        .map(|day| (day.weekday, day_summary(&day.lessons)))
+        .filter(|(_, summary)| summary.lesson_count > 0)
+        .collect();
+
+    WeekStats {
+        time_per_subject,
+        busiest_day: days.iter()
+            .max_by_key(|(_, summary)| summary.total_teaching_time)
+            .map(|(weekday, _)| *weekday),
+        earliest_start: days.iter().filter_map(|(_, summary)| summary.first_start).min(),
+        latest_end: days.iter().filter_map(|(_, summary)| summary.last_end).max(),
+    }
+}
+
+// Index pairs `(i, j)` with `i < j` of lessons overlapping in time on the same day
+pub fn find_conflicts(lessons: &[Lesson]) -> Vec<(usize, usize)> {
+    let mut conflicts = Vec::new();
+    for i in 0..lessons.len() {
+        for j in i + 1..lessons.len() {
+            if lessons[i].weekday == lessons[j].weekday && lessons[i].time_range().overlaps(&lessons[j].time_range()) {
+                conflicts.push((i, j));
+            }
+        }
+    }
+
+    conflicts
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LessonBlock<'a> {
+    pub name: String,
+    pub weekday: Weekday,
+    pub lessons: Vec<&'a Lesson>,
+    // From the earliest start to the latest end of the member lessons
+    pub span: TimeRange,
+}
+
+// Groups lessons sharing a block on the same day, lessons without a block are left out
+pub fn group_by_block(lessons: &[Lesson]) -> Vec<LessonBlock<'_>> {
+    let mut blocks: Vec<LessonBlock> = Vec::new();
+    for lesson in lessons {
+        let name = match &lesson.block {
+            Some(name) => name,
+            None => continue,
+        };
+
+        match blocks.iter_mut().find(|b| b.name == *name && b.weekday == lesson.weekday) {
+            Some(block) => {
+                block.lessons.push(lesson);
+                block.span = TimeRange::new(block.span.start.min(lesson.start), block.span.end.max(lesson.end));
+            },
+            None => blocks.push(LessonBlock {
+                name: name.clone(),
+                weekday: lesson.weekday,
+                lessons: vec![lesson],
+                span: lesson.time_range(),
+            }),
+        }
+    }
+
+    blocks
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreeSlot {
+    pub weekday: Weekday,
+    pub date: Option<NaiveDate>,
+    pub range: TimeRange,
+}
+
+// Slots within `school_hours` on weekdays where none of the schedules have a lesson
+pub fn common_free_slots(schedules: &[WeekSchedule], school_hours: TimeRange, min_duration: Duration) -> Vec<FreeSlot> {
+    let mut slots = Vec::new();
+
+    for &weekday in &WEEKDAYS[..5] {
+        let busy = busy_ranges(schedules.iter().flat_map(|schedule| schedule.lessons_on(weekday)));
+
+        let mut start = school_hours.start;
+        for range in busy.iter().chain(std::iter::once(&TimeRange::new(school_hours.end, school_hours.end))) {
+            let end = range.start.min(school_hours.end);
+            if end > start && end - start >= min_duration {
+                slots.push(FreeSlot {
+                    weekday,
+                    date: schedules.first().and_then(|schedule| schedule.date_of(weekday)),
+                    range: TimeRange::new(start, end),
+                });
+            }
+            start = start.max(range.end);
+        }
+    }
+
+    slots
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubjectTotal {
+    pub subject: String,
+    pub minutes: i64,
+    pub lesson_count: usize,
+}
+
+// Minutes per subject over any number of lessons, e.g. a week or all weeks of a term. Most time first.
+pub fn subject_totals<'a, I>(lessons: I) -> Vec<SubjectTotal>
+where
+    I: IntoIterator<Item = &'a Lesson>,
+{
+    let mut totals: BTreeMap<&str, SubjectTotal> = BTreeMap::new();
+    for lesson in lessons.into_iter().filter(|lesson| !lesson.subject.is_empty()) {
+        let total = totals.entry(&lesson.subject).or_insert_with(|| SubjectTotal {
+            subject: lesson.subject.clone(),
+            minutes: 0,
+            lesson_count: 0,
+        });
+        total.minutes += lesson.duration().num_minutes();
+        total.lesson_count += 1;
+    }
+
+    let mut totals: Vec<SubjectTotal> = totals.into_values().collect();
+    totals.sort_by_key(|total| std::cmp::Reverse(total.minutes));
+    totals
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmptyReason {
+    // A holiday ("lov") text was rendered in place of lessons
+    Holiday(String),
+    // The API rejected the selection
+    Validation,
+    // Nothing at all was rendered, usually an API glitch
+    NoRenderData,
+    // Lesson boxes were rendered but no lesson info was sent along
+    MissingLessonInfo,
+    // A rendered but empty timetable, e.g. a day without lessons or a wrong selection GUID
+    NoLessons,
+}
+
+// Why a schema has no lessons, `None` if it has any
+pub fn empty_reason(response: &Response<Schema>) -> Option<EmptyReason> {
+    let schema = &response.data;
+    if !schema.lesson_info.is_empty() {
+        return None;
+    }
+
+    if !response.validation.is_empty() {
+        return Some(EmptyReason::Validation);
+    }
+
+    let holiday = schema.text_list.iter().find(|text| {
+        text.text.split_whitespace().any(|word| word.to_lowercase().ends_with("lov"))
+    });
+    if let Some(text) = holiday {
+        return Some(EmptyReason::Holiday(text.text.clone()));
+    }
+
+    if schema.box_list.is_empty() && schema.text_list.is_empty() {
+        Some(EmptyReason::NoRenderData)
+    }
+    else if schema.boxes_of_type(BoxType::Lesson).next().is_some() {
+        Some(EmptyReason::MissingLessonInfo)
+    }
+    else {
+        Some(EmptyReason::NoLessons)
+    }
+}
+
+pub(crate) const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun,
+];
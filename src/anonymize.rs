@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use crate::data::{LessonInfo, Schema};
+use crate::model::{Lesson, WeekSchedule};
+
+// Removes teacher signatures, names given with `add_name`, e.g. of the student a schedule
+// belongs to, and selection GUIDs from schedules, so they can be shared or attached to bug
+// reports. Names are replaced wherever they appear as a whole word, also in the header and
+// other texts of a schema, and the same name always gets the same pseudonym ("T1", "T2", …
+// for teachers, "N1", … for other names, "S1", … for selections). Subjects, rooms and times are kept.
+//
+// Teachers are learnt from the lessons seen, so a schema should be anonymized as a whole with
+// `schema` rather than text by text.
+#[derive(Debug, Clone, Default)]
+pub struct Anonymizer {
+    // Remove names instead of replacing them with pseudonyms
+    strip: bool,
+    pseudonyms: HashMap<String, String>,
+    teachers: usize,
+    names: usize,
+    sources: usize,
+}
+
+impl Anonymizer {
+    pub fn new() -> Self {
+        Anonymizer::default()
+    }
+
+    // Removes names instead of replacing them with pseudonyms
+    pub fn stripping() -> Self {
+        Anonymizer {
+            strip: true,
+            ..Anonymizer::default()
+        }
+    }
+
+    pub fn add_name(&mut self, name: &str) {
+        let name = name.trim();
+        if !name.is_empty() && !self.pseudonyms.contains_key(name) {
+            self.names += 1;
+            self.pseudonyms.insert(name.to_string(), format!("N{}", self.names));
+        }
+    }
+
+    fn add_teachers(&mut self, teachers: &str) {
+        for teacher in teachers.split(',').map(str::trim).filter(|teacher| !teacher.is_empty()) {
+            if !self.pseudonyms.contains_key(teacher) {
+                self.teachers += 1;
+                self.pseudonyms.insert(teacher.to_string(), format!("T{}", self.teachers));
+            }
+        }
+    }
+
+    // `text` with every known name replaced
+    pub fn text(&self, text: &str) -> String {
+        let mut names: Vec<(&String, &String)> = self.pseudonyms.iter().collect();
+        // Longer names first, so "Anna Berg" goes before "Anna"
+        names.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+
+        let mut text = text.to_string();
+        for (name, pseudonym) in names {
+            text = replace_word(&text, name, if self.strip { "" } else { pseudonym });
+        }
+        text
+    }
+
+    fn sources(&mut self, sources: &mut Vec<String>) {
+        if self.strip {
+            sources.clear();
+            return;
+        }
+        for source in sources {
+            if !self.pseudonyms.contains_key(source.as_str()) {
+                self.sources += 1;
+                self.pseudonyms.insert(source.clone(), format!("S{}", self.sources));
+            }
+            *source = self.pseudonyms[source.as_str()].clone();
+        }
+    }
+
+    pub fn lesson(&mut self, lesson: &mut Lesson) {
+        self.add_teachers(&lesson.teacher);
+        lesson.teacher = self.text(&lesson.teacher);
+        for text in &mut lesson.texts {
+            *text = self.text(text);
+        }
+        self.sources(&mut lesson.sources);
+    }
+
+    // The teacher is the second text, as in `Lesson::from_info`
+    pub fn lesson_info(&mut self, info: &mut LessonInfo) {
+        if let Some(teacher) = info.texts.get(1) {
+            let teacher = teacher.clone();
+            self.add_teachers(&teacher);
+        }
+        for text in &mut info.texts {
+            *text = self.text(text);
+        }
+        self.sources(&mut info.sources);
+    }
+
+    pub fn schema(&mut self, schema: &mut Schema) {
+        for info in &mut schema.lesson_info {
+            self.lesson_info(info);
+        }
+        for text in &mut schema.text_list {
+            text.text = self.text(&text.text);
+        }
+    }
+
+    pub fn week(&mut self, week: &mut WeekSchedule) {
+        for lesson in week.days.iter_mut().flat_map(|day| day.lessons.iter_mut()) {
+            self.lesson(lesson);
+        }
+    }
+}
+
+fn replace_word(text: &str, word: &str, replacement: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (at, _) in text.match_indices(word) {
+        if at < copied {
+            continue;
+        }
+        let end = at + word.len();
+        let before = text[..at].chars().next_back();
+        let after = text[end..].chars().next();
+        if before.is_some_and(char::is_alphanumeric) || after.is_some_and(char::is_alphanumeric) {
+            continue;
+        }
+        out += &text[copied..at];
+        out += replacement;
+        copied = end;
+    }
+    out += &text[copied..];
+    out
+}
@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::diff::{diff_schedules, ScheduleChange};
+use crate::ics::{to_ical, uid};
+use crate::model::{Lesson, TermSchedule, WeekSchedule};
+use crate::RequestError;
+
+#[derive(Debug, Clone)]
+pub struct CalDavCollection {
+    // e.g. "https://cloud.example.com/remote.php/dav/calendars/user/school/"
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncReport {
+    // Events written, new or updated
+    pub written: usize,
+    pub deleted: usize,
+}
+
+impl CalDavCollection {
+    fn resource_url(&self, uid: &str) -> String {
+        format!("{}/{}.ics", self.url.trim_end_matches('/'), uid)
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    async fn put(&self, client: &reqwest::Client, uid: &str, lesson: &Lesson) -> Result<(), RequestError> {
+        let request = client.put(self.resource_url(uid))
+            .header("Content-Type", "text/calendar; charset=utf-8")
+            .body(to_ical(std::iter::once(lesson)));
+        self.authorize(request).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn delete(&self, client: &reqwest::Client, uid: &str) -> Result<(), RequestError> {
+        let response = self.authorize(client.delete(self.resource_url(uid))).send().await?;
+        // Already gone is as good as deleted
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+// Pushes `current` to the collection, one resource per lesson named by its ICS UID.
+// With the previously pushed term only the weeks' changes are sent, otherwise every lesson
+// is written. Weeks missing from `current` are left alone, so past weeks stay in the calendar.
+pub async fn push(collection: &CalDavCollection, previous: Option<&TermSchedule>, current: &TermSchedule) -> Result<SyncReport, RequestError> {
+    let mut writes: HashMap<String, &Lesson> = HashMap::new();
+    let mut deletes: HashSet<String> = HashSet::new();
+
+    for week in &current.weeks {
+        let old = previous.and_then(|previous| find_week(previous, week));
+        let old = match old {
+            Some(old) => old,
+            None => {
+                writes.extend(week.lessons().filter_map(|lesson| Some((uid(lesson)?, lesson))));
+                continue;
+            },
+        };
+
+        // The new lessons borrow from `current`, so look them up rather than using the changes' clones
+        let by_guid: HashMap<&str, &Lesson> = week.lessons().map(|l| (l.id.as_str(), l)).collect();
+        for change in diff_schedules(old, week) {
+            match change {
+                ScheduleChange::Added(new) | ScheduleChange::RoomChanged { new, .. } => {
+                    if let Some((uid, lesson)) = uid(&new).zip(by_guid.get(new.id.as_str())) {
+                        writes.insert(uid, lesson);
+                    }
+                },
+                ScheduleChange::Moved { old, new } => {
+                    deletes.extend(uid(&old));
+                    if let Some((uid, lesson)) = uid(&new).zip(by_guid.get(new.id.as_str())) {
+                        writes.insert(uid, lesson);
+                    }
+                },
+                ScheduleChange::Removed(old) => deletes.extend(uid(&old)),
+            }
+        }
+    }
+
+    let client = reqwest::Client::new();
+    let mut report = SyncReport::default();
+    for (uid, lesson) in &writes {
+        collection.put(&client, uid, lesson).await?;
+        report.written += 1;
+    }
+    // A lesson moved within its day keeps its UID and is overwritten instead
+    for uid in deletes.iter().filter(|uid| !writes.contains_key(*uid)) {
+        collection.delete(&client, uid).await?;
+        report.deleted += 1;
+    }
+
+    Ok(report)
+}
+
+fn find_week<'a>(term: &'a TermSchedule, week: &WeekSchedule) -> Option<&'a WeekSchedule> {
+    term.weeks.iter().find(|w| w.year == week.year && w.week == week.week)
+}
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{Dimensions, RequestError, Selection};
+
+// Defaults shared by tools built on this crate, read from `$XDG_CONFIG_HOME/libschedule24/config.toml`:
+//
+//   domain = "example.skola24.se"
+//   school = "Example School"
+//   class = "9A"              # or teacher = "ABC", or room = "A101"
+//
+//   [dimensions]
+//   width = 1200
+//   height = 800
+//
+//   [cache]
+//   enabled = true
+//   write_behind = false
+//   refresh = false
+//   stale_fallback = false
+//
+//   [outputs]                 # files kept up to date by `ops::refresh_and_export`
+//   svg = "/srv/schedule/week.svg"
+//   ics = "/srv/schedule/term.ics"
+//
+// `config.json` with the same structure is read if there is no `config.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub domain: String,
+    pub school: String,
+    pub class: Option<String>,
+    // A signature, e.g. "ABC"
+    pub teacher: Option<String>,
+    pub room: Option<String>,
+    pub dimensions: Option<ConfigDimensions>,
+    pub cache: CacheConfig,
+    // Format name, as the file extension, to the file to write it to
+    pub outputs: BTreeMap<String, PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigDimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    // See `set_cache_write_behind`
+    pub write_behind: bool,
+    // See `set_cache_refresh`
+    pub refresh: bool,
+    // See `set_stale_fallback`
+    pub stale_fallback: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            write_behind: false,
+            refresh: false,
+            stale_fallback: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    BaseDirectories(xdg::BaseDirectoriesError),
+    Toml(toml::de::Error),
+    Serde(serde_json::Error),
+    // No class, teacher or room to resolve a selection for
+    NoSelection,
+    Request(RequestError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(v: std::io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+impl From<xdg::BaseDirectoriesError> for ConfigError {
+    fn from(v: xdg::BaseDirectoriesError) -> Self {
+        Self::BaseDirectories(v)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(v: toml::de::Error) -> Self {
+        Self::Toml(v)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(v: serde_json::Error) -> Self {
+        Self::Serde(v)
+    }
+}
+
+impl From<RequestError> for ConfigError {
+    fn from(v: RequestError) -> Self {
+        Self::Request(v)
+    }
+}
+
+impl Config {
+    // The first of `config.toml` and `config.json` in the XDG config directories, `None` if
+    // there is neither
+    pub fn path() -> Result<Option<PathBuf>, ConfigError> {
+        let dirs = xdg::BaseDirectories::with_prefix(env!("CARGO_PKG_NAME"))?;
+        Ok(dirs.find_config_file("config.toml").or_else(|| dirs.find_config_file("config.json")))
+    }
+
+    // The default config when no file exists
+    pub fn load() -> Result<Config, ConfigError> {
+        match Config::path()? {
+            Some(path) => Config::from_path(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path)?;
+        if path.extension().is_some_and(|extension| extension == "json") {
+            Ok(serde_json::from_str(&text)?)
+        }
+        else {
+            Config::from_toml(&text)
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Config, ConfigError> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn dimensions(&self) -> Dimensions {
+        self.dimensions.map_or_else(Dimensions::default, |dimensions| Dimensions {
+            width: dimensions.width,
+            height: dimensions.height,
+        })
+    }
+
+    pub fn should_cache(&self) -> bool {
+        self.cache.enabled
+    }
+
+    // Applies the process wide parts of the cache policy
+    pub fn apply(&self) {
+        crate::set_cache_write_behind(self.cache.write_behind);
+        crate::set_cache_refresh(self.cache.refresh);
+        crate::set_stale_fallback(self.cache.stale_fallback);
+    }
+
+    // Looks up the configured class, teacher or room, in that order
+    pub async fn selection(&self) -> Result<Selection, ConfigError> {
+        let should_cache = self.should_cache();
+        let selection = if let Some(class) = &self.class {
+            Selection::class(&self.domain, &self.school, class, should_cache).await?
+        }
+        else if let Some(teacher) = &self.teacher {
+            Selection::teacher(&self.domain, &self.school, teacher, should_cache).await?
+        }
+        else if let Some(room) = &self.room {
+            Selection::room(&self.domain, &self.school, room, should_cache).await?
+        }
+        else {
+            return Err(ConfigError::NoSelection);
+        };
+        Ok(selection)
+    }
+}
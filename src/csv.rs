@@ -0,0 +1,65 @@
+use crate::model::Lesson;
+
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            header: true,
+        }
+    }
+}
+
+pub fn to_csv<'a, I>(lessons: I) -> String
+where
+    I: IntoIterator<Item = &'a Lesson>,
+{
+    to_csv_with(lessons, &CsvOptions::default())
+}
+
+pub fn to_csv_with<'a, I>(lessons: I, options: &CsvOptions) -> String
+where
+    I: IntoIterator<Item = &'a Lesson>,
+{
+    let mut csv = String::new();
+    if options.header {
+        write_row(&mut csv, &["date", "start", "end", "subject", "teacher", "room", "block"], options.delimiter);
+    }
+
+    for lesson in lessons {
+        let date = lesson.date.map(|date| date.to_string()).unwrap_or_default();
+        write_row(&mut csv, &[
+            &date,
+            &lesson.start.format("%H:%M:%S").to_string(),
+            &lesson.end.format("%H:%M:%S").to_string(),
+            &lesson.subject,
+            &lesson.teacher,
+            &lesson.room,
+            lesson.block.as_deref().unwrap_or_default(),
+        ], options.delimiter);
+    }
+
+    csv
+}
+
+fn write_row(out: &mut String, fields: &[&str], delimiter: char) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(delimiter);
+        }
+        if field.contains(delimiter) || field.contains('"') || field.contains('\n') {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        }
+        else {
+            out.push_str(field);
+        }
+    }
+    out.push('\n');
+}
@@ -1,22 +1,66 @@
 use serde_json::Value;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::IgnoredAny;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, ParseError, TimeZone, Utc, Weekday};
 
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct Response<T> {
-    pub error: Value,
+    #[serde(default, deserialize_with = "deserialize_response_error")]
+    pub error: Option<ResponseError>,
     pub data: T,
     pub exception: Value,
     pub validation: Vec<Value>,
-    pub session_expires: Value,
+    #[serde(default, deserialize_with = "deserialize_session_expires")]
+    pub session_expires: Option<DateTime<Utc>>,
     pub need_session_refresh: bool,
+    // The JSON this was parsed from, for archiving or debugging. Filled in by the functions
+    // fetching responses, `None` for responses deserialized directly.
+    #[serde(skip)]
+    pub raw: Option<String>,
+    // How old the cached response was that got returned because the request failed, see
+    // `set_stale_fallback`. `None` for fresh responses and ones from the cache as usual.
+    #[serde(skip)]
+    pub stale_age: Option<std::time::Duration>,
+}
+
+impl<T> Response<T> {
+    pub fn session_expires_in(&self) -> Option<Duration> {
+        self.session_expires.map(|expires| expires - crate::timezone::now())
+    }
+
+    // True if the API asks for a refresh or the session runs out within `margin`.
+    pub fn is_session_near_expiry(&self, margin: Duration) -> bool {
+        self.need_session_refresh || self.session_expires_in().is_some_and(|left| left <= margin)
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseError {
+    pub message: Option<String>,
+    pub details: Value,
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{}", message),
+            None => write!(f, "{}", self.details),
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct Schema {
     pub text_list: Vec<Text>,
     pub box_list: Vec<Box>,
@@ -25,12 +69,248 @@ pub struct Schema {
     pub lesson_info: Vec<LessonInfo>,
 }
 
+impl Schema {
+    pub fn lessons(&self) -> impl Iterator<Item = &LessonInfo> {
+        self.lesson_info.iter()
+    }
+
+    pub fn boxes_of_type(&self, box_type: BoxType) -> impl Iterator<Item = &Box> {
+        self.box_list.iter().filter(move |b| b.type_field == box_type.as_str())
+    }
+
+    pub fn texts_for_box(&self, id: i64) -> impl Iterator<Item = &Text> {
+        self.text_list.iter().filter(move |t| t.parent_id == id)
+    }
+
+    // Lesson boxes by the GUIDs they list. When several boxes list the same lesson the last
+    // one wins.
+    pub fn lesson_boxes(&self) -> HashMap<&str, &Box> {
+        self.boxes_of_type(BoxType::Lesson)
+            .flat_map(|b| b.lesson_guids.iter().flatten().map(move |guid| (guid.as_str(), b)))
+            .collect()
+    }
+
+    // Each lesson with its box, without copying either. Some units leave out `lesson_guids`,
+    // so lessons not found by GUID get the first unclaimed Lesson box without GUIDs that
+    // contains all their texts.
+    pub fn lessons_with_boxes(&self) -> impl Iterator<Item = (&LessonInfo, Option<&Box>)> {
+        let boxes = self.lesson_boxes();
+        let mut unlisted: Vec<(&Box, Vec<&str>)> = self.boxes_of_type(BoxType::Lesson)
+            .filter(|b| b.lesson_guids.as_ref().is_none_or(Vec::is_empty))
+            .map(|b| (b, self.texts_for_box(b.id).map(|t| t.text.as_str()).collect()))
+            .collect();
+
+        self.lesson_info.iter().map(move |info| {
+            if let Some(b) = boxes.get(info.guid_id.as_str()) {
+                return (info, Some(*b));
+            }
+            let texts: Vec<&str> = info.texts.iter().map(String::as_str).filter(|text| !text.is_empty()).collect();
+            if texts.is_empty() {
+                return (info, None);
+            }
+            let found = unlisted.iter().position(|(_, box_texts)| texts.iter().all(|text| box_texts.contains(text)));
+            (info, found.map(|i| unlisted.remove(i).0))
+        })
+    }
+}
+
+// `Schema` without the geometry, for callers that only read the lessons. The parser still has
+// to step over the lists, but nothing is allocated for them.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct LessonSchema {
+    #[serde(default)]
+    text_list: IgnoredAny,
+    #[serde(default)]
+    box_list: IgnoredAny,
+    #[serde(default)]
+    line_list: IgnoredAny,
+    #[serde(deserialize_with = "deserialize_null_default")]
+    pub lesson_info: Vec<LessonInfo>,
+}
+
+// `Schema` without the lines, which are only needed for drawing. Keeps what
+// `Schema::lessons_with_boxes` reads.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct LessonBoxSchema {
+    pub text_list: Vec<Text>,
+    pub box_list: Vec<Box>,
+    #[serde(default)]
+    line_list: IgnoredAny,
+    #[serde(deserialize_with = "deserialize_null_default")]
+    pub lesson_info: Vec<LessonInfo>,
+}
+
+impl From<LessonBoxSchema> for Schema {
+    fn from(schema: LessonBoxSchema) -> Self {
+        Schema {
+            text_list: schema.text_list,
+            box_list: schema.box_list,
+            line_list: Vec::new(),
+            lesson_info: schema.lesson_info,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BoxType {
+    Lesson,
+    Footer,
+    ClockFrameStart,
+    ClockFrameEnd,
+    ClockAxisBox,
+    HeadingDay,
+    Other(String),
+}
+
+impl BoxType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            BoxType::Lesson => "Lesson",
+            BoxType::Footer => "Footer",
+            BoxType::ClockFrameStart => "ClockFrameStart",
+            BoxType::ClockFrameEnd => "ClockFrameEnd",
+            BoxType::ClockAxisBox => "ClockAxisBox",
+            BoxType::HeadingDay => "HeadingDay",
+            BoxType::Other(s) => s,
+        }
+    }
+}
+
+impl From<&str> for BoxType {
+    fn from(s: &str) -> Self {
+        match s {
+            "Lesson" => BoxType::Lesson,
+            "Footer" => BoxType::Footer,
+            "ClockFrameStart" => BoxType::ClockFrameStart,
+            "ClockFrameEnd" => BoxType::ClockFrameEnd,
+            "ClockAxisBox" => BoxType::ClockAxisBox,
+            "HeadingDay" => BoxType::HeadingDay,
+            s => BoxType::Other(s.to_string()),
+        }
+    }
+}
+
+// An immutable string for the colors and types repeated across a schema. Parsing with
+// `parse_interned` makes equal ones share one allocation.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SharedStr(Arc<str>);
+
+impl SharedStr {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for SharedStr {
+    fn default() -> Self {
+        SharedStr(Arc::from(""))
+    }
+}
+
+impl std::ops::Deref for SharedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for SharedStr {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::borrow::Borrow<str> for SharedStr {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl PartialEq<str> for SharedStr {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for SharedStr {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl From<&str> for SharedStr {
+    fn from(v: &str) -> Self {
+        SharedStr(Arc::from(v))
+    }
+}
+
+impl From<String> for SharedStr {
+    fn from(v: String) -> Self {
+        SharedStr(Arc::from(v))
+    }
+}
+
+impl Serialize for SharedStr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedStr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        Ok(INTERNED.with(|interned| match interned.borrow_mut().as_mut() {
+            Some(interned) => match interned.get(&*s) {
+                Some(shared) => shared.clone(),
+                None => {
+                    let shared = SharedStr::from(&*s);
+                    interned.insert(shared.clone());
+                    shared
+                },
+            },
+            None => SharedStr::from(&*s),
+        }))
+    }
+}
+
+thread_local! {
+    static INTERNED: RefCell<Option<HashSet<SharedStr>>> = const { RefCell::new(None) };
+}
+
+// Like `serde_json::from_str`, but every `SharedStr` with the same content shares one
+// allocation. Worth it for schemas kept in memory, which repeat a handful of colors and types
+// thousands of times.
+pub fn parse_interned<T: serde::de::DeserializeOwned>(json: &str) -> serde_json::Result<T> {
+    let previous = INTERNED.with(|interned| interned.replace(Some(HashSet::new())));
+    let result = serde_json::from_str(json);
+    INTERNED.with(|interned| interned.replace(previous));
+    result
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct Text {
     pub x: i64,
     pub y: i64,
-    pub f_color: String,
+    pub f_color: SharedStr,
     pub fontsize: f64,
     pub text: String,
     pub bold: bool,
@@ -38,44 +318,93 @@ pub struct Text {
     pub id: i64,
     pub parent_id: i64,
     #[serde(rename = "type")]
-    pub type_field: String,
+    pub type_field: SharedStr,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct Box {
     pub x: i64,
     pub y: i64,
     pub width: i64,
     pub height: i64,
-    pub b_color: String,
-    pub f_color: String,
+    pub b_color: SharedStr,
+    pub f_color: SharedStr,
     pub id: i64,
     pub parent_id: Option<i64>,
     #[serde(rename = "type")]
-    pub type_field: String,
+    pub type_field: SharedStr,
     #[serde(default)]
-    pub lesson_guids: Option<Vec<String>>,
+    pub lesson_guids: Option<Vec<LessonId>>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl Box {
+    pub fn box_type(&self) -> BoxType {
+        BoxType::from(self.type_field.as_str())
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct Line {
     pub p1x: i64,
     pub p1y: i64,
     pub p2x: i64,
     pub p2y: i64,
-    pub color: String,
+    pub color: SharedStr,
     pub id: i64,
     pub parent_id: i64,
     #[serde(rename = "type")]
-    pub type_field: String,
+    pub type_field: SharedStr,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+// The GUID the API identifies a lesson by. Only stable within one fetched week, the same
+// lesson can get another one in the next week or a later fetch, so long-lived maps should
+// not be keyed by it. One GUID can also cover several slots of a week.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+pub struct LessonId(pub String);
+
+impl LessonId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for LessonId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for LessonId {
+    fn from(v: String) -> Self {
+        LessonId(v)
+    }
+}
+
+impl From<&str> for LessonId {
+    fn from(v: &str) -> Self {
+        LessonId(v.to_string())
+    }
+}
+
+impl std::borrow::Borrow<str> for LessonId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct LessonInfo {
-    pub guid_id: String,
+    pub guid_id: LessonId,
     pub texts: Vec<String>,
     pub time_start: String,
     pub time_end: String,
@@ -83,8 +412,291 @@ pub struct LessonInfo {
     pub block_name: String,
     #[serde(default)]
     pub block: Box,
+    #[serde(default)]
+    pub date: Option<NaiveDate>,
+    // Selection GUIDs this lesson was fetched for, filled in for merged schedules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+}
+
+// By day and start time, so sorting gives the order the lessons take place in
+impl Ord for LessonInfo {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.sort_key().cmp(&other.sort_key())
+            .then_with(|| (&self.texts, &self.block_name, &self.block, &self.sources).cmp(&(&other.texts, &other.block_name, &other.block, &other.sources)))
+    }
+}
+
+impl PartialOrd for LessonInfo {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl LessonInfo {
+    fn sort_key(&self) -> (Option<NaiveDate>, i64, &str, &str, &LessonId) {
+        (self.date, self.day_of_week_number, &self.time_start, &self.time_end, &self.guid_id)
+    }
+
+    pub fn weekday(&self) -> Option<Weekday> {
+        weekday_from_number(self.day_of_week_number)
+    }
+
+    // The API lists the subject first, followed by teacher and room
+    pub fn subject(&self) -> Option<&str> {
+        self.texts.first().map(String::as_str)
+    }
+
+    pub fn teacher(&self) -> Option<&str> {
+        self.texts.get(1).map(String::as_str)
+    }
+
+    pub fn room(&self) -> Option<&str> {
+        self.texts.get(2).map(String::as_str)
+    }
+
+    pub fn start_time(&self) -> Result<NaiveTime, ParseError> {
+        NaiveTime::parse_from_str(&self.time_start, "%H:%M:%S")
+    }
+
+    pub fn end_time(&self) -> Result<NaiveTime, ParseError> {
+        NaiveTime::parse_from_str(&self.time_end, "%H:%M:%S")
+    }
+
+    // Only available once the date has been resolved, see `resolve_date`
+    pub fn start_datetime(&self) -> Option<NaiveDateTime> {
+        Some(self.date?.and_time(self.start_time().ok()?))
+    }
+
+    pub fn end_datetime(&self) -> Option<NaiveDateTime> {
+        Some(self.date?.and_time(self.end_time().ok()?))
+    }
+
+    pub fn time_range(&self) -> Result<crate::model::TimeRange, ParseError> {
+        Ok(crate::model::TimeRange::new(self.start_time()?, self.end_time()?))
+    }
+
+    // Combines the weekday with the ISO week the lesson was requested for
+    pub fn resolve_date(&mut self, year: i32, week: u32) {
+        self.date = self.weekday().and_then(|day| NaiveDate::from_isoywd_opt(year, week, day));
+    }
+}
+
+fn weekday_from_number(day_of_week_number: i64) -> Option<Weekday> {
+    match day_of_week_number {
+        1 => Some(Weekday::Mon),
+        2 => Some(Weekday::Tue),
+        3 => Some(Weekday::Wed),
+        4 => Some(Weekday::Thu),
+        5 => Some(Weekday::Fri),
+        6 => Some(Weekday::Sat),
+        7 => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+// A string of `SchemaRef` inside a list. Serde borrows a `Cow<str>` only when it is a field of
+// its own, one in a `Vec` or `Option` would always be copied.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(transparent)]
+pub struct BorrowedStr<'a>(#[serde(borrow)] pub Cow<'a, str>);
+
+impl BorrowedStr<'_> {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_owned(self) -> String {
+        self.0.into_owned()
+    }
+}
+
+impl std::ops::Deref for BorrowedStr<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Borrowing counterparts of `Schema` and its parts, for hot paths that parse the same cached
+// response over and over, e.g. a status bar. Strings point into the JSON, except that those
+// with escapes, e.g. `\"` or `\u00e5`, differ from their JSON text and need an owned copy,
+// which is why they are `Cow`s rather than `&str`s.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaRef<'a> {
+    #[serde(borrow)]
+    pub text_list: Vec<TextRef<'a>>,
+    #[serde(borrow)]
+    pub box_list: Vec<BoxRef<'a>>,
+    #[serde(borrow)]
+    pub line_list: Vec<LineRef<'a>>,
+    #[serde(borrow, deserialize_with = "deserialize_null_default")]
+    pub lesson_info: Vec<LessonInfoRef<'a>>,
 }
 
+impl SchemaRef<'_> {
+    pub fn into_owned(self) -> Schema {
+        Schema {
+            text_list: self.text_list.into_iter().map(TextRef::into_owned).collect(),
+            box_list: self.box_list.into_iter().map(BoxRef::into_owned).collect(),
+            line_list: self.line_list.into_iter().map(LineRef::into_owned).collect(),
+            lesson_info: self.lesson_info.into_iter().map(LessonInfoRef::into_owned).collect(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextRef<'a> {
+    pub x: i64,
+    pub y: i64,
+    #[serde(borrow)]
+    pub f_color: Cow<'a, str>,
+    pub fontsize: f64,
+    #[serde(borrow)]
+    pub text: Cow<'a, str>,
+    pub bold: bool,
+    pub italic: bool,
+    pub id: i64,
+    pub parent_id: i64,
+    #[serde(borrow, rename = "type")]
+    pub type_field: Cow<'a, str>,
+}
+
+impl TextRef<'_> {
+    pub fn into_owned(self) -> Text {
+        Text {
+            x: self.x,
+            y: self.y,
+            f_color: self.f_color.as_ref().into(),
+            fontsize: self.fontsize,
+            text: self.text.into_owned(),
+            bold: self.bold,
+            italic: self.italic,
+            id: self.id,
+            parent_id: self.parent_id,
+            type_field: self.type_field.as_ref().into(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoxRef<'a> {
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+    #[serde(borrow)]
+    pub b_color: Cow<'a, str>,
+    #[serde(borrow)]
+    pub f_color: Cow<'a, str>,
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    #[serde(borrow, rename = "type")]
+    pub type_field: Cow<'a, str>,
+    #[serde(borrow, default)]
+    pub lesson_guids: Option<Vec<BorrowedStr<'a>>>,
+}
+
+impl BoxRef<'_> {
+    pub fn box_type(&self) -> BoxType {
+        BoxType::from(self.type_field.as_ref())
+    }
+
+    pub fn into_owned(self) -> Box {
+        Box {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+            b_color: self.b_color.as_ref().into(),
+            f_color: self.f_color.as_ref().into(),
+            id: self.id,
+            parent_id: self.parent_id,
+            type_field: self.type_field.as_ref().into(),
+            lesson_guids: self.lesson_guids.map(|guids| guids.into_iter().map(|guid| LessonId(guid.into_owned())).collect()),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineRef<'a> {
+    pub p1x: i64,
+    pub p1y: i64,
+    pub p2x: i64,
+    pub p2y: i64,
+    #[serde(borrow)]
+    pub color: Cow<'a, str>,
+    pub id: i64,
+    pub parent_id: i64,
+    #[serde(borrow, rename = "type")]
+    pub type_field: Cow<'a, str>,
+}
+
+impl LineRef<'_> {
+    pub fn into_owned(self) -> Line {
+        Line {
+            p1x: self.p1x,
+            p1y: self.p1y,
+            p2x: self.p2x,
+            p2y: self.p2y,
+            color: self.color.as_ref().into(),
+            id: self.id,
+            parent_id: self.parent_id,
+            type_field: self.type_field.as_ref().into(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LessonInfoRef<'a> {
+    #[serde(borrow)]
+    pub guid_id: Cow<'a, str>,
+    #[serde(borrow)]
+    pub texts: Vec<BorrowedStr<'a>>,
+    #[serde(borrow)]
+    pub time_start: Cow<'a, str>,
+    #[serde(borrow)]
+    pub time_end: Cow<'a, str>,
+    pub day_of_week_number: i64,
+    #[serde(borrow)]
+    pub block_name: Cow<'a, str>,
+}
+
+impl LessonInfoRef<'_> {
+    pub fn weekday(&self) -> Option<Weekday> {
+        weekday_from_number(self.day_of_week_number)
+    }
+
+    pub fn start_time(&self) -> Result<NaiveTime, ParseError> {
+        NaiveTime::parse_from_str(&self.time_start, "%H:%M:%S")
+    }
+
+    pub fn end_time(&self) -> Result<NaiveTime, ParseError> {
+        NaiveTime::parse_from_str(&self.time_end, "%H:%M:%S")
+    }
+
+    // Without block and date, see `lib::add_box_info` and `LessonInfo::resolve_date`
+    pub fn into_owned(self) -> LessonInfo {
+        LessonInfo {
+            guid_id: LessonId(self.guid_id.into_owned()),
+            texts: self.texts.into_iter().map(BorrowedStr::into_owned).collect(),
+            time_start: self.time_start.into_owned(),
+            time_end: self.time_end.into_owned(),
+            day_of_week_number: self.day_of_week_number,
+            block_name: self.block_name.into_owned(),
+            ..LessonInfo::default()
+        }
+    }
+}
+
+// Like `Class` and `DomainInfo` not covered by the strict feature, the commented out
+// fields are left out on purpose
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ClassList {
@@ -92,13 +704,18 @@ pub struct ClassList {
     // pub subjects: Vec<Value>,
     // pub periods: Vec<Value>,
     // pub groups: Vec<Value>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
     pub classes: Vec<Class>,
-    // pub rooms: Vec<Value>,
-    // pub teachers: Vec<Value>,
-    // pub students: Vec<Value>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    pub rooms: Vec<Room>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    pub teachers: Vec<Teacher>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    pub students: Vec<Student>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Class {
     // pub id: Value,
@@ -116,6 +733,183 @@ pub struct Class {
     // pub substitute_teacher_guid: Value,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct Teacher {
+    pub person_guid: String,
+    // The teacher's signature, e.g. "ABC"
+    pub id: String,
+    pub first_name: String,
+    pub last_name: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct Student {
+    pub person_guid: String,
+    pub first_name: String,
+    pub last_name: String,
+}
+
+impl Student {
+    pub fn full_name(&self) -> String {
+        format!("{} {}", self.first_name, self.last_name)
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct Room {
+    pub edu_guid: String,
+    pub name: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct RenderKey {
+    pub key: String,
+}
+
+// Request bodies, without the `renderKey` that is added when sending them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderTimetableRequest<'a> {
+    pub host: &'a str,
+    pub unit_guid: &'a str,
+    // 0 for the whole week, otherwise 1 = Monday
+    pub schedule_day: i32,
+    pub black_and_white: bool,
+    pub width: u32,
+    pub height: u32,
+    pub selection_type: i32,
+    pub selection: &'a str,
+    pub show_header: bool,
+    pub period_text: &'a str,
+    pub week: i32,
+    pub year: i32,
+    pub private_selection_mode: bool,
+    pub customer_key: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SelectionFilter {
+    Class,
+    Teacher,
+    Room,
+    Student,
+}
+
+impl SelectionFilter {
+    pub const ALL: [SelectionFilter; 4] = [SelectionFilter::Class, SelectionFilter::Teacher, SelectionFilter::Room, SelectionFilter::Student];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SelectionFilter::Class => "class",
+            SelectionFilter::Teacher => "teacher",
+            SelectionFilter::Room => "room",
+            SelectionFilter::Student => "student",
+        }
+    }
+}
+
+// Sent as `{"class": true}` and so on
+impl Serialize for SelectionFilter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.as_str(), &true)?;
+        map.end()
+    }
+}
+
+// Several categories for one request, e.g.
+// `SelectionFilters::default().with(SelectionFilter::Teacher).with(SelectionFilter::Room)`.
+// Only the enabled ones are sent, so a single category is sent like `SelectionFilter`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct SelectionFilters {
+    #[serde(skip_serializing_if = "is_false")]
+    pub class: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub teacher: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub room: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub student: bool,
+}
+
+impl SelectionFilters {
+    pub fn all() -> Self {
+        SelectionFilters {
+            class: true,
+            teacher: true,
+            room: true,
+            student: true,
+        }
+    }
+
+    pub fn with(mut self, filter: SelectionFilter) -> Self {
+        match filter {
+            SelectionFilter::Class => self.class = true,
+            SelectionFilter::Teacher => self.teacher = true,
+            SelectionFilter::Room => self.room = true,
+            SelectionFilter::Student => self.student = true,
+        }
+        self
+    }
+
+    pub fn contains(&self, filter: SelectionFilter) -> bool {
+        match filter {
+            SelectionFilter::Class => self.class,
+            SelectionFilter::Teacher => self.teacher,
+            SelectionFilter::Room => self.room,
+            SelectionFilter::Student => self.student,
+        }
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = SelectionFilter> {
+        SelectionFilter::ALL.iter().copied().filter(move |filter| self.contains(*filter))
+    }
+}
+
+impl From<SelectionFilter> for SelectionFilters {
+    fn from(filter: SelectionFilter) -> Self {
+        SelectionFilters::default().with(filter)
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionRequest<'a> {
+    pub host_name: &'a str,
+    pub unit_guid: &'a str,
+    pub filters: SelectionFilters,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnitsRequest<'a> {
+    #[serde(rename = "getTimetableViewerUnitsRequest")]
+    pub request: UnitsRequestHost<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnitsRequestHost<'a> {
+    pub host_name: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SignatureRequest<'a> {
+    pub signature: &'a str,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DomainInfo {
@@ -127,49 +921,126 @@ pub struct DomainInfo {
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct SchoolList {
     pub host_name: String,
     pub units: Vec<School>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct School {
     pub unit_guid: String,
     pub unit_id: String,
-    // pub allow_calendar_export: bool,
-    // pub private: Value,
-    // pub staff: Value,
-    // pub anonymous: Anonymous,
+    #[serde(default)]
+    pub allow_calendar_export: bool,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub private: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub staff: Option<bool>,
+    #[serde(default)]
+    pub anonymous: Option<Anonymous>,
+}
+
+impl School {
+    // skola24 uses the unit id as the human readable school name
+    pub fn display_name(&self) -> &str {
+        &self.unit_id
+    }
+
+    // Whether the unit lets anyone fetch student timetables without logging in
+    pub fn allows_student_lookup(&self) -> bool {
+        self.anonymous.as_ref().is_some_and(|anonymous| anonymous.students)
+    }
 }
 
-// #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct Anonymous {
-//     pub students: bool,
-//     pub classes: bool,
-//     pub groups: bool,
-//     pub teachers: bool,
-//     pub rooms: bool,
-//     pub subjects: bool,
-//     pub courses: bool,
-// }
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase", default)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct Anonymous {
+    pub students: bool,
+    pub classes: bool,
+    pub groups: bool,
+    pub teachers: bool,
+    pub rooms: bool,
+    pub subjects: bool,
+    pub courses: bool,
+}
 
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct ValidationError {
     pub id: u32,
     pub description: String,
 }
 
+impl ValidationError {
+    pub fn kind(&self) -> ValidationErrorKind {
+        ValidationErrorKind::from(self.id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValidationErrorKind {
+    HostNotFound,
+    UnitNotFound,
+    NoTimetable,
+    SelectionNotFound,
+    // Ids not catalogued above
+    Unknown(u32),
+}
+
+impl From<u32> for ValidationErrorKind {
+    fn from(id: u32) -> Self {
+        match id {
+            1 => Self::HostNotFound,
+            2 => Self::UnitNotFound,
+            3 => Self::NoTimetable,
+            4 => Self::SelectionNotFound,
+            id => Self::Unknown(id),
+        }
+    }
+}
+
+impl From<ValidationErrorKind> for u32 {
+    fn from(kind: ValidationErrorKind) -> Self {
+        match kind {
+            ValidationErrorKind::HostNotFound => 1,
+            ValidationErrorKind::UnitNotFound => 2,
+            ValidationErrorKind::NoTimetable => 3,
+            ValidationErrorKind::SelectionNotFound => 4,
+            ValidationErrorKind::Unknown(id) => id,
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct ErrorData {
     pub errors: Value,
     pub validation_errors: Vec<ValidationError>,
 }
 
+impl ErrorData {
+    pub fn kinds(&self) -> impl Iterator<Item = ValidationErrorKind> + '_ {
+        self.validation_errors.iter().map(ValidationError::kind)
+    }
+
+    // The kind of the error, if the API reported exactly one.
+    pub fn kind(&self) -> Option<ValidationErrorKind> {
+        match self.validation_errors.as_slice() {
+            [e] => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub(crate) enum APIResult<T> {
@@ -180,8 +1051,9 @@ pub(crate) enum APIResult<T> {
 #[derive(Debug)]
 pub enum SchemaError {
     API(ErrorData),
+    #[cfg(feature = "net")]
     Request(reqwest::Error),
-    APIRoot,
+    APIRoot(ResponseError),
 }
 
 impl fmt::Display for SchemaError {
@@ -192,12 +1064,22 @@ impl fmt::Display for SchemaError {
 
 impl std::error::Error for SchemaError {}
 
+impl SchemaError {
+    pub fn validation_kind(&self) -> Option<ValidationErrorKind> {
+        match self {
+            Self::API(e) => e.kind(),
+            _ => None,
+        }
+    }
+}
+
 impl From<ErrorData> for SchemaError {
     fn from(v: ErrorData) -> Self {
         Self::API(v)
     }
 }
 
+#[cfg(feature = "net")]
 impl From<reqwest::Error> for SchemaError {
     fn from(v: reqwest::Error) -> Self {
         Self::Request(v)
@@ -208,8 +1090,8 @@ impl<T> TryFrom<Response<APIResult<T>>> for Response<T> {
     type Error = SchemaError;
 
     fn try_from(v: Response<APIResult<T>>) -> Result<Response<T>, SchemaError> {
-        if !v.error.is_null() {
-            return Err(SchemaError::APIRoot);
+        if let Some(e) = v.error {
+            return Err(SchemaError::APIRoot(e));
         }
 
         match v.data {
@@ -220,6 +1102,8 @@ impl<T> TryFrom<Response<APIResult<T>>> for Response<T> {
                 validation: v.validation,
                 session_expires: v.session_expires,
                 need_session_refresh: v.need_session_refresh,
+                raw: v.raw,
+                stale_age: v.stale_age,
             }),
             APIResult::Failure(e) => Err(SchemaError::API(e))
         }
@@ -227,11 +1111,74 @@ impl<T> TryFrom<Response<APIResult<T>>> for Response<T> {
 }
 
 
+// For the lists skola24 sends as null when they are empty, e.g. `lesson_info` of a week
+// without lessons and the categories of `ClassList` that weren't asked for. These are
+// expected, so they are empty also with the strict feature, which still rejects nulls
+// anywhere else.
 fn deserialize_null_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
 where
     T: Default + Deserialize<'de>,
     D: Deserializer<'de>,
 {
-    let opt = Option::deserialize(deserializer)?;
-    Ok(opt.unwrap_or_default())
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+fn deserialize_response_error<'de, D>(deserializer: D) -> Result<Option<ResponseError>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let error = match Value::deserialize(deserializer)? {
+        Value::Null => return Ok(None),
+        Value::String(message) => ResponseError {
+            message: Some(message),
+            details: Value::Null,
+        },
+        details => ResponseError {
+            message: details.get("message")
+                .or_else(|| details.get("Message"))
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+            details,
+        },
+    };
+
+    Ok(Some(error))
+}
+
+// An expiry that can't be read is left out, since the session works without it. With the
+// strict feature it is an error instead.
+fn deserialize_session_expires<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let value = Value::deserialize(deserializer)?;
+    let expires = match &value {
+        Value::Null => return Ok(None),
+        Value::String(s) if s.is_empty() => return Ok(None),
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .map(|time| time.with_timezone(&Utc))
+            .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f").map(|time| Utc.from_utc_datetime(&time)))
+            .ok(),
+        // Unix timestamp in milliseconds
+        Value::Number(n) => n.as_i64().and_then(|ms| Utc.timestamp_millis_opt(ms).single()),
+        _ => None,
+    };
+
+    if expires.is_none() {
+        if cfg!(feature = "strict") {
+            return Err(D::Error::custom(format!("unexpected session expiry value: {}", value)));
+        }
+        debug!("ignoring unexpected session expiry value: {}", value);
+    }
+    Ok(expires)
+}
+
+// Some units send these flags as objects or null rather than booleans
+fn deserialize_flag<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Value::deserialize(deserializer)?.as_bool())
 }
@@ -0,0 +1,221 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+
+use crate::model::{Lesson, WeekSchedule};
+#[cfg(feature = "net")]
+use crate::{cache_dir, RequestError, Selection};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ScheduleChange {
+    Added(Lesson),
+    Removed(Lesson),
+    // Different day or time
+    Moved { old: Lesson, new: Lesson },
+    RoomChanged { old: Lesson, new: Lesson },
+}
+
+type MatchPass = fn(&Lesson, &Lesson) -> bool;
+
+// Matches lessons by GUID, day and start time first, since one GUID can take several slots,
+// and then pairs the remaining ones by GUID alone as moved lessons. A lesson that both moved
+// and changed room yields both changes.
+pub fn diff_schedules<'a, I, J>(old: I, new: J) -> Vec<ScheduleChange>
+where
+    I: IntoIterator<Item = &'a Lesson>,
+    J: IntoIterator<Item = &'a Lesson>,
+{
+    let old: Vec<&Lesson> = old.into_iter().collect();
+    let mut unmatched_new: Vec<Option<&Lesson>> = new.into_iter().map(Some).collect();
+    let mut matched: Vec<Option<&Lesson>> = vec![None; old.len()];
+
+    let passes: [MatchPass; 2] = [
+        |o, n| o.id == n.id && o.weekday == n.weekday && o.start == n.start,
+        |o, n| o.id == n.id,
+    ];
+
+    for pass in passes.iter() {
+        for (i, old_lesson) in old.iter().enumerate() {
+            if matched[i].is_some() {
+                continue;
+            }
+            matched[i] = unmatched_new.iter_mut()
+                .find(|n| n.is_some_and(|n| pass(old_lesson, n)))
+                .and_then(Option::take);
+        }
+    }
+
+    let mut changes = Vec::new();
+    for (old_lesson, new_lesson) in old.into_iter().zip(matched) {
+        match new_lesson {
+            None => changes.push(ScheduleChange::Removed(old_lesson.clone())),
+            Some(new_lesson) => {
+                if !same_time(old_lesson, new_lesson) {
+                    changes.push(ScheduleChange::Moved {
+                        old: old_lesson.clone(),
+                        new: new_lesson.clone(),
+                    });
+                }
+                if old_lesson.room != new_lesson.room {
+                    changes.push(ScheduleChange::RoomChanged {
+                        old: old_lesson.clone(),
+                        new: new_lesson.clone(),
+                    });
+                }
+            },
+        }
+    }
+    changes.extend(unmatched_new.into_iter().flatten().map(|n| ScheduleChange::Added(n.clone())));
+
+    changes
+}
+
+fn same_time(a: &Lesson, b: &Lesson) -> bool {
+    a.weekday == b.weekday && a.start == b.start && a.end == b.end
+}
+
+// Identifies a lesson slot independent of the GUID, which changes between weeks
+pub(crate) type SlotKey = (u32, NaiveTime, NaiveTime, String);
+
+pub(crate) fn slot_key(lesson: &Lesson) -> SlotKey {
+    (
+        lesson.weekday.num_days_from_monday(),
+        lesson.start,
+        lesson.end,
+        lesson.subject.clone(),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Deviation {
+    // A recurring lesson that does not take place this week
+    Missing { week: u32, lesson: Lesson },
+    // A lesson outside the weekly pattern
+    Extra { week: u32, lesson: Lesson },
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecurrenceReport {
+    pub recurring: Vec<Lesson>,
+    pub deviations: Vec<Deviation>,
+}
+
+// Lessons occurring in more than half of the weeks make up the regular pattern
+pub fn recurring_pattern(weeks: &[WeekSchedule]) -> RecurrenceReport {
+    let mut counts: HashMap<SlotKey, (usize, &Lesson)> = HashMap::new();
+    for week in weeks {
+        let mut seen = HashSet::new();
+        for lesson in week {
+            let key = slot_key(lesson);
+            if seen.insert(key.clone()) {
+                counts.entry(key).or_insert((0, lesson)).0 += 1;
+            }
+        }
+    }
+
+    let mut recurring: Vec<(SlotKey, &Lesson)> = counts.into_iter()
+        .filter(|(_, (count, _))| *count * 2 > weeks.len())
+        .map(|(key, (_, lesson))| (key, lesson))
+        .collect();
+    recurring.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut deviations = Vec::new();
+    for week in weeks {
+        let keys: HashSet<SlotKey> = week.lessons().map(slot_key).collect();
+        for (key, lesson) in &recurring {
+            if !keys.contains(key) {
+                deviations.push(Deviation::Missing { week: week.week, lesson: (*lesson).clone() });
+            }
+        }
+        for lesson in week {
+            let key = slot_key(lesson);
+            if !recurring.iter().any(|(k, _)| *k == key) {
+                deviations.push(Deviation::Extra { week: week.week, lesson: lesson.clone() });
+            }
+        }
+    }
+
+    RecurrenceReport {
+        recurring: recurring.into_iter().map(|(_, lesson)| lesson.clone()).collect(),
+        deviations,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LessonStatus {
+    Unchanged(Lesson),
+    Cancelled(Lesson),
+    Moved { baseline: Box<Lesson>, current: Box<Lesson> },
+    New(Lesson),
+}
+
+// Unlike `diff_schedules` this tolerates GUIDs changing between fetches by falling back to
+// matching on subject and time, and then on subject alone for moved lessons.
+pub fn compare_to_baseline<'a, I, J>(baseline: I, current: J) -> Vec<LessonStatus>
+where
+    I: IntoIterator<Item = &'a Lesson>,
+    J: IntoIterator<Item = &'a Lesson>,
+{
+    let current: Vec<&Lesson> = current.into_iter().collect();
+    let mut unmatched_baseline: Vec<Option<&Lesson>> = baseline.into_iter().map(Some).collect();
+    let mut matched: Vec<Option<LessonStatus>> = vec![None; current.len()];
+
+    let passes: [MatchPass; 3] = [
+        |b, c| b.id == c.id,
+        |b, c| slot_key(b) == slot_key(c),
+        |b, c| !b.subject.is_empty() && b.subject == c.subject,
+    ];
+
+    for pass in passes.iter() {
+        for (i, lesson) in current.iter().enumerate() {
+            if matched[i].is_some() {
+                continue;
+            }
+
+            let found = unmatched_baseline.iter_mut()
+                .find(|b| b.is_some_and(|b| pass(b, lesson)))
+                .and_then(Option::take);
+
+            if let Some(base) = found {
+                matched[i] = Some(if same_time(base, lesson) {
+                    LessonStatus::Unchanged((*lesson).clone())
+                }
+                else {
+                    LessonStatus::Moved {
+                        baseline: Box::new(base.clone()),
+                        current: Box::new((*lesson).clone()),
+                    }
+                });
+            }
+        }
+    }
+
+    let mut statuses: Vec<LessonStatus> = matched.into_iter()
+        .zip(current)
+        .map(|(status, lesson)| status.unwrap_or_else(|| LessonStatus::New(lesson.clone())))
+        .collect();
+    statuses.extend(unmatched_baseline.into_iter().flatten().map(|b| LessonStatus::Cancelled(b.clone())));
+
+    statuses
+}
+
+#[cfg(feature = "net")]
+fn baseline_key(selection: &Selection) -> String {
+    format!("baseline{}{}{}", selection.host, selection.unit_guid, selection.target.guid())
+}
+
+#[cfg(feature = "net")]
+pub async fn save_baseline(selection: &Selection, schedule: &WeekSchedule) -> Result<(), RequestError> {
+    cacache::write(&cache_dir()?, &baseline_key(selection), serde_json::to_vec(schedule)?).await?;
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+pub async fn load_baseline(selection: &Selection) -> Result<Option<WeekSchedule>, RequestError> {
+    match cacache::read(&cache_dir()?, &baseline_key(selection)).await {
+        Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        Err(cacache::Error::EntryNotFound(_, _)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
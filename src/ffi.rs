@@ -0,0 +1,153 @@
+// C interface for consumers in other languages, declared in `include/libschedule24.h`. Build
+// a linkable library with `cargo rustc --release --features ffi --crate-type cdylib` (or
+// `staticlib`).
+//
+// Strings passed in are NUL terminated UTF-8. Strings returned are owned by the caller and
+// must be released with `ls24_string_free`. Functions returning NULL failed,
+// `ls24_last_error` then describes why. For Python, see the `python` feature instead.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+
+use crate::image::{generate_svg, SvgOptions};
+use crate::{data, json, Dimensions, RequestError, SchemaRequestOptions, Selection};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+unsafe fn str_arg<'a>(arg: *const c_char, name: &str) -> Option<&'a str> {
+    if arg.is_null() {
+        set_error(format!("{} is NULL", name));
+        return None;
+    }
+    match CStr::from_ptr(arg).to_str() {
+        Ok(arg) => Some(arg),
+        Err(e) => {
+            set_error(format!("{} is not UTF-8: {}", name, e));
+            None
+        },
+    }
+}
+
+fn into_c_string(value: Result<String, String>) -> *mut c_char {
+    match value.and_then(|value| CString::new(value).map_err(|e| e.to_string())) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            set_error(e);
+            std::ptr::null_mut()
+        },
+    }
+}
+
+fn request_error(error: RequestError) -> String {
+    format!("{:?}", error)
+}
+
+async fn class_selection(domain: &str, school: &str, class: &str) -> Result<Selection, String> {
+    Selection::class(domain, school, class, true).await.map_err(request_error)
+}
+
+/// The week's lessons of a class as `json::SimpleSchedule` JSON.
+///
+/// # Safety
+///
+/// `domain`, `school` and `class` must be NULL or point to NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn ls24_week_json(domain: *const c_char, school: *const c_char, class: *const c_char, week: c_int) -> *mut c_char {
+    let (domain, school, class) = match (str_arg(domain, "domain"), str_arg(school, "school"), str_arg(class, "class")) {
+        (Some(domain), Some(school), Some(class)) => (domain, school, class),
+        _ => return std::ptr::null_mut(),
+    };
+
+    into_c_string(async_std::task::block_on(async {
+        let selection = class_selection(domain, school, class).await?;
+        let schedule = crate::get_week_schedule(selection, week, true).await.map_err(request_error)?;
+        json::to_simple_json(&schedule).map_err(|e| e.to_string())
+    }))
+}
+
+/// The week's lessons of a class as an iCalendar document.
+///
+/// # Safety
+///
+/// `domain`, `school` and `class` must be NULL or point to NUL terminated strings.
+#[cfg(feature = "ics")]
+#[no_mangle]
+pub unsafe extern "C" fn ls24_week_ics(domain: *const c_char, school: *const c_char, class: *const c_char, week: c_int) -> *mut c_char {
+    let (domain, school, class) = match (str_arg(domain, "domain"), str_arg(school, "school"), str_arg(class, "class")) {
+        (Some(domain), Some(school), Some(class)) => (domain, school, class),
+        _ => return std::ptr::null_mut(),
+    };
+
+    into_c_string(async_std::task::block_on(async {
+        let selection = class_selection(domain, school, class).await?;
+        let schedule = crate::get_week_schedule(selection, week, true).await.map_err(request_error)?;
+        Ok(crate::ics::to_ical(&schedule))
+    }))
+}
+
+/// The week or, for `day` 1 to 7, one day of a class drawn as SVG at `width` x `height`.
+///
+/// # Safety
+///
+/// `domain`, `school` and `class` must be NULL or point to NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn ls24_render_svg(domain: *const c_char, school: *const c_char, class: *const c_char, week: c_int, day: c_int, width: u32, height: u32) -> *mut c_char {
+    let (domain, school, class) = match (str_arg(domain, "domain"), str_arg(school, "school"), str_arg(class, "class")) {
+        (Some(domain), Some(school), Some(class)) => (domain, school, class),
+        _ => return std::ptr::null_mut(),
+    };
+    let dimensions = Dimensions { width, height };
+
+    into_c_string(async_std::task::block_on(async {
+        let selection = class_selection(domain, school, class).await?;
+        let schema = crate::get_schema(selection, day, week, Some(dimensions), &SchemaRequestOptions::default(), true).await.map_err(request_error)?;
+        generate_svg(&schema.data, dimensions, &SvgOptions::default())
+            .map(|doc| doc.to_string())
+            .map_err(|e| format!("{:?}", e))
+    }))
+}
+
+/// Draws an already fetched `data::Schema`, e.g. one cached by the caller, as SVG.
+///
+/// # Safety
+///
+/// `schema_json` must be NULL or point to a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ls24_render_schema_svg(schema_json: *const c_char, width: u32, height: u32) -> *mut c_char {
+    let schema_json = match str_arg(schema_json, "schema_json") {
+        Some(schema_json) => schema_json,
+        None => return std::ptr::null_mut(),
+    };
+
+    into_c_string(serde_json::from_str::<data::Schema>(schema_json)
+        .map_err(|e| e.to_string())
+        .and_then(|schema| generate_svg(&schema, Dimensions { width, height }, &SvgOptions::default()).map_err(|e| format!("{:?}", e)))
+        .map(|doc| doc.to_string()))
+}
+
+/// Why the last call on this thread failed, NULL if none did. Owned by the library and valid
+/// until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn ls24_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(std::ptr::null(), |error| error.as_ptr()))
+}
+
+/// Releases a string returned by this library. NULL is ignored.
+///
+/// # Safety
+///
+/// `value` must be NULL or a string returned by this library that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn ls24_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+
+use std::collections::HashMap;
+
+use crate::diff::{slot_key, SlotKey};
+use crate::model::{Lesson, TermSchedule};
+use crate::timezone::SCHEDULE_TIMEZONE;
+
+// An event resource for the Google Calendar API's `events.insert`/`events.import`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleEvent {
+    pub summary: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub start: EventDateTime,
+    pub end: EventDateTime,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recurrence: Vec<String>,
+    #[serde(rename = "iCalUID")]
+    pub ical_uid: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventDateTime {
+    // Wall clock time, interpreted in `time_zone`
+    pub date_time: NaiveDateTime,
+    pub time_zone: String,
+}
+
+impl EventDateTime {
+    fn new(date_time: NaiveDateTime) -> Self {
+        EventDateTime {
+            date_time,
+            time_zone: SCHEDULE_TIMEZONE.name().to_string(),
+        }
+    }
+}
+
+// Lessons with the same slot, teacher and room in two or more weeks become one weekly
+// event, with the weeks they skip as EXDATEs. Lessons without a resolved date are skipped.
+pub fn to_google_events(term: &TermSchedule) -> Vec<GoogleEvent> {
+    let mut series: HashMap<(SlotKey, &str, &str), Vec<&Lesson>> = HashMap::new();
+    let mut order = Vec::new();
+    for lesson in term.lessons().filter(|lesson| lesson.start_datetime().is_some()) {
+        let key = (slot_key(lesson), lesson.teacher.as_str(), lesson.room.as_str());
+        if !series.contains_key(&key) {
+            order.push(key.clone());
+        }
+        series.entry(key).or_default().push(lesson);
+    }
+
+    let mut events = Vec::new();
+    for key in order {
+        let mut lessons = series.remove(&key).unwrap_or_default();
+        lessons.sort_by_key(|lesson| lesson.start_datetime());
+        lessons.dedup_by_key(|lesson| lesson.start_datetime());
+
+        match lessons.as_slice() {
+            [] => {},
+            [lesson] => events.extend(event(lesson, Vec::new())),
+            [first, .., last] => {
+                let (first_start, last_start) = match (first.start_datetime(), last.start_datetime()) {
+                    (Some(first_start), Some(last_start)) => (first_start, last_start),
+                    _ => continue,
+                };
+
+                let mut recurrence = vec![format!("RRULE:FREQ=WEEKLY;UNTIL={}", utc_until(last_start))];
+                let mut occurrence = first_start;
+                while occurrence <= last_start {
+                    if !lessons.iter().any(|lesson| lesson.start_datetime() == Some(occurrence)) {
+                        recurrence.push(format!("EXDATE;TZID={}:{}", SCHEDULE_TIMEZONE.name(), occurrence.format("%Y%m%dT%H%M%S")));
+                    }
+                    occurrence += Duration::weeks(1);
+                }
+
+                events.extend(event(first, recurrence));
+            },
+        }
+    }
+
+    events
+}
+
+fn event(lesson: &Lesson, recurrence: Vec<String>) -> Option<GoogleEvent> {
+    let start = lesson.start_datetime()?;
+    let end = lesson.end_datetime()?;
+    let suffix = if recurrence.is_empty() {""} else {"-weekly"};
+
+    Some(GoogleEvent {
+        summary: lesson.subject.clone(),
+        location: Some(lesson.room.clone()).filter(|room| !room.is_empty()),
+        description: Some(lesson.teacher.clone()).filter(|teacher| !teacher.is_empty()),
+        start: EventDateTime::new(start),
+        end: EventDateTime::new(end),
+        recurrence,
+        ical_uid: format!("{}-{}{}@{}", lesson.id, start.format("%Y%m%d"), suffix, env!("CARGO_PKG_NAME")),
+    })
+}
+
+// RFC 5545 wants UNTIL in UTC when DTSTART has a timezone. A day's margin keeps the last
+// occurrence inside the rule regardless of the offset.
+fn utc_until(last_start: NaiveDateTime) -> String {
+    (last_start + Duration::days(1)).format("%Y%m%dT%H%M%SZ").to_string()
+}
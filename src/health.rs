@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use crate::data::SelectionFilter;
+use crate::{fetch_key, get_schools, get_selection_list, Endpoint, RequestError};
+
+// One request of `health_check`, always sent to the API and never answered from the cache
+#[derive(Debug)]
+pub struct Probe {
+    pub endpoint: Endpoint,
+    pub latency: Duration,
+    pub result: Result<(), RequestError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    // skola24 could not be reached or answered with something unexpected
+    Unreachable,
+    // skola24 answered, but not for this domain or its schools, e.g. a typo in the config
+    Misconfigured,
+    // Some probes failed in other ways
+    Degraded,
+}
+
+#[derive(Debug)]
+pub struct HealthReport {
+    pub domain: String,
+    // In the order they ran. Probes that needed the result of a failed one are left out.
+    pub probes: Vec<Probe>,
+    // The school whose selection was requested, the first one of the domain
+    pub school: Option<String>,
+}
+
+impl HealthReport {
+    pub fn health(&self) -> Health {
+        let mut health = Health::Healthy;
+        for probe in &self.probes {
+            let error = match &probe.result {
+                Ok(()) => continue,
+                Err(e) => e.root(),
+            };
+            match error {
+                RequestError::Reqwest(_) | RequestError::KeyFormat(_) => return Health::Unreachable,
+                RequestError::NotFound { .. } | RequestError::NotPublic(_) => health = Health::Misconfigured,
+                _ if health == Health::Healthy => health = Health::Degraded,
+                _ => {},
+            }
+        }
+        health
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.health() == Health::Healthy
+    }
+}
+
+async fn probe<T, F>(endpoint: Endpoint, request: F) -> (Probe, Option<T>)
+where
+    F: std::future::Future<Output = Result<T, RequestError>>,
+{
+    let started = Instant::now();
+    let result = request.await;
+    let latency = started.elapsed();
+    let (result, value) = match result {
+        Ok(value) => (Ok(()), Some(value)),
+        Err(e) => (Err(e), None),
+    };
+    (Probe { endpoint, latency, result }, value)
+}
+
+// Probes the render key, the units of `domain` and the class list of its first school, so
+// "skola24 is down" can be told apart from a wrong domain. The latencies include fetching
+// render keys where a request needs one.
+pub async fn health_check(domain: &str) -> HealthReport {
+    let mut report = HealthReport {
+        domain: domain.to_string(),
+        probes: Vec::new(),
+        school: None,
+    };
+
+    let (key, _) = probe(Endpoint::RenderKey, fetch_key()).await;
+    let key_failed = key.result.is_err();
+    report.probes.push(key);
+    if key_failed {
+        return report;
+    }
+
+    let (units, schools) = probe(Endpoint::ViewerUnits, get_schools(domain, false)).await;
+    report.probes.push(units);
+    let school = match schools.and_then(|schools| schools.into_iter().next()) {
+        Some(school) => school,
+        None => return report,
+    };
+
+    let (selection, _) = probe(Endpoint::Selection, get_selection_list(domain, &school.unit_guid, SelectionFilter::Class, false)).await;
+    report.probes.push(selection);
+    report.school = Some(school.unit_id);
+
+    report
+}
@@ -0,0 +1,85 @@
+use chrono::NaiveTime;
+use serde::Serialize;
+
+use crate::model::Lesson;
+use crate::query;
+use crate::statusbar::StatusClass;
+use crate::timezone::{self, SCHEDULE_TIMEZONE};
+
+// Payload for a Home Assistant REST/command line sensor:
+// `value_template: "{{ value_json.state }}"`, `json_attributes: [current, next, status]`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SensorPayload {
+    // Subject of the lesson in progress, or "free"
+    pub state: String,
+    pub attributes: SensorAttributes,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SensorAttributes {
+    pub status: StatusClass,
+    pub current: Option<SensorLesson>,
+    pub next: Vec<SensorLesson>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SensorLesson {
+    pub subject: String,
+    pub teacher: String,
+    pub room: String,
+    // RFC 3339 timestamps when the lesson date is known, "HH:MM:SS" otherwise
+    pub start: String,
+    pub end: String,
+}
+
+impl SensorLesson {
+    pub fn from_lesson(lesson: &Lesson) -> Self {
+        let start = timezone::lesson_start(lesson, SCHEDULE_TIMEZONE)
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_else(|| lesson.start.format("%H:%M:%S").to_string());
+        let end = timezone::lesson_end(lesson, SCHEDULE_TIMEZONE)
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_else(|| lesson.end.format("%H:%M:%S").to_string());
+
+        SensorLesson {
+            subject: lesson.subject.clone(),
+            teacher: lesson.teacher.clone(),
+            room: lesson.room.clone(),
+            start,
+            end,
+        }
+    }
+}
+
+// `next` holds at most `count` of the day's upcoming lessons
+pub fn sensor_payload(lessons: &[Lesson], at: NaiveTime, count: usize) -> SensorPayload {
+    let current = query::current_lesson(lessons, at);
+    let next: Vec<SensorLesson> = query::upcoming_lessons(lessons, at)
+        .into_iter()
+        .take(count)
+        .map(SensorLesson::from_lesson)
+        .collect();
+
+    let status = match (current, next.is_empty()) {
+        (Some(_), _) => StatusClass::Lesson,
+        (None, false) => StatusClass::Break,
+        (None, true) => StatusClass::Free,
+    };
+
+    SensorPayload {
+        state: current
+            .map(|lesson| lesson.subject.as_str())
+            .filter(|subject| !subject.is_empty())
+            .unwrap_or("free")
+            .to_string(),
+        attributes: SensorAttributes {
+            status,
+            current: current.map(SensorLesson::from_lesson),
+            next,
+        },
+    }
+}
+
+pub fn sensor_json(lessons: &[Lesson], at: NaiveTime, count: usize) -> String {
+    serde_json::to_string(&sensor_payload(lessons, at, count)).unwrap_or_default()
+}
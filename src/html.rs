@@ -0,0 +1,176 @@
+use chrono::{NaiveTime, Timelike};
+
+use crate::data::Schema;
+use crate::model::{Lesson, WeekSchedule};
+use crate::text::week_columns;
+
+const STYLE: &str = "
+body { font-family: 'Open Sans', sans-serif; margin: 1em; color: #000; background: #fff; }
+h1 { font-size: 1.2em; }
+.schedule { position: relative; }
+.box, .text, .lesson { position: absolute; box-sizing: border-box; overflow: visible; }
+.box { border-style: solid; border-width: 1px; }
+.box-Footer, .box-ClockFrameStart, .box-ClockFrameEnd { border-width: 0; }
+.text { white-space: nowrap; pointer-events: none; }
+.heading { text-align: center; }
+.week { display: flex; }
+.axis { position: relative; width: 3.5em; font-size: 0.75em; }
+.axis span { position: absolute; }
+.day { position: relative; flex: 1; border-left: 1px solid #bbb; }
+.day h2 { font-size: 0.9em; text-align: center; margin: 0; height: 1.5em; }
+.slots { position: relative; }
+.lesson { left: 2px; right: 2px; border: 1px solid #000; padding: 2px 4px; font-size: 0.75em; overflow: hidden; }
+.lesson .subject { font-weight: bold; }
+.details { display: none; position: absolute; z-index: 1; left: 100%; top: 0; min-width: 12em;
+    padding: 0.5em; background: #fff; color: #000; border: 1px solid #000; box-shadow: 2px 2px 4px rgba(0, 0, 0, 0.3); }
+[tabindex]:hover, [tabindex]:focus { z-index: 1; overflow: visible; outline: 2px solid #000; }
+[tabindex]:hover > .details, [tabindex]:focus > .details { display: block; }
+@media print {
+    @page { size: A4 landscape; margin: 1cm; }
+    body { margin: 0; }
+    * { -webkit-print-color-adjust: exact; print-color-adjust: exact; }
+    .details { display: none !important; }
+    [tabindex] { outline: none !important; }
+}
+";
+
+pub trait ToHtml {
+    fn to_html(&self) -> String;
+}
+
+// A self-contained page with embedded CSS, e.g. `to_html(&schema)` or `to_html(&week)`
+pub fn to_html(source: &impl ToHtml) -> String {
+    source.to_html()
+}
+
+// Reproduces the API's layout with positioned elements instead of SVG
+impl ToHtml for Schema {
+    fn to_html(&self) -> String {
+        let width = self.box_list.iter().map(|b| b.x + b.width).max().unwrap_or(0);
+        let height = self.box_list.iter().map(|b| b.y + b.height).max().unwrap_or(0);
+
+        let mut body = format!("<div class=\"schedule\" style=\"width: {}px; height: {}px;\">\n", width, height);
+        for rect in &self.box_list {
+            let lessons: Vec<Lesson> = match &rect.lesson_guids {
+                Some(guids) => self.lesson_info.iter()
+                    .filter(|l| guids.contains(&l.guid_id))
+                    .filter_map(|l| Lesson::from_info(l).ok())
+                    .collect(),
+                None => Vec::new(),
+            };
+
+            body += &format!(
+                "<div class=\"box box-{}\" style=\"left: {}px; top: {}px; width: {}px; height: {}px; background: {}; border-color: {};\"",
+                escape(&rect.type_field), rect.x, rect.y, rect.width, rect.height, escape(&rect.b_color), escape(&rect.f_color)
+            );
+            if lessons.is_empty() {
+                body += "></div>\n";
+            }
+            else {
+                body += &format!(" tabindex=\"0\">{}</div>\n", details(lessons.iter()));
+            }
+        }
+
+        for txt in &self.text_list {
+            let parent = self.box_list.iter().find(|b| b.id == txt.parent_id);
+            let position = match (txt.type_field.as_str(), parent) {
+                ("ClockAxisBox" | "HeadingDay", Some(parent)) => {
+                    format!("left: {}px; width: {}px; top: {}px;", parent.x, parent.width, txt.y)
+                },
+                _ => format!("left: {}px; top: {}px;", txt.x, txt.y),
+            };
+            let class = if matches!(txt.type_field.as_str(), "ClockAxisBox" | "HeadingDay") {"text heading"} else {"text"};
+
+            body += &format!(
+                "<span class=\"{}\" style=\"{} color: {}; font-size: {}px;{}{}\">{}</span>\n",
+                class, position, escape(&txt.f_color), txt.fontsize,
+                if txt.bold {" font-weight: bold;"} else {""},
+                if txt.italic {" font-style: italic;"} else {""},
+                escape(&txt.text)
+            );
+        }
+        body += "</div>\n";
+
+        page("Schedule", &body)
+    }
+}
+
+impl ToHtml for WeekSchedule {
+    fn to_html(&self) -> String {
+        let columns = week_columns(self);
+        let ranges = columns.iter().flat_map(|c| c.lessons.iter().map(|(range, _)| range));
+        let first = ranges.clone().map(|range| range.start.hour()).min().unwrap_or(8);
+        let last = ranges.map(|range| range.end.hour() + 1).max().unwrap_or(17).max(first + 1);
+        let offset = |time: NaiveTime| (time.num_seconds_from_midnight() / 60) as i64 - (first * 60) as i64;
+
+        let title = format!("Week {} {}", self.week, self.year);
+        let height = (last - first) * 60;
+
+        let mut body = format!("<h1>{}</h1>\n<div class=\"week\">\n", escape(&title));
+        body += &format!("<div class=\"axis\" style=\"margin-top: 1.5em; height: {}px;\">", height);
+        for hour in first..last {
+            body += &format!("<span style=\"top: {}px;\">{:02}:00</span>", (hour - first) * 60, hour);
+        }
+        body += "</div>\n";
+
+        for column in &columns {
+            let heading = match column.weekday.and_then(|day| self.date_of(day)) {
+                Some(date) => format!("{} {}", column.title, date.format("%d/%m")),
+                None => column.title.clone(),
+            };
+            body += &format!("<div class=\"day\"><h2>{}</h2><div class=\"slots\" style=\"height: {}px;\">\n", escape(&heading), height);
+            for (range, lesson) in &column.lessons {
+                let top = offset(range.start);
+                body += &format!(
+                    "<div class=\"lesson\" tabindex=\"0\" style=\"top: {}px; height: {}px; background: {};\">\
+                     <div class=\"subject\">{}</div><div>{}</div>{}</div>\n",
+                    top,
+                    offset(range.end) - top,
+                    escape(lesson.color.as_deref().unwrap_or("#e6e6e6")),
+                    escape(&lesson.subject),
+                    escape(&lesson.room),
+                    details(std::iter::once(*lesson))
+                );
+            }
+            body += "</div></div>\n";
+        }
+        body += "</div>\n";
+
+        page(&title, &body)
+    }
+}
+
+fn details<'a>(lessons: impl Iterator<Item = &'a Lesson>) -> String {
+    let mut out = String::from("<div class=\"details\">");
+    for lesson in lessons {
+        out += &format!(
+            "<div><strong>{}</strong><br>{}–{}",
+            escape(&lesson.subject),
+            lesson.start.format("%H:%M"),
+            lesson.end.format("%H:%M")
+        );
+        for (label, value) in [("Teacher", &lesson.teacher), ("Room", &lesson.room)] {
+            if !value.is_empty() {
+                out += &format!("<br>{}: {}", label, escape(value));
+            }
+        }
+        out += "</div>";
+    }
+    out += "</div>";
+    out
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title), STYLE, body
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,142 @@
+use chrono::NaiveDateTime;
+
+use std::collections::HashMap;
+
+use crate::model::Lesson;
+
+const TIMEZONE: &str = "Europe/Stockholm";
+
+const VTIMEZONE: &str = "BEGIN:VTIMEZONE\r
+TZID:Europe/Stockholm\r
+BEGIN:DAYLIGHT\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+TZNAME:CEST\r
+DTSTART:19700329T020000\r
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+TZNAME:CET\r
+DTSTART:19701025T030000\r
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r
+END:STANDARD\r
+END:VTIMEZONE\r
+";
+
+#[derive(Debug, Clone, Default)]
+pub struct IcsOptions {
+    // Minutes before every lesson to raise an alarm, `None` for no alarms
+    pub reminder: Option<u32>,
+    // Overrides `reminder` by subject, compared case-insensitively. `None` silences the subject.
+    pub subject_reminders: HashMap<String, Option<u32>>,
+    // Minutes between refreshes suggested to subscribed calendar apps, see `server`
+    pub refresh_interval: Option<u32>,
+}
+
+impl IcsOptions {
+    pub fn reminder_for(&self, subject: &str) -> Option<u32> {
+        self.subject_reminders.iter()
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case(subject.trim()))
+            .map_or(self.reminder, |(_, minutes)| *minutes)
+    }
+}
+
+// Builds an RFC 5545 calendar, e.g. `to_ical(&week)` or `to_ical(&term)`.
+// Lessons without a resolved date are skipped.
+pub fn to_ical<'a, I>(lessons: I) -> String
+where
+    I: IntoIterator<Item = &'a Lesson>,
+{
+    to_ical_with(lessons, &IcsOptions::default())
+}
+
+pub fn to_ical_with<'a, I>(lessons: I, options: &IcsOptions) -> String
+where
+    I: IntoIterator<Item = &'a Lesson>,
+{
+    let mut cal = String::new();
+    cal += "BEGIN:VCALENDAR\r\n";
+    cal += "VERSION:2.0\r\n";
+    cal += concat!("PRODID:-//", env!("CARGO_PKG_NAME"), "//", env!("CARGO_PKG_VERSION"), "//EN\r\n");
+    cal += "CALSCALE:GREGORIAN\r\n";
+    if let Some(minutes) = options.refresh_interval {
+        // RFC 7986, and the older property Outlook and Google Calendar read
+        property(&mut cal, "REFRESH-INTERVAL;VALUE=DURATION", &format!("PT{}M", minutes));
+        property(&mut cal, "X-PUBLISHED-TTL", &format!("PT{}M", minutes));
+    }
+    cal += VTIMEZONE;
+
+    let stamp = crate::timezone::now().format("%Y%m%dT%H%M%SZ").to_string();
+    for lesson in lessons {
+        if let Some(event) = event(lesson, &stamp, options) {
+            cal += &event;
+        }
+    }
+
+    cal += "END:VCALENDAR\r\n";
+    cal
+}
+
+fn event(lesson: &Lesson, stamp: &str, options: &IcsOptions) -> Option<String> {
+    let start = lesson.start_datetime()?;
+    let end = lesson.end_datetime()?;
+
+    let mut event = String::new();
+    event += "BEGIN:VEVENT\r\n";
+    property(&mut event, "UID", &uid(lesson)?);
+    property(&mut event, "DTSTAMP", stamp);
+    property(&mut event, &format!("DTSTART;TZID={}", TIMEZONE), &local_time(start));
+    property(&mut event, &format!("DTEND;TZID={}", TIMEZONE), &local_time(end));
+    property(&mut event, "SUMMARY", &escape(&lesson.subject));
+    if !lesson.room.is_empty() {
+        property(&mut event, "LOCATION", &escape(&lesson.room));
+    }
+    if !lesson.teacher.is_empty() {
+        property(&mut event, "DESCRIPTION", &escape(&lesson.teacher));
+    }
+    if let Some(minutes) = options.reminder_for(&lesson.subject) {
+        event += "BEGIN:VALARM\r\n";
+        property(&mut event, "ACTION", "DISPLAY");
+        property(&mut event, "DESCRIPTION", &escape(&lesson.subject));
+        property(&mut event, "TRIGGER", &format!("-PT{}M", minutes));
+        event += "END:VALARM\r\n";
+    }
+    event += "END:VEVENT\r\n";
+
+    Some(event)
+}
+
+// Stable per lesson occurrence, so re-exported calendars update events instead of duplicating them
+pub(crate) fn uid(lesson: &Lesson) -> Option<String> {
+    let start = lesson.start_datetime()?;
+    Some(format!("{}-{}@{}", lesson.id, start.format("%Y%m%d"), env!("CARGO_PKG_NAME")))
+}
+
+fn local_time(time: NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Writes a content line, folded at 75 octets as required by RFC 5545
+fn property(out: &mut String, name: &str, value: &str) {
+    let line = format!("{}:{}", name, value);
+
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        width += c.len_utf8();
+        out.push(c);
+    }
+    out.push_str("\r\n");
+}
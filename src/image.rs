@@ -1,141 +1,1545 @@
-use svg::node::element::{Rectangle, Text as TextElement, Line};
+use svg::node::element::{Animate, ClipPath, Definitions, Description, Group, Line, Link, Pattern, Rectangle, Style, Text as TextElement, Title};
 use svg::node::Text as TextNode;
 
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday};
+
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
-use std::num::ParseIntError;
 use std::fmt;
 
-use crate::{Dimensions, data::*};
+use crate::{Dimensions, data, data::*};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderError {
+    // A schema or theme color that is not a '#rrggbb' hex code
+    Color(String),
+    // A text refers to a box that is not in the schema
+    MissingParent {
+        id: i64,
+        parent_id: i64,
+    },
+    // The schema or options ask for something the renderer cannot draw
+    Unsupported(String),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for RenderError {}
 
-#[derive(Debug, PartialEq)]
-struct Rgb {
-    r: u8,
-    g: u8,
-    b: u8,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
 }
 
 impl FromStr for Rgb {
-    type Err = ParseIntError;
+    type Err = RenderError;
 
     // Parses a color hex code of the form '#rRgGbB..' into an
     // instance of 'Rgb'
     fn from_str(hex_code: &str) -> Result<Self, Self::Err> {
         // u8::from_str_radix(src: &str, radix: u32) converts a string
         // slice in a given base to u8
-        let r: u8 = u8::from_str_radix(&hex_code[1..3], 16)?;
-        let g: u8 = u8::from_str_radix(&hex_code[3..5], 16)?;
-        let b: u8 = u8::from_str_radix(&hex_code[5..7], 16)?;
+        let channel = |range| hex_code.get(range)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| RenderError::Color(hex_code.to_string()));
+        let r: u8 = channel(1..3)?;
+        let g: u8 = channel(3..5)?;
+        let b: u8 = channel(5..7)?;
 
         Ok(Rgb { r, g, b })
     }
 }
 
+impl Rgb {
+    const BLACK: Rgb = Rgb { r: 0, g: 0, b: 0 };
+    const WHITE: Rgb = Rgb { r: 255, g: 255, b: 255 };
+
+    fn gray(&self) -> Rgb {
+        let l = (0.299 * self.r as f64 + 0.587 * self.g as f64 + 0.114 * self.b as f64).round() as u8;
+        Rgb { r: l, g: l, b: l }
+    }
+}
+
 impl fmt::Display for Rgb {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "rgb({}, {}, {})", self.r, self.g, self.b)
     }
 }
 
-fn rect_style(rect: &Box) -> Result<String, ParseIntError> {
-    // bg = fill, fg = stroke
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Theme {
+    // Fill of every box except lessons, e.g. the page, headings and clock axis
+    pub background: Option<String>,
+    // Box strokes, lines and all text outside of lessons
+    pub foreground: Option<String>,
+    // Replaces individual API colors, keys are compared case-insensitively
+    pub colors: HashMap<String, String>,
+    // Applied after all other colors, independent of the API's own blackAndWhite flag
+    pub monochrome: Option<Monochrome>,
+    // Replaces lesson colors with ones that stay distinguishable with red-green color blindness.
+    // A color gets the same replacement in every render unless two colors compete for it.
+    pub colorblind_safe: bool,
+}
 
-    let mut cursor_pointer = false;
-    let mut stroke_width = 1;
-    match rect.type_field.as_str() {
-        "Footer"|"ClockFrameStart"|"ClockFrameEnd" => {
-            stroke_width = 0;
-        },
-        "Lesson" => {
-            cursor_pointer = true;
-        },
-        _ => {},
+// Paul Tol's "light" scheme, light enough for black text
+const COLORBLIND_PALETTE: [&str; 8] = ["#77AADD", "#EE8866", "#EEDD88", "#FFAABB", "#99DDFF", "#44BB99", "#BBCC33", "#AAAA00"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Monochrome {
+    Grayscale,
+    // Black on white, lessons are told apart by a hatch pattern per color instead
+    BlackAndWhite,
+}
+
+impl Theme {
+    // Lesson boxes keep their colors so subjects stay recognizable
+    pub fn dark() -> Self {
+        Theme {
+            background: Some("#1e1e1e".to_string()),
+            foreground: Some("#d4d4d4".to_string()),
+            colors: HashMap::new(),
+            monochrome: None,
+            colorblind_safe: false,
+        }
+    }
+
+    // Adds the palette replacements for the lesson colors of `days` to `colors`, colors the
+    // caller already replaced are left alone
+    pub(crate) fn with_palette(&self, days: &[Schema]) -> Theme {
+        let slots = COLORBLIND_PALETTE.len();
+        let mut lesson_colors: Vec<(usize, String)> = days.iter()
+            .flat_map(|day| day.boxes_of_type(BoxType::Lesson))
+            .map(|rect| rect.b_color.to_ascii_uppercase())
+            .filter(|color| !self.colors.keys().any(|from| from.eq_ignore_ascii_case(color)))
+            .map(|color| (fnv1a(color.as_bytes()) as usize % slots, color))
+            .collect();
+        lesson_colors.sort();
+        lesson_colors.dedup();
+
+        let mut theme = self.clone();
+        let mut taken = [false; COLORBLIND_PALETTE.len()];
+        for (preferred, color) in lesson_colors {
+            // The next free slot, or the preferred one again once the palette runs out
+            let slot = (0..slots).map(|i| (preferred + i) % slots).find(|slot| !taken[*slot]).unwrap_or(preferred);
+            taken[slot] = true;
+            theme.colors.insert(color, COLORBLIND_PALETTE[slot].to_string());
+        }
+        theme
+    }
+
+    fn paint(&self, color: &str) -> Result<Rgb, RenderError> {
+        let rgb = Rgb::from_str(color)?;
+        Ok(match self.monochrome {
+            None => rgb,
+            Some(Monochrome::Grayscale) => rgb.gray(),
+            Some(Monochrome::BlackAndWhite) => Rgb::BLACK,
+        })
+    }
+
+    fn color<'a>(&'a self, api_color: &'a str) -> &'a str {
+        self.colors.iter()
+            .find(|(from, _)| from.eq_ignore_ascii_case(api_color))
+            .map_or(api_color, |(_, to)| to)
+    }
+
+    fn background<'a>(&'a self, api_color: &'a str) -> &'a str {
+        self.background.as_deref().unwrap_or_else(|| self.color(api_color))
+    }
+
+    fn foreground<'a>(&'a self, api_color: &'a str) -> &'a str {
+        self.foreground.as_deref().unwrap_or_else(|| self.color(api_color))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontOptions {
+    pub family: String,
+    // Tried in order when `family` is not installed, generic families like `sans-serif` work too
+    pub fallbacks: Vec<String>,
+    // Multiplies every font size sent by the API
+    pub scale: f64,
+    // A TTF, OTF, WOFF or WOFF2 file embedded as `family`, so the output looks the same without it installed
+    pub embed: Option<Vec<u8>>,
+}
+
+impl Default for FontOptions {
+    fn default() -> Self {
+        FontOptions {
+            family: "Open Sans".to_string(),
+            fallbacks: vec!["sans-serif".to_string()],
+            scale: 1.0,
+            embed: None,
+        }
+    }
+}
+
+impl FontOptions {
+    pub(crate) fn font_family(&self) -> String {
+        const GENERIC: [&str; 6] = ["serif", "sans-serif", "monospace", "cursive", "fantasy", "system-ui"];
+
+        std::iter::once(&self.family)
+            .chain(&self.fallbacks)
+            .map(|family| if GENERIC.contains(&family.as_str()) {
+                family.clone()
+            } else {
+                format!("'{}'", family.replace('\'', ""))
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    pub(crate) fn size(&self, fontsize: f64) -> f64 {
+        fontsize * self.scale
+    }
+
+    fn font_face(&self) -> Result<Option<String>, RenderError> {
+        let data = match &self.embed {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let (mime, format) = match data.get(0..4) {
+            Some(b"wOFF") => ("font/woff", "woff"),
+            Some(b"wOF2") => ("font/woff2", "woff2"),
+            Some(b"OTTO") => ("font/otf", "opentype"),
+            Some(b"\0\x01\0\0") | Some(b"true") => ("font/ttf", "truetype"),
+            _ => return Err(RenderError::Unsupported("embedded font is not TrueType, OpenType or WOFF".to_string())),
+        };
+
+        Ok(Some(format!(
+            "@font-face {{ font-family: '{}'; src: url(data:{};base64,{}) format('{}'); }}",
+            self.family.replace('\'', ""), mime, base64(data), format,
+        )))
+    }
+}
+
+// Stable across runs and platforms, unlike the standard library's hasher
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c9dc5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x01000193))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SvgOptions {
+    pub theme: Theme,
+    pub fonts: FontOptions,
+    // No fixed size, the document scales to fit its container keeping the aspect ratio
+    pub responsive: bool,
+    // Wall clock time for the live markers, e.g. `timezone::now_in(SCHEDULE_TIMEZONE)`
+    pub now: Option<NaiveDateTime>,
+    // A horizontal line across the schedule at the time of `now`
+    pub now_line: bool,
+    pub highlight: Highlight,
+    // Links lesson boxes to this URL, with `{guid}` replaced by the lesson's GUID
+    pub link: Option<String>,
+    // Lesson details shown when hovering a lesson box
+    pub tooltips: bool,
+    // Announced by screen readers along with a summary of the lessons, "Schedule" by default
+    pub title: Option<String>,
+    pub overflow: Overflow,
+    pub box_styles: HashMap<BoxType, BoxStyle>,
+    // Where to add a list of the subjects and their colors, if anywhere
+    pub legend: Option<LegendPosition>,
+    // Draws a weekday header row and a time axis when the API render has none
+    pub headers: bool,
+    // Language of the headers and the accessible description
+    pub locale: Locale,
+    // Keeps the API's element order instead of layering by parent relationships, which can put
+    // lines over lesson text
+    pub api_order: bool,
+    // Multiplies the document's size, 1 by default. The layout stays that of the requested
+    // dimensions, unlike asking the API for a larger render.
+    pub scale: Option<f64>,
+    // Caller supplied footer text, e.g. when the schedule was generated and for whom
+    pub footer: Option<FooterText>,
+    // Leaves out the pointer cursor and keyboard focus of lesson boxes, for SVGs that end up
+    // flattened to an image
+    pub static_image: bool,
+    // Only the part of the day between these times, stretched to the full height
+    pub window: Option<(NaiveTime, NaiveTime)>,
+    // Shades the elapsed part of the lesson going on at `now`
+    pub progress: Option<Progress>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    // As of `now`
+    Overlay,
+    // Keeps growing from `now` with a SMIL animation until the lesson ends, for pages that
+    // are only reloaded now and then
+    Animated,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    English,
+    Swedish,
+}
+
+impl Locale {
+    pub fn weekday(self, weekday: Weekday) -> &'static str {
+        const ENGLISH: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+        const SWEDISH: [&str; 7] = ["måndag", "tisdag", "onsdag", "torsdag", "fredag", "lördag", "söndag"];
+
+        let index = weekday.num_days_from_monday() as usize;
+        match self {
+            Locale::English => ENGLISH[index],
+            Locale::Swedish => SWEDISH[index],
+        }
+    }
+
+    // Day and month, e.g. "15 Jan" or "15/1"
+    pub fn date(self, date: NaiveDate) -> String {
+        match self {
+            Locale::English => date.format("%-d %b").to_string(),
+            Locale::Swedish => date.format("%-d/%-m").to_string(),
+        }
+    }
+
+    // The weekday, followed by the date when known
+    pub fn day(self, weekday: Weekday, date: Option<NaiveDate>) -> String {
+        match date {
+            Some(date) => format!("{} {}", self.weekday(weekday), self.date(date)),
+            None => self.weekday(weekday).to_string(),
+        }
+    }
+
+    fn lesson_count(self, count: usize) -> String {
+        match (self, count) {
+            (Locale::English, 1) => "1 lesson".to_string(),
+            (Locale::English, count) => format!("{} lessons", count),
+            (Locale::Swedish, 1) => "1 lektion".to_string(),
+            (Locale::Swedish, count) => format!("{} lektioner", count),
+        }
+    }
+}
+
+// Drawn in the API's footer box, or in a strip below the schedule when it has none
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FooterText {
+    // Instead of the API's footer text
+    Replace(String),
+    // Right aligned, next to the API's footer text
+    Append(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegendPosition {
+    Below,
+    Right,
+}
+
+// Overrides for all boxes of a type, unset fields keep the default look
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BoxStyle {
+    pub corner_radius: Option<f64>,
+    pub fill_opacity: Option<f64>,
+    pub stroke_width: Option<f64>,
+}
+
+// What to do with text running past the edges of its box
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    #[default]
+    Visible,
+    Clip,
+    // Clips and also cuts off lines that are estimated to be too wide with "…"
+    Ellipsis,
+}
+
+// Which lessons around `now` to emphasize, all other lessons are faded out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Highlight {
+    #[default]
+    None,
+    Current,
+    CurrentAndNext,
+}
+
+impl SvgOptions {
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn fonts(mut self, fonts: FontOptions) -> Self {
+        self.fonts = fonts;
+        self
+    }
+
+    pub fn responsive(mut self, responsive: bool) -> Self {
+        self.responsive = responsive;
+        self
+    }
+
+    pub fn now(mut self, now: NaiveDateTime) -> Self {
+        self.now = Some(now);
+        self
     }
 
-    let cursor_string = if cursor_pointer {
-        " cursor: pointer;"
-    } else {
-        ""
+    pub fn now_line(mut self, now_line: bool) -> Self {
+        self.now_line = now_line;
+        self
+    }
+
+    pub fn highlight(mut self, highlight: Highlight) -> Self {
+        self.highlight = highlight;
+        self
+    }
+
+    pub fn link<S: Into<String>>(mut self, template: S) -> Self {
+        self.link = Some(template.into());
+        self
+    }
+
+    pub fn tooltips(mut self, tooltips: bool) -> Self {
+        self.tooltips = tooltips;
+        self
+    }
+
+    pub fn title<S: Into<String>>(mut self, title: S) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn box_style(mut self, box_type: BoxType, style: BoxStyle) -> Self {
+        self.box_styles.insert(box_type, style);
+        self
+    }
+
+    pub fn legend(mut self, position: LegendPosition) -> Self {
+        self.legend = Some(position);
+        self
+    }
+
+    pub fn headers(mut self, headers: bool) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    pub fn api_order(mut self, api_order: bool) -> Self {
+        self.api_order = api_order;
+        self
+    }
+
+    pub fn scale(mut self, scale: f64) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    pub fn footer(mut self, footer: FooterText) -> Self {
+        self.footer = Some(footer);
+        self
+    }
+
+    pub fn static_image(mut self, static_image: bool) -> Self {
+        self.static_image = static_image;
+        self
+    }
+
+    pub fn window(mut self, start: NaiveTime, end: NaiveTime) -> Self {
+        self.window = Some((start, end));
+        self
+    }
+
+    pub fn progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+}
+
+// Collects the distinct style declarations of the document, so each is written out once
+#[derive(Default)]
+struct Stylesheet {
+    rules: Vec<String>,
+    classes: Vec<(String, String)>,
+}
+
+impl Stylesheet {
+    fn rule(&mut self, selector: &str, declarations: &str) {
+        self.rules.push(format!("{} {{ {} }}", selector, declarations));
+    }
+
+    fn class(&mut self, prefix: &str, declarations: String) -> String {
+        if let Some((class, _)) = self.classes.iter().find(|(_, existing)| *existing == declarations) {
+            return class.clone();
+        }
+
+        let class = format!("{}{}", prefix, self.classes.len());
+        self.classes.push((class.clone(), declarations));
+        class
+    }
+
+    fn to_css(&self) -> String {
+        let classes = self.classes.iter().map(|(class, declarations)| format!(".{} {{ {} }}", class, declarations));
+        self.rules.iter().cloned().chain(classes).collect::<Vec<String>>().join("\n")
+    }
+}
+
+// `ClockAxisBox` -> `clock-axis-box`
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::new();
+    for c in name.chars() {
+        if c.is_uppercase() && !kebab.is_empty() {
+            kebab.push('-');
+        }
+        kebab.extend(c.to_lowercase());
+    }
+    kebab
+}
+
+fn subject_class(subject: &str) -> String {
+    let slug: String = subject.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() {c} else {'-'})
+        .collect();
+    format!("subject-{}", slug)
+}
+
+// Subject, time, room and teacher on separate lines
+fn details(info: &LessonInfo) -> Vec<String> {
+    let time = match (info.start_time(), info.end_time()) {
+        (Ok(start), Ok(end)) => format!("{}–{}", start.format("%H:%M"), end.format("%H:%M")),
+        _ => String::new(),
+    };
+
+    [info.subject(), Some(time.as_str()), info.room(), info.teacher()].iter()
+        .flatten()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+// Every lesson in weekday and time order, for the document description
+fn summary(days: &[Schema], locale: Locale) -> String {
+    let mut lessons: Vec<&LessonInfo> = days.iter().flat_map(|day| day.lessons()).collect();
+    lessons.sort_by(|a, b| (a.day_of_week_number, &a.time_start).cmp(&(b.day_of_week_number, &b.time_start)));
+
+    let mut summary = locale.lesson_count(lessons.len());
+    for info in lessons {
+        let weekday = info.weekday().map_or("", |weekday| locale.weekday(weekday));
+        summary += &format!("; {} {}", weekday, details(info).join(", "));
+    }
+    summary
+}
+
+// The svg crate writes attribute values and text as they are
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn lesson_info<'a>(rect: &Box, lessons: &HashMap<&str, &'a LessonInfo>) -> Option<&'a LessonInfo> {
+    rect.lesson_guids.as_ref()?
+        .iter()
+        .find_map(|guid| lessons.get(guid.as_str()).copied())
+}
+
+// Fill and stroke of a box. Black and white renders fill everything white, the SVG output
+// puts hatch patterns on the lessons instead.
+pub(crate) fn rect_colors(rect: &Box, theme: &Theme) -> Result<(Rgb, Rgb), RenderError> {
+    let stroke = theme.paint(theme.foreground(&rect.f_color))?;
+    let fill = if theme.monochrome == Some(Monochrome::BlackAndWhite) {
+        Rgb::WHITE
+    }
+    else if rect.box_type() == BoxType::Lesson {
+        theme.paint(theme.color(&rect.b_color))?
+    }
+    else {
+        theme.paint(theme.background(&rect.b_color))?
+    };
+    Ok((fill, stroke))
+}
+
+pub(crate) fn text_color(txt: &Text, parent: Option<&Box>, theme: &Theme) -> Result<Rgb, RenderError> {
+    match parent {
+        Some(parent) if parent.box_type() == BoxType::Lesson => theme.paint(theme.color(&txt.f_color)),
+        _ => theme.paint(theme.foreground(&txt.f_color)),
+    }
+}
+
+pub(crate) fn line_color(line: &data::Line, theme: &Theme) -> Result<Rgb, RenderError> {
+    theme.paint(theme.foreground(&line.color))
+}
+
+fn rect_style(rect: &Box, theme: &Theme, hatch: Option<usize>) -> Result<String, RenderError> {
+    let (fill, stroke) = rect_colors(rect, theme)?;
+    let fill = match hatch {
+        Some(index) if theme.monochrome == Some(Monochrome::BlackAndWhite) => format!("url(#hatch-{})", index),
+        _ => fill.to_string(),
     };
+    Ok(format!("fill: {}; stroke: {};", fill, stroke))
+}
+
+fn text_style(txt: &Text, parent: Option<&Box>, theme: &Theme, fonts: &FontOptions) -> Result<String, RenderError> {
+    let color = text_color(txt, parent, theme)?;
+    let weight = if txt.bold {" font-weight: bold;"} else {""};
+    let style = if txt.italic {" font-style: italic;"} else {""};
+    Ok(format!("fill: {}; font-size: {}px;{}{}", color, fonts.size(txt.fontsize), weight, style))
+}
 
-    let fg = Rgb::from_str(&rect.f_color)?;
-    let bg = Rgb::from_str(&rect.b_color)?;
-    Ok(format!("fill: {}; stroke: {}; stroke-width: {};{}", bg, fg, stroke_width, cursor_string))
+pub fn generate_svg(schema_data: &Schema, dimensions: Dimensions, options: &SvgOptions) -> Result<svg::Document, RenderError> {
+    generate_week_svg(std::slice::from_ref(schema_data), dimensions, options)
 }
 
-fn text_style(txt: &Text) -> Result<String, ParseIntError> {
-    let color = Rgb::from_str(&txt.f_color)?;
-    Ok(format!("fill: {}; font-size: {}px; font-family: Open Sans; pointer-events: none;", color, txt.fontsize))
+// Lays out the days side by side, each as rendered by the API at `dimensions`. The days
+// share the clock axis of the first one, so they should all be requested at the same size.
+pub fn generate_week_svg(days: &[Schema], dimensions: Dimensions, options: &SvgOptions) -> Result<svg::Document, RenderError> {
+    let started = std::time::Instant::now();
+    let doc = build_week_svg(days, dimensions, options);
+    crate::metrics::record_render("svg", started.elapsed());
+    doc
 }
 
-pub fn generate_svg(schema_data: &Schema, dimensions: Dimensions) -> Result<svg::Document, std::num::ParseIntError> {
+fn build_week_svg(days: &[Schema], dimensions: Dimensions, options: &SvgOptions) -> Result<svg::Document, RenderError> {
+    let remapped;
+    let options = if options.theme.colorblind_safe {
+        remapped = SvgOptions {
+            theme: options.theme.with_palette(days),
+            ..options.clone()
+        };
+        &remapped
+    }
+    else {
+        options
+    };
+    let mut scale = days.first().and_then(TimeScale::of);
+    let cropped: Vec<Schema>;
+    let days = match options.window.and_then(|(start, end)| Crop::new(days.first()?, scale?, start, end)) {
+        Some(crop) => {
+            cropped = days.iter().map(|day| crop.apply(day)).collect();
+            scale = scale.map(|scale| crop.scale(scale));
+            &cropped[..]
+        },
+        None => days,
+    };
+    let SvgOptions { theme, fonts, responsive, .. } = options;
+    let axis = days.first().map_or(0, axis_width);
+    let column_width = dimensions.width as i64 - axis;
+    let width = axis + column_width * days.len().max(1) as i64;
+    let height = dimensions.height;
+
     let mut doc = svg::Document::new()
-        .set("width", dimensions.width)
-        .set("height", dimensions.height)
-        .set("shape-rendering", "crispEdges")
-        .set("viewBox", (0, 0, dimensions.width, dimensions.height));
+        .set("shape-rendering", "crispEdges");
+    let title = options.title.as_deref().unwrap_or("Schedule");
+    doc = doc
+        .set("role", "group")
+        .set("aria-labelledby", "schedule-title")
+        .set("aria-describedby", "schedule-desc")
+        .add(Title::new().set("id", "schedule-title").add(TextNode::new(escape(title))))
+        .add(Description::new().set("id", "schedule-desc").add(TextNode::new(escape(&summary(days, options.locale)))));
+    let mut content = Group::new();
+
+    let mut stylesheet = Stylesheet::default();
+    if let Some(font_face) = fonts.font_face()? {
+        stylesheet.rules.push(font_face);
+    }
+    stylesheet.rule("rect", "stroke-width: 1;");
+    stylesheet.rule(".footer, .clock-frame-start, .clock-frame-end", "stroke-width: 0;");
+    if !options.static_image {
+        stylesheet.rule(".lesson", "cursor: pointer;");
+    }
+    stylesheet.rule("text", &format!("font-family: {}; pointer-events: none;", fonts.font_family()));
+    let mut box_rules: Vec<(String, String)> = options.box_styles.iter()
+        .map(|(box_type, style)| {
+            let mut declarations = String::new();
+            if let Some(opacity) = style.fill_opacity {
+                declarations += &format!("fill-opacity: {}; ", opacity);
+            }
+            if let Some(width) = style.stroke_width {
+                declarations += &format!("stroke-width: {}; ", width);
+            }
+            (format!("rect.{}", kebab_case(box_type.as_str())), declarations.trim_end().to_string())
+        })
+        .filter(|(_, declarations)| !declarations.is_empty())
+        .collect();
+    box_rules.sort();
+    for (selector, declarations) in box_rules {
+        stylesheet.rule(&selector, &declarations);
+    }
+    if options.highlight != Highlight::None && options.now.is_some() {
+        stylesheet.rule(".lesson.current", "stroke-width: 3;");
+        stylesheet.rule(".lesson.next", "stroke-width: 2; stroke-dasharray: 4 2;");
+        stylesheet.rule(".lesson.faded", "opacity: 0.45;");
+    }
+    if options.progress.is_some() && options.now.is_some() {
+        stylesheet.rule(".progress", &format!("fill: {}; fill-opacity: 0.2; stroke: none; pointer-events: none;", theme.paint(theme.foreground("#000000"))?));
+    }
+    if theme.monochrome == Some(Monochrome::BlackAndWhite) {
+        // A white outline keeps text readable on top of hatch patterns
+        stylesheet.rule("text", &format!("stroke: {}; stroke-width: 3px; paint-order: stroke;", Rgb::WHITE));
+    }
+
+    // Lessons sharing a color share a pattern, so subjects stay distinguishable without color
+    let mut hatches: Vec<&str> = Vec::new();
+    if theme.monochrome == Some(Monochrome::BlackAndWhite) {
+        for rect in days.iter().flat_map(|day| day.boxes_of_type(BoxType::Lesson)) {
+            if !hatches.iter().any(|color| color.eq_ignore_ascii_case(&rect.b_color)) {
+                hatches.push(&rect.b_color);
+            }
+        }
+        if !hatches.is_empty() {
+            content = content.add((0..hatches.len()).fold(Definitions::new(), |defs, index| defs.add(hatch_pattern(index))));
+        }
+    }
+
+    if days.len() > 1 {
+        // Keeps backgrounds spanning a whole render from covering the day to the left
+        let column = Rectangle::new()
+            .set("x", axis)
+            .set("y", 0)
+            .set("width", column_width)
+            .set("height", height);
+        content = content.add(Definitions::new().add(ClipPath::new().set("id", "day-column").add(column)));
+    }
+
+    for (i, day) in days.iter().enumerate() {
+        if i == 0 {
+            content = content.add(draw_schema(day, i, options, &mut stylesheet, &hatches, None)?);
+        }
+        else {
+            content = content.add(
+                draw_schema(day, i, options, &mut stylesheet, &hatches, Some(axis_width(day)))?
+                    .set("transform", format!("translate({}, 0)", column_width * i as i64))
+                    .set("clip-path", "url(#day-column)")
+            );
+        }
+    }
+
+    let now_y = options.now
+        .filter(|_| options.now_line)
+        .and_then(|now| Some(scale?.y(now.time())))
+        .filter(|y| (0.0..=height as f64).contains(y));
+    if let Some(y) = now_y {
+        stylesheet.rule(".now-line", &format!("stroke: {}; stroke-width: 2;", theme.paint(NOW_COLOR)?));
+        content = content.add(
+            Line::new()
+                .set("x1", axis)
+                .set("y1", y)
+                .set("x2", width)
+                .set("y2", y)
+                .set("class", "now-line")
+        );
+    }
+
+    let mut footer_strip = 0;
+    if let Some(footer) = &options.footer {
+        let (text, append) = match footer {
+            FooterText::Replace(text) => (text, false),
+            FooterText::Append(text) => (text, true),
+        };
+        let first = days.first();
+        let footer_box = first.and_then(|day| day.boxes_of_type(BoxType::Footer).next());
+        let fontsize = footer_box
+            .and_then(|rect| first?.texts_for_box(rect.id).next())
+            .map_or(fonts.size(LEGEND_FONT_SIZE * 0.9), |txt| fonts.size(txt.fontsize));
+        let (x, y, box_width, box_height) = match footer_box {
+            Some(rect) => (rect.x, rect.y, rect.width, rect.height),
+            None => {
+                footer_strip = FOOTER_HEIGHT;
+                let background = if theme.monochrome == Some(Monochrome::BlackAndWhite) {Rgb::WHITE} else {theme.paint(theme.background("#FFFFFF"))?};
+                let background = stylesheet.class("r", format!("fill: {}; stroke: none;", background));
+                content = content.add(Rectangle::new()
+                    .set("y", height)
+                    .set("width", width)
+                    .set("height", FOOTER_HEIGHT)
+                    .set("class", background.as_str()));
+                (0, height as i64, width, FOOTER_HEIGHT)
+            },
+        };
+        stylesheet.rule(".custom-footer", &format!("fill: {}; font-size: {}px;", theme.paint(theme.foreground("#000000"))?, fontsize));
+        let mut elem = TextElement::new()
+            .set("x", if append {x + box_width - FOOTER_PADDING} else {x + FOOTER_PADDING})
+            .set("y", y as f64 + box_height as f64 / 2.0 + fontsize * 0.35)
+            .set("class", "custom-footer")
+            .add(TextNode::new(escape(text)));
+        if append {
+            elem = elem.set("text-anchor", "end");
+        }
+        content = content.add(elem);
+    }
+
+    // Room for the local header row and time axis, the schedule is moved right and down to make it
+    let has_headings = days.iter().any(|day| day.boxes_of_type(BoxType::HeadingDay).next().is_some());
+    let top = if options.headers && !has_headings {HEADER_HEIGHT} else {0};
+    let left = if options.headers && axis == 0 && scale.is_some() {TIME_AXIS_WIDTH} else {0};
+    if top > 0 || left > 0 {
+        // The headers go on top, in case the schedule's own background reaches into them
+        let schedule = Group::new()
+            .set("class", "schedule")
+            .set("transform", format!("translate({}, {})", left, top))
+            .add(content);
+        let foreground = theme.paint(theme.foreground("#000000"))?;
+        let background = if theme.monochrome == Some(Monochrome::BlackAndWhite) {Rgb::WHITE} else {theme.paint(theme.background("#FFFFFF"))?};
+        stylesheet.rule(".local-header", &format!("fill: {}; font-size: {}px; font-weight: bold;", foreground, fonts.size(LEGEND_FONT_SIZE)));
+        stylesheet.rule(".local-time", &format!("fill: {}; font-size: {}px;", foreground, fonts.size(LEGEND_FONT_SIZE * 0.9)));
+        let background = stylesheet.class("r", format!("fill: {}; stroke: none;", background));
+
+        content = Group::new().add(schedule);
+        if top > 0 {
+            content = content.add(Rectangle::new()
+                .set("width", left + width)
+                .set("height", top)
+                .set("class", background.as_str()));
+            for column in header_columns(days, axis, column_width) {
+                let label = options.locale.day(column.weekday, column.date);
+                content = content.add(TextElement::new()
+                    .set("x", left as f64 + column.x + column.width / 2.0)
+                    .set("y", top as f64 * 0.65)
+                    .set("text-anchor", "middle")
+                    .set("class", "local-header")
+                    .add(TextNode::new(escape(&label))));
+            }
+        }
+        if let Some(scale) = scale.filter(|_| left > 0) {
+            content = content.add(Rectangle::new()
+                .set("y", top)
+                .set("width", left)
+                .set("height", height as i64 + footer_strip)
+                .set("class", background.as_str()));
+            for hour in 0..24 {
+                let y = scale.y(NaiveTime::from_hms_opt(hour, 0, 0).unwrap_or_default());
+                if (0.0..=height as f64).contains(&y) {
+                    content = content.add(TextElement::new()
+                        .set("x", left - 4)
+                        .set("y", top as f64 + (y + fonts.size(LEGEND_FONT_SIZE) * 0.35).max(fonts.size(LEGEND_FONT_SIZE)))
+                        .set("text-anchor", "end")
+                        .set("class", "local-time")
+                        .add(TextNode::new(format!("{:02}:00", hour))));
+                }
+            }
+        }
+    }
+
+    let (mut doc_width, mut doc_height) = (width + left, height as i64 + top + footer_strip);
+    let entries = legend_entries(days);
+    if let (Some(position), false) = (options.legend, entries.is_empty()) {
+        let (legend, legend_width, legend_height) = draw_legend(&entries, position, doc_width, options, &mut stylesheet, &hatches)?;
+        match position {
+            LegendPosition::Below => {
+                content = content.add(legend.set("transform", format!("translate(0, {})", doc_height)));
+                doc_height += legend_height;
+            },
+            LegendPosition::Right => {
+                content = content.add(legend.set("transform", format!("translate({}, 0)", doc_width)));
+                doc_width += legend_width;
+                doc_height = doc_height.max(legend_height);
+            },
+        }
+    }
+
+    doc = doc.set("viewBox", (0, 0, doc_width, doc_height));
+    if *responsive {
+        doc = doc
+            .set("preserveAspectRatio", "xMidYMid meet")
+            .set("style", "width: 100%; height: auto;");
+    }
+    else {
+        // The viewBox maps the unscaled coordinates onto the scaled size
+        let scale = options.scale.filter(|scale| *scale > 0.0).unwrap_or(1.0);
+        doc = doc
+            .set("width", doc_width as f64 * scale)
+            .set("height", doc_height as f64 * scale);
+    }
+
+    doc = doc
+        .add(Style::new(stylesheet.to_css()))
+        .add(content);
+
+    Ok(doc)
+}
+
+const HEADER_HEIGHT: i64 = 24;
+const TIME_AXIS_WIDTH: i64 = 44;
+
+struct HeaderColumn {
+    weekday: Weekday,
+    date: Option<NaiveDate>,
+    x: f64,
+    width: f64,
+}
+
+// A column per day render. Renders of several days at once get a column per weekday,
+// spanning that weekday's lesson boxes.
+fn header_columns(days: &[Schema], axis: i64, column_width: i64) -> Vec<HeaderColumn> {
+    let mut columns = Vec::new();
+    for (i, day) in days.iter().enumerate() {
+        let offset = (column_width * i as i64) as f64;
+        let lessons: HashMap<&str, &LessonInfo> = day.lesson_info.iter().map(|info| (info.guid_id.as_str(), info)).collect();
+
+        let mut weekdays: Vec<(Weekday, Option<NaiveDate>, f64, f64)> = Vec::new();
+        for rect in day.boxes_of_type(BoxType::Lesson) {
+            let info = match lesson_info(rect, &lessons) {
+                Some(info) => info,
+                None => continue,
+            };
+            let weekday = match info.weekday() {
+                Some(weekday) => weekday,
+                None => continue,
+            };
+            let (left, right) = (rect.x as f64, (rect.x + rect.width) as f64);
+            match weekdays.iter_mut().find(|(existing, ..)| *existing == weekday) {
+                Some((_, date, start, end)) => {
+                    *date = date.or(info.date);
+                    *start = start.min(left);
+                    *end = end.max(right);
+                },
+                None => weekdays.push((weekday, info.date, left, right)),
+            }
+        }
+
+        if let [(weekday, date, _, _)] = weekdays[..] {
+            columns.push(HeaderColumn { weekday, date, x: offset + axis as f64, width: column_width as f64 });
+        }
+        else {
+            weekdays.sort_by_key(|(weekday, ..)| weekday.num_days_from_monday());
+            for (weekday, date, start, end) in weekdays {
+                columns.push(HeaderColumn { weekday, date, x: offset + start, width: end - start });
+            }
+        }
+    }
+    columns
+}
+
+const LEGEND_FONT_SIZE: f64 = 12.0;
+const LEGEND_PADDING: f64 = 8.0;
+
+// Subjects with the color of their first lesson box, in order of appearance
+fn legend_entries(days: &[Schema]) -> Vec<(&str, &str)> {
+    let mut entries: Vec<(&str, &str)> = Vec::new();
+    for day in days {
+        let lessons: HashMap<&str, &LessonInfo> = day.lesson_info.iter().map(|info| (info.guid_id.as_str(), info)).collect();
+        for rect in day.boxes_of_type(BoxType::Lesson) {
+            let subject = match lesson_info(rect, &lessons).and_then(LessonInfo::subject).map(str::trim) {
+                Some(subject) if !subject.is_empty() => subject,
+                _ => continue,
+            };
+            if !entries.iter().any(|(existing, _)| existing.eq_ignore_ascii_case(subject)) {
+                entries.push((subject, &rect.b_color));
+            }
+        }
+    }
+    entries
+}
+
+// The legend with its origin at the top left and its width and height. Below the schedule
+// the entries wrap at `available_width`, to the right of it they are stacked.
+fn draw_legend(entries: &[(&str, &str)], position: LegendPosition, available_width: i64, options: &SvgOptions, stylesheet: &mut Stylesheet, hatches: &[&str]) -> Result<(Group, i64, i64), RenderError> {
+    let theme = &options.theme;
+    let fontsize = options.fonts.size(LEGEND_FONT_SIZE);
+    let (swatch, gap, row) = (fontsize, fontsize / 2.0, fontsize * 1.75);
+    let black_and_white = theme.monochrome == Some(Monochrome::BlackAndWhite);
+
+    stylesheet.rule(".legend-text", &format!("fill: {}; font-size: {}px;", theme.paint(theme.foreground("#000000"))?, fontsize));
+
+    let mut items = Group::new();
+    let (mut x, mut y) = (LEGEND_PADDING, LEGEND_PADDING);
+    let mut right = 0.0f64;
+    for (subject, color) in entries {
+        let entry_width = swatch + gap + estimated_width(subject, fontsize);
+        if position == LegendPosition::Below && x > LEGEND_PADDING && x + entry_width > available_width as f64 - LEGEND_PADDING {
+            x = LEGEND_PADDING;
+            y += row;
+        }
+
+        let fill = match hatches.iter().position(|hatch| hatch.eq_ignore_ascii_case(color)) {
+            Some(index) if black_and_white => format!("url(#hatch-{})", index),
+            _ => theme.paint(theme.color(color))?.to_string(),
+        };
+        let class = stylesheet.class("r", format!("fill: {}; stroke: {};", fill, theme.paint(theme.foreground("#000000"))?));
+        items = items
+            .add(Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("width", swatch)
+                .set("height", swatch)
+                .set("class", class)
+                .set("aria-hidden", "true"))
+            .add(TextElement::new()
+                .set("x", x + swatch + gap)
+                .set("y", y + swatch * 0.85)
+                .set("class", "legend-text")
+                .add(TextNode::new(escape(subject))));
+
+        right = right.max(x + entry_width);
+        match position {
+            LegendPosition::Below => x += entry_width + fontsize * 1.5,
+            LegendPosition::Right => y += row,
+        }
+    }
+
+    let legend_width = match position {
+        LegendPosition::Below => available_width,
+        LegendPosition::Right => (right + LEGEND_PADDING).ceil() as i64,
+    };
+    let legend_height = match position {
+        LegendPosition::Below => (y + row + LEGEND_PADDING - (row - swatch)).ceil() as i64,
+        LegendPosition::Right => (y + LEGEND_PADDING - (row - swatch)).ceil() as i64,
+    };
+
+    let background = if black_and_white {Rgb::WHITE} else {theme.paint(theme.background("#FFFFFF"))?};
+    let legend = Group::new()
+        .set("class", "legend")
+        .add(Rectangle::new()
+            .set("width", legend_width)
+            .set("height", legend_height)
+            .set("class", stylesheet.class("r", format!("fill: {}; stroke: none;", background))))
+        .add(items);
+
+    Ok((legend, legend_width, legend_height))
+}
+
+const NOW_COLOR: &str = "#e53935";
+const FOOTER_HEIGHT: i64 = 20;
+const FOOTER_PADDING: i64 = 4;
+
+// Maps times of day onto the vertical axis of a render
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TimeScale {
+    origin: f64,
+    y: f64,
+    pixels_per_minute: f64,
+}
+
+impl TimeScale {
+    // Lesson boxes span exactly their lesson's times, so they are preferred over the clock axis
+    // labels, which are assumed to sit at the top of their box
+    pub(crate) fn of(schema: &Schema) -> Option<TimeScale> {
+        let lessons: HashMap<&str, &LessonInfo> = schema.lesson_info.iter().map(|info| (info.guid_id.as_str(), info)).collect();
+        let mut samples: Vec<(NaiveTime, f64)> = Vec::new();
+        for rect in schema.boxes_of_type(BoxType::Lesson) {
+            if let Some(info) = lesson_info(rect, &lessons) {
+                if let (Ok(start), Ok(end)) = (info.start_time(), info.end_time()) {
+                    samples.push((start, rect.y as f64));
+                    samples.push((end, (rect.y + rect.height) as f64));
+                }
+            }
+        }
+
+        if samples.is_empty() {
+            let boxes: HashMap<i64, &Box> = schema.box_list.iter().map(|rect| (rect.id, rect)).collect();
+            for txt in &schema.text_list {
+                let parent = boxes.get(&txt.parent_id).filter(|rect| rect.box_type() == BoxType::ClockAxisBox);
+                if let (Some(rect), Ok(time)) = (parent, NaiveTime::parse_from_str(txt.text.trim(), "%H:%M")) {
+                    samples.push((time, rect.y as f64));
+                }
+            }
+        }
+
+        let (first, first_y) = samples.iter().min_by_key(|(time, _)| *time).copied()?;
+        let (last, last_y) = samples.iter().max_by_key(|(time, _)| *time).copied()?;
+        let span = minutes(last) - minutes(first);
+        if span <= 0.0 {
+            return None;
+        }
+
+        Some(TimeScale {
+            origin: minutes(first),
+            y: first_y,
+            pixels_per_minute: (last_y - first_y) / span,
+        })
+    }
+
+    fn y(&self, time: NaiveTime) -> f64 {
+        self.y + (minutes(time) - self.origin) * self.pixels_per_minute
+    }
+}
+
+// Maps the part of a render between two times onto the space the whole day took up, between
+// the day headings and the footer. Boxes reaching outside of it are cut off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Crop {
+    top: f64,
+    bottom: f64,
+    from: f64,
+    factor: f64,
+}
+
+impl Crop {
+    pub(crate) fn new(schema: &Schema, scale: TimeScale, start: NaiveTime, end: NaiveTime) -> Option<Crop> {
+        let top = schema.boxes_of_type(BoxType::HeadingDay).map(|rect| rect.y + rect.height).max().unwrap_or(0);
+        let bottom = schema.boxes_of_type(BoxType::Footer).map(|rect| rect.y).min()
+            .or_else(|| schema.box_list.iter().map(|rect| rect.y + rect.height).max())?;
+        let (from, to) = (scale.y(start), scale.y(end));
+        if to <= from || bottom <= top {
+            return None;
+        }
+
+        Some(Crop {
+            top: top as f64,
+            bottom: bottom as f64,
+            from,
+            factor: (bottom - top) as f64 / (to - from),
+        })
+    }
+
+    fn y(&self, y: f64) -> f64 {
+        self.top + (y - self.from) * self.factor
+    }
+
+    fn inside(&self, y: i64) -> bool {
+        self.top <= y as f64 && y as f64 <= self.bottom
+    }
+
+    fn clamp(&self, y: f64) -> i64 {
+        y.clamp(self.top, self.bottom).round() as i64
+    }
+
+    pub(crate) fn scale(&self, scale: TimeScale) -> TimeScale {
+        TimeScale {
+            origin: scale.origin,
+            y: self.y(scale.y),
+            pixels_per_minute: scale.pixels_per_minute * self.factor,
+        }
+    }
+
+    // Headings, footers and boxes spanning the whole render stay where they are
+    pub(crate) fn apply(&self, schema: &Schema) -> Schema {
+        let mut cropped = Schema {
+            lesson_info: schema.lesson_info.clone(),
+            ..Schema::default()
+        };
+
+        // Texts keep their distance to the top of their box rather than being spread out with it
+        let mut moved: HashMap<i64, (i64, f64)> = HashMap::new();
+        for rect in &schema.box_list {
+            if !(self.inside(rect.y) && self.inside(rect.y + rect.height)) {
+                cropped.box_list.push(rect.clone());
+                continue;
+            }
+            let top = self.y(rect.y as f64);
+            let (clamped_top, clamped_bottom) = (self.clamp(top), self.clamp(self.y((rect.y + rect.height) as f64)));
+            if clamped_bottom - clamped_top < 1 {
+                continue;
+            }
+            moved.insert(rect.id, (rect.y, top));
+            cropped.box_list.push(Box {
+                y: clamped_top,
+                height: clamped_bottom - clamped_top,
+                ..rect.clone()
+            });
+        }
+
+        let kept: HashSet<i64> = cropped.box_list.iter().map(|rect| rect.id).collect();
+        for txt in &schema.text_list {
+            let y = match moved.get(&txt.parent_id) {
+                Some((old_top, top)) => (top + (txt.y - old_top) as f64).round() as i64,
+                None if kept.contains(&txt.parent_id) || !self.inside(txt.y) => txt.y,
+                None if txt.parent_id != 0 => continue,
+                None => self.y(txt.y as f64).round() as i64,
+            };
+            let bottom = y + txt.fontsize.ceil() as i64;
+            if moved.contains_key(&txt.parent_id) && !(self.inside(y) && self.inside(bottom)) {
+                continue;
+            }
+            cropped.text_list.push(Text { y, ..txt.clone() });
+        }
+
+        for line in &schema.line_list {
+            if !(self.inside(line.p1y) && self.inside(line.p2y)) {
+                cropped.line_list.push(line.clone());
+                continue;
+            }
+            let (p1y, p2y) = (self.y(line.p1y as f64), self.y(line.p2y as f64));
+            if p1y.max(p2y) < self.top || p1y.min(p2y) > self.bottom {
+                continue;
+            }
+            cropped.line_list.push(data::Line {
+                p1y: self.clamp(p1y),
+                p2y: self.clamp(p2y),
+                ..line.clone()
+            });
+        }
+
+        cropped
+    }
+}
+
+fn minutes(time: NaiveTime) -> f64 {
+    time.num_seconds_from_midnight() as f64 / 60.0
+}
+
+// Covers the elapsed part of `rect` when it is the lesson going on at `now`
+fn progress_overlay(rect: &Box, lessons: &HashMap<&str, &LessonInfo>, options: &SvgOptions) -> Option<Rectangle> {
+    let (now, progress) = (options.now?, options.progress?);
+    let info = lesson_info(rect, lessons).filter(|_| rect.box_type() == BoxType::Lesson)?;
+    if info.weekday() != Some(now.weekday()) || info.date.is_some_and(|date| date != now.date()) {
+        return None;
+    }
+    let (start, end) = (info.start_time().ok()?, info.end_time().ok()?);
+    if !(start <= now.time() && now.time() < end) {
+        return None;
+    }
+
+    let elapsed = (minutes(now.time()) - minutes(start)) / (minutes(end) - minutes(start));
+    let height = rect.height as f64 * elapsed;
+    let mut overlay = Rectangle::new()
+        .set("x", rect.x)
+        .set("y", rect.y)
+        .set("width", rect.width)
+        .set("height", height)
+        .set("class", "progress")
+        .set("aria-hidden", "true");
+    if progress == Progress::Animated {
+        overlay = overlay.add(Animate::new()
+            .set("attributeName", "height")
+            .set("from", height)
+            .set("to", rect.height)
+            .set("dur", format!("{}s", (minutes(end) - minutes(now.time())) * 60.0))
+            .set("fill", "freeze"));
+    }
+    Some(overlay)
+}
+
+// GUIDs of the lessons going on at `now` and of the next one to start the same day
+fn around(schema: &Schema, now: NaiveDateTime) -> (Vec<&str>, Option<&str>) {
+    let today: Vec<(&LessonInfo, NaiveTime, NaiveTime)> = schema.lesson_info.iter()
+        .filter(|info| info.weekday() == Some(now.weekday()) && info.date.is_none_or(|date| date == now.date()))
+        .filter_map(|info| Some((info, info.start_time().ok()?, info.end_time().ok()?)))
+        .collect();
+
+    let time = now.time();
+    let current = today.iter()
+        .filter(|(_, start, end)| *start <= time && time < *end)
+        .map(|(info, _, _)| info.guid_id.as_str())
+        .collect();
+    let next = today.iter()
+        .filter(|(_, start, _)| *start > time)
+        .min_by_key(|(_, start, _)| *start)
+        .map(|(info, _, _)| info.guid_id.as_str());
+
+    (current, next)
+}
+
+// Right edge of the clock axis on the left of a render
+fn axis_width(schema: &Schema) -> i64 {
+    schema.boxes_of_type(BoxType::ClockAxisBox)
+        .map(|rect| rect.x + rect.width)
+        .max()
+        .unwrap_or(0)
+}
+
+// Clock axis boxes, their text and lines left of `skip_axis` are left out
+// clip path IDs are prefixed with `day`, since box IDs are only unique within a render
+fn draw_schema(schema_data: &Schema, day: usize, options: &SvgOptions, stylesheet: &mut Stylesheet, hatches: &[&str], skip_axis: Option<i64>) -> Result<Group, RenderError> {
+    let SvgOptions { theme, fonts, .. } = options;
+    let lessons: HashMap<&str, &LessonInfo> = schema_data.lesson_info.iter().map(|info| (info.guid_id.as_str(), info)).collect();
+    let subject = |rect: &Box| lesson_info(rect, &lessons).and_then(LessonInfo::subject).filter(|subject| !subject.trim().is_empty());
+    let (current, next) = match options.now {
+        Some(now) if options.highlight != Highlight::None => around(schema_data, now),
+        _ => (Vec::new(), None),
+    };
+    let highlight_class = |rect: &Box| {
+        let guids = rect.lesson_guids.as_deref().unwrap_or_default();
+        if guids.iter().any(|guid| current.contains(&guid.as_str())) {
+            "current"
+        }
+        else if options.highlight == Highlight::CurrentAndNext && guids.iter().any(|guid| next == Some(guid.as_str())) {
+            "next"
+        }
+        else {
+            "faded"
+        }
+    };
+
+    let mut rects = Group::new().set("class", "boxes");
+    let mut texts = Group::new().set("class", "texts");
+    let mut lines = Group::new().set("class", "lines");
+
+    let order = layers(schema_data, options, skip_axis)?;
+    for rect in order.boxes {
+        let hatch = if rect.box_type() == BoxType::Lesson {
+            hatches.iter().position(|color| color.eq_ignore_ascii_case(&rect.b_color))
+        }
+        else {
+            None
+        };
+        let mut classes = vec![kebab_case(&rect.type_field), stylesheet.class("r", rect_style(rect, theme, hatch)?)];
+        classes.extend(subject(rect).map(subject_class));
+        if options.now.is_some() && options.highlight != Highlight::None && rect.box_type() == BoxType::Lesson {
+            classes.push(highlight_class(rect).to_string());
+        }
 
-    for rect in &schema_data.box_list {
-        let style = rect_style(rect)?;
         let mut elem = Rectangle::new()
             .set("x", rect.x)
             .set("y", rect.y)
             .set("width", rect.width)
             .set("height", rect.height)
             .set("box-id", rect.id)
-            .set("shape-rendering", "crispEdges")
             .set("box-type", &rect.type_field[..])
-            .set("style", &style[..]);
-        if rect.type_field == "Lesson" {
+            .set("class", classes.join(" "));
+        if let Some(radius) = options.box_styles.get(&rect.box_type()).and_then(|style| style.corner_radius) {
             elem = elem
-                .set("focusable", true)
-                .set("tabindex", 0);
+                .set("rx", radius)
+                .set("ry", radius);
         }
-        doc = doc.add(elem)
-    }
+        if options.tooltips {
+            if let Some(info) = lesson_info(rect, &lessons) {
+                elem = elem.add(Title::new().add(TextNode::new(escape(&details(info).join("\n")))));
+            }
+        }
+        let label = lesson_info(rect, &lessons).map(|info| escape(&details(info).join(", ")));
 
-    for txt in &schema_data.text_list {
-        let style = text_style(txt)?;
-        let text_node = TextNode::new(&txt.text[..]);
-        let x_coord = match txt.type_field.as_str() {
-            "ClockAxisBox"|"HeadingDay" => {
-                let mut x = txt.x;
-                for rect in &schema_data.box_list {
-                    if rect.id == txt.parent_id {
-                        // This is not perfect because it does not take letter spacing into account, but it is good enough.
-                        x = rect.x + (rect.width/2) - (txt.text.len() as i64 * txt.fontsize as i64)/4;
-                        break
-                    }
+        let href = options.link.as_ref()
+            .filter(|_| rect.box_type() == BoxType::Lesson)
+            .and_then(|template| Some(template.replace("{guid}", rect.lesson_guids.as_ref()?.first()?.as_str())));
+        match href {
+            // The link takes the focus instead of the box
+            Some(href) => {
+                let mut link = Link::new().set("href", escape(&href));
+                if let Some(label) = label {
+                    link = link.set("aria-label", label);
+                }
+                rects = rects.add(link.add(elem))
+            },
+            None => {
+                if rect.type_field == "Lesson" && !options.static_image {
+                    elem = elem
+                        .set("focusable", true)
+                        .set("tabindex", 0);
                 }
+                if let Some(label) = label {
+                    elem = elem
+                        .set("role", "img")
+                        .set("aria-label", label);
+                }
+                rects = rects.add(elem)
+            },
+        }
+        if let Some(overlay) = progress_overlay(rect, &lessons, options) {
+            rects = rects.add(overlay);
+        }
+    }
+
+    let mut clipped = HashSet::new();
+    let mut clip_paths = Definitions::new();
+    for (txt, parent) in order.texts {
+        let mut classes = vec![kebab_case(&txt.type_field), stylesheet.class("t", text_style(txt, parent, theme, fonts)?)];
+        classes.extend(parent.and_then(subject).map(subject_class));
+        let PlacedText { x, y, anchor, text } = place_text(txt, parent, options);
+        let text_node = TextNode::new(escape(&text));
 
-                x
+        let mut elem = TextElement::new()
+            .set("x", x)
+            .set("y", y)
+            .set("text-id", txt.id)
+            .set("class", classes.join(" "))
+            .add(text_node);
+        if let Some(anchor) = anchor {
+            elem = elem.set("text-anchor", anchor);
+        }
+        if let Some(rect) = parent.filter(|_| options.overflow != Overflow::Visible) {
+            let id = format!("d{}-clip-{}", day, rect.id);
+            if clipped.insert(rect.id) {
+                clip_paths = clip_paths.add(ClipPath::new().set("id", id.as_str()).add(
+                    Rectangle::new()
+                        .set("x", rect.x)
+                        .set("y", rect.y)
+                        .set("width", rect.width)
+                        .set("height", rect.height)
+                ));
             }
-            _ => txt.x
-        };
-        if txt.italic || txt.bold {
-            // These do not seem to be used at all.
-            eprintln!("Unimplemented: italic|bold");
-        }
-
-        doc = doc.add(
-            TextElement::new()
-                .set("x", x_coord)
-                .set("y", txt.y + txt.fontsize as i64)
-                .set("text-id", txt.id)
-                .set("style", &style[..])
-                .add(text_node)
-        )
+            elem = elem.set("clip-path", format!("url(#{})", id));
+        }
+        // Already read out as part of the lesson's label
+        if parent.is_some_and(|rect| lesson_info(rect, &lessons).is_some()) {
+            elem = elem.set("aria-hidden", "true");
+        }
+        texts = texts.add(elem)
     }
 
-    for line in &schema_data.line_list {
-        doc = doc.add(
+    for line in order.lines {
+        let class = stylesheet.class("l", format!("stroke: {};", line_color(line, theme)?));
+        lines = lines.add(
             Line::new()
                 .set("x1", line.p1x)
                 .set("y1", line.p1y)
                 .set("x2", line.p2x)
                 .set("y2", line.p2y)
-                .set("stroke", &line.color[..])
+                .set("class", format!("{} {}", kebab_case(&line.type_field), class))
+                .set("aria-hidden", "true")
         )
     }
 
-    Ok(doc)
+    let mut content = Group::new().add(rects);
+    content = if options.api_order {content.add(texts).add(lines)} else {content.add(lines).add(texts)};
+    if !clipped.is_empty() {
+        content = content.add(clip_paths);
+    }
+
+    Ok(content)
+}
+
+// What gets drawn, in drawing order. Texts come with their parent box.
+pub(crate) struct Layers<'a> {
+    pub boxes: Vec<&'a Box>,
+    pub lines: Vec<&'a data::Line>,
+    pub texts: Vec<(&'a Text, Option<&'a Box>)>,
+}
+
+// Leaves out the clock axis when `skip_axis` is set, the days after the first of a week
+// render share the first one's
+pub(crate) fn layers<'a>(schema_data: &'a Schema, options: &SvgOptions, skip_axis: Option<i64>) -> Result<Layers<'a>, RenderError> {
+    let boxes: HashMap<i64, &Box> = schema_data.box_list.iter().map(|rect| (rect.id, rect)).collect();
+    let on_axis = |rect: &Box| skip_axis.is_some() && rect.box_type() == BoxType::ClockAxisBox;
+
+    let mut texts = Vec::new();
+    for txt in &schema_data.text_list {
+        // Top level texts have parent 0
+        let parent = match boxes.get(&txt.parent_id) {
+            Some(rect) => Some(*rect),
+            None if txt.parent_id == 0 => None,
+            None => return Err(RenderError::MissingParent { id: txt.id, parent_id: txt.parent_id }),
+        };
+        let replaced = matches!(options.footer, Some(FooterText::Replace(_))) && parent.is_some_and(|rect| rect.box_type() == BoxType::Footer);
+        if !(replaced || parent.is_some_and(on_axis) || (skip_axis.is_some() && txt.type_field == "ClockAxisBox")) {
+            texts.push((txt, parent));
+        }
+    }
+    let mut layers = Layers {
+        boxes: schema_data.box_list.iter().filter(|rect| !on_axis(rect)).collect(),
+        lines: schema_data.line_list.iter().filter(|line| skip_axis.is_none_or(|axis| line.p1x.max(line.p2x) > axis)).collect(),
+        texts,
+    };
+
+    // Children are drawn over their parents and lessons over everything else
+    if !options.api_order {
+        let layer = |rect: &Box| (rect.box_type() == BoxType::Lesson, depth(rect, &boxes));
+        layers.boxes.sort_by_key(|rect| layer(rect));
+        layers.texts.sort_by_key(|(_, parent)| parent.map(layer));
+    }
+    Ok(layers)
+}
+
+pub(crate) struct PlacedText {
+    pub x: i64,
+    // Of the baseline
+    pub y: i64,
+    pub anchor: Option<&'static str>,
+    // Shortened to fit its box with `Overflow::Ellipsis`
+    pub text: String,
+}
+
+pub(crate) fn place_text(txt: &Text, parent: Option<&Box>, options: &SvgOptions) -> PlacedText {
+    let fontsize = options.fonts.size(txt.fontsize);
+    // Headings are centered on their box by the renderer, which knows the actual glyph widths
+    let (x, anchor) = match (txt.type_field.as_str(), parent) {
+        ("ClockAxisBox"|"HeadingDay", Some(rect)) => (rect.x + rect.width/2, Some("middle")),
+        _ => (txt.x, None),
+    };
+    let text = match parent {
+        Some(rect) if options.overflow == Overflow::Ellipsis => {
+            let available = match anchor {
+                Some(_) => rect.width,
+                None => rect.x + rect.width - x,
+            };
+            // Bold glyphs run about a tenth wider
+            let width_scale = if txt.bold {1.1} else {1.0};
+            ellipsize(&txt.text, available as f64 - TEXT_PADDING, fontsize * width_scale)
+        },
+        _ => txt.text.clone(),
+    };
+    PlacedText { x, y: txt.y + fontsize as i64, anchor, text }
+}
+
+// Number of ancestors of a box, stopping at parent cycles
+fn depth(rect: &Box, boxes: &HashMap<i64, &Box>) -> usize {
+    let mut depth = 0;
+    let mut parent = rect.parent_id;
+    while let Some(rect) = parent.and_then(|id| boxes.get(&id)) {
+        if depth >= boxes.len() {
+            break;
+        }
+        depth += 1;
+        parent = rect.parent_id;
+    }
+    depth
+}
+
+// Space kept between text and the right edge of its box
+const TEXT_PADDING: f64 = 2.0;
+
+// Glyphs of common sans fonts average a bit over half the font size in width
+fn estimated_width(text: &str, fontsize: f64) -> f64 {
+    text.chars().count() as f64 * fontsize * 0.55
+}
+
+fn ellipsize(text: &str, available: f64, fontsize: f64) -> String {
+    if estimated_width(text, fontsize) <= available {
+        return text.to_string();
+    }
+
+    let mut cut: String = text.chars()
+        .take_while({
+            let mut width = estimated_width("…", fontsize);
+            move |_| {
+                width += estimated_width("x", fontsize);
+                width <= available
+            }
+        })
+        .collect();
+    cut.truncate(cut.trim_end().len());
+    cut.push('…');
+    cut
+}
+
+// Parallel lines at one of eight angles, getting wider apart once the angles run out
+fn hatch_pattern(index: usize) -> Pattern {
+    const ANGLES: [f64; 8] = [45.0, 135.0, 0.0, 90.0, 22.5, 112.5, 67.5, 157.5];
+    let spacing = 6 + 4 * (index / ANGLES.len());
+
+    Pattern::new()
+        .set("id", format!("hatch-{}", index))
+        .set("patternUnits", "userSpaceOnUse")
+        .set("width", spacing)
+        .set("height", spacing)
+        .set("patternTransform", format!("rotate({})", ANGLES[index % ANGLES.len()]))
+        .add(Rectangle::new()
+            .set("width", spacing)
+            .set("height", spacing)
+            .set("fill", Rgb::WHITE.to_string()))
+        .add(Line::new()
+            .set("x1", spacing as f64 / 2.0)
+            .set("y1", 0)
+            .set("x2", spacing as f64 / 2.0)
+            .set("y2", spacing)
+            .set("stroke", Rgb::BLACK.to_string())
+            .set("stroke-width", 1))
 }
@@ -0,0 +1,100 @@
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::model::{Lesson, WeekSchedule};
+
+// Bumped whenever the simplified model changes incompatibly
+pub const SIMPLE_SCHEDULE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+pub struct SimpleSchedule {
+    pub version: u32,
+    pub year: i32,
+    pub week: u32,
+    pub days: Vec<SimpleDay>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+pub struct SimpleDay {
+    // 1 = Monday, 7 = Sunday
+    pub weekday: u32,
+    pub date: Option<NaiveDate>,
+    pub lessons: Vec<SimpleLesson>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+pub struct SimpleLesson {
+    pub id: String,
+    pub subject: Option<String>,
+    pub teacher: Option<String>,
+    pub room: Option<String>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub block: Option<String>,
+    pub color: Option<String>,
+}
+
+impl SimpleLesson {
+    pub fn from_lesson(lesson: &Lesson) -> Self {
+        let non_empty = |text: &str| Some(text.to_string()).filter(|text| !text.is_empty());
+
+        SimpleLesson {
+            id: lesson.id.to_string(),
+            subject: non_empty(&lesson.subject),
+            teacher: non_empty(&lesson.teacher),
+            room: non_empty(&lesson.room),
+            start: lesson.start,
+            end: lesson.end,
+            block: lesson.block.clone(),
+            color: lesson.color.clone(),
+        }
+    }
+}
+
+impl From<&WeekSchedule> for SimpleSchedule {
+    fn from(week: &WeekSchedule) -> Self {
+        SimpleSchedule {
+            version: SIMPLE_SCHEDULE_VERSION,
+            year: week.year,
+            week: week.week,
+            days: week.days.iter()
+                .filter(|day| !day.is_empty())
+                .map(|day| SimpleDay {
+                    weekday: day.weekday.number_from_monday(),
+                    date: day.date,
+                    lessons: day.lessons.iter().map(SimpleLesson::from_lesson).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+pub fn to_simple_json(week: &WeekSchedule) -> Result<String, serde_json::Error> {
+    serde_json::to_string(&SimpleSchedule::from(week))
+}
+
+// Field names are kept so generic MessagePack decoders can read the payload
+#[cfg(feature = "msgpack")]
+pub fn to_simple_msgpack(week: &WeekSchedule) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    rmp_serde::to_vec_named(&SimpleSchedule::from(week))
+}
+
+#[cfg(feature = "msgpack")]
+pub fn from_simple_msgpack(bytes: &[u8]) -> Result<SimpleSchedule, rmp_serde::decode::Error> {
+    rmp_serde::from_slice(bytes)
+}
+
+#[cfg(feature = "cbor")]
+pub fn to_simple_cbor(week: &WeekSchedule) -> Result<Vec<u8>, ciborium::ser::Error<std::io::Error>> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&SimpleSchedule::from(week), &mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(feature = "cbor")]
+pub fn from_simple_cbor(bytes: &[u8]) -> Result<SimpleSchedule, ciborium::de::Error<std::io::Error>> {
+    ciborium::from_reader(bytes)
+}
@@ -0,0 +1,58 @@
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde_json::Value;
+
+use std::borrow::Cow;
+
+use crate::data::{Class, LessonInfo, School, SharedStr};
+use crate::json::SimpleSchedule;
+
+// JSON Schemas (draft 2020-12) of the types as this crate serializes them, for generating
+// types in other languages. Derived with schemars from the types themselves, so they follow
+// every change to them.
+
+// Serialized as a plain string
+impl JsonSchema for SharedStr {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "SharedStr".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        String::json_schema(generator)
+    }
+}
+
+fn schema<T: JsonSchema>() -> Value {
+    schemars::schema_for!(T).to_value()
+}
+
+// `json::SimpleSchedule`, the output of `json::to_simple_json`
+pub fn simple_schedule() -> Value {
+    schema::<SimpleSchedule>()
+}
+
+// `data::LessonInfo`, e.g. in the output of `get_lesson_info`
+pub fn lesson_info() -> Value {
+    schema::<LessonInfo>()
+}
+
+pub fn school() -> Value {
+    schema::<School>()
+}
+
+pub fn class() -> Value {
+    schema::<Class>()
+}
+
+// All of the above by title, e.g. to write them to files
+pub fn all() -> Vec<(&'static str, Value)> {
+    vec![
+        ("SimpleSchedule", simple_schedule()),
+        ("LessonInfo", lesson_info()),
+        ("School", school()),
+        ("Class", class()),
+    ]
+}
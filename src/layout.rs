@@ -0,0 +1,212 @@
+use chrono::{NaiveTime, Timelike, Weekday};
+
+use crate::{Dimensions, data::*, image::Locale};
+
+// Lays out lessons the way the API's render endpoint would, at any size. The result can be
+// drawn with `image::generate_svg` like a schema from the API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutOptions {
+    // Shown time span, by default the lessons' own rounded out to whole hours
+    pub start: Option<NaiveTime>,
+    pub end: Option<NaiveTime>,
+    // One column per weekday, by default the weekdays with lessons
+    pub weekdays: Option<Vec<Weekday>>,
+    pub locale: Locale,
+    pub axis_width: i64,
+    pub heading_height: i64,
+    pub fontsize: f64,
+    // For lessons whose block has no color
+    pub lesson_color: String,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        LayoutOptions {
+            start: None,
+            end: None,
+            weekdays: None,
+            locale: Locale::default(),
+            axis_width: 50,
+            heading_height: 24,
+            fontsize: 11.0,
+            lesson_color: "#E0E0E0".to_string(),
+        }
+    }
+}
+
+const WORKWEEK: [Weekday; 5] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+const LINE_COLOR: &str = "#CCCCCC";
+const TEXT_PADDING: i64 = 3;
+
+struct Placed<'a> {
+    info: &'a LessonInfo,
+    start: NaiveTime,
+    end: NaiveTime,
+    lane: usize,
+    lanes: usize,
+}
+
+// Minutes since midnight
+fn minutes(time: NaiveTime) -> i64 {
+    (time.hour() * 60 + time.minute()) as i64
+}
+
+pub fn layout(lessons: &[LessonInfo], dimensions: Dimensions, options: &LayoutOptions) -> Schema {
+    let timed: Vec<(&LessonInfo, Weekday, NaiveTime, NaiveTime)> = lessons.iter()
+        .filter_map(|info| Some((info, info.weekday()?, info.start_time().ok()?, info.end_time().ok()?)))
+        .filter(|(_, _, start, end)| start < end)
+        .collect();
+
+    let weekdays = match &options.weekdays {
+        Some(weekdays) => weekdays.clone(),
+        None => {
+            let mut weekdays: Vec<Weekday> = timed.iter().map(|(_, weekday, ..)| *weekday).collect();
+            weekdays.sort_by_key(|weekday| weekday.num_days_from_monday());
+            weekdays.dedup();
+            if weekdays.is_empty() {WORKWEEK.to_vec()} else {weekdays}
+        },
+    };
+
+    let earliest = timed.iter().map(|(_, _, start, _)| minutes(*start)).min().map_or(8 * 60, |start| start / 60 * 60);
+    let latest = timed.iter().map(|(_, _, _, end)| minutes(*end)).max().map_or(16 * 60, |end| (end + 59) / 60 * 60);
+    let start = options.start.map_or(earliest, minutes);
+    let end = options.end.map_or(latest, minutes).max(start + 60);
+
+    let (width, height) = (dimensions.width as i64, dimensions.height as i64);
+    let column_width = (width - options.axis_width).max(1) as f64 / weekdays.len().max(1) as f64;
+    let pixels_per_minute = (height - options.heading_height).max(1) as f64 / (end - start) as f64;
+    let y = |minute: i64| options.heading_height + ((minute - start) as f64 * pixels_per_minute).round() as i64;
+    let column_x = |i: usize| options.axis_width + (column_width * i as f64).round() as i64;
+
+    let mut schema = Schema::default();
+    let mut next_id = 0;
+    let mut id = || {
+        next_id += 1;
+        next_id
+    };
+    let add_box = |schema: &mut Schema, id: i64, (x, y, width, height): (i64, i64, i64, i64), color: &str, type_field: BoxType| {
+        schema.box_list.push(Box {
+            x,
+            y,
+            width,
+            height,
+            b_color: color.into(),
+            // Only lessons get a visible border
+            f_color: if type_field == BoxType::Lesson {"#000000".into()} else {color.into()},
+            id,
+            parent_id: None,
+            type_field: type_field.as_str().into(),
+            lesson_guids: None,
+        });
+    };
+    let text = |id: i64, parent_id: i64, (x, y): (i64, i64), text: String, bold: bool, type_field: BoxType| Text {
+        x,
+        y,
+        f_color: "#000000".into(),
+        fontsize: options.fontsize,
+        text,
+        bold,
+        italic: false,
+        id,
+        parent_id,
+        type_field: type_field.as_str().into(),
+    };
+
+    let background = id();
+    add_box(&mut schema, background, (0, 0, width, height), "#FFFFFF", BoxType::Other("Background".to_string()));
+
+    for hour in (start + 59) / 60..=end / 60 {
+        let top = y(hour * 60);
+        let (axis_box, label) = (id(), id());
+        let label_height = options.fontsize.ceil() as i64 + 2;
+        // Centered on the hour line, unless that would put the last hour's label outside the image
+        let label_y = (top - label_height / 2).min(height - label_height);
+        add_box(&mut schema, axis_box, (0, label_y, options.axis_width, label_height), "#FFFFFF", BoxType::ClockAxisBox);
+        schema.text_list.push(text(label, axis_box, (0, label_y), format!("{:02}:00", hour), false, BoxType::ClockAxisBox));
+        schema.line_list.push(Line {
+            p1x: options.axis_width,
+            p1y: top,
+            p2x: width,
+            p2y: top,
+            color: LINE_COLOR.into(),
+            id: id(),
+            parent_id: background,
+            type_field: "ClockAxisLine".into(),
+        });
+    }
+
+    for (i, weekday) in weekdays.iter().enumerate() {
+        let (x, right) = (column_x(i), column_x(i + 1));
+        let date = timed.iter().find(|(info, day, ..)| day == weekday && info.date.is_some()).and_then(|(info, ..)| info.date);
+        let (heading, label) = (id(), id());
+        add_box(&mut schema, heading, (x, 0, right - x, options.heading_height), "#FFFFFF", BoxType::HeadingDay);
+        let label_y = (options.heading_height - options.fontsize.ceil() as i64) / 2;
+        schema.text_list.push(text(label, heading, (x, label_y), options.locale.day(*weekday, date), true, BoxType::HeadingDay));
+
+        for placed in lanes(timed.iter().filter(|(_, day, ..)| day == weekday).map(|(info, _, start, end)| (*info, *start, *end))) {
+            let lane_width = (right - x) as f64 / placed.lanes as f64;
+            let left = x + (lane_width * placed.lane as f64).round() as i64;
+            let lane_right = x + (lane_width * (placed.lane + 1) as f64).round() as i64;
+            let (top, bottom) = (y(minutes(placed.start)), y(minutes(placed.end)));
+            let color = Some(placed.info.block.b_color.as_str()).filter(|color| !color.is_empty()).unwrap_or(&options.lesson_color);
+
+            let lesson = id();
+            add_box(&mut schema, lesson, (left, top, lane_right - left, bottom - top), color, BoxType::Lesson);
+            let rect = schema.box_list.last_mut().unwrap();
+            rect.lesson_guids = Some(vec![placed.info.guid_id.clone()]);
+            let rect = rect.clone();
+
+            let line_height = options.fontsize.ceil() as i64 + 2;
+            for (n, line) in placed.info.texts.iter().enumerate() {
+                let line_y = top + TEXT_PADDING + n as i64 * line_height;
+                if line.is_empty() || line_y + line_height > bottom {
+                    continue;
+                }
+                schema.text_list.push(text(id(), lesson, (left + TEXT_PADDING, line_y), line.clone(), n == 0, BoxType::Lesson));
+            }
+
+            schema.lesson_info.push(LessonInfo {
+                block: rect,
+                ..placed.info.clone()
+            });
+        }
+    }
+
+    schema
+}
+
+// Puts overlapping lessons side by side, each group of overlapping lessons sharing the
+// column width evenly
+fn lanes<'a>(lessons: impl Iterator<Item = (&'a LessonInfo, NaiveTime, NaiveTime)>) -> Vec<Placed<'a>> {
+    let mut lessons: Vec<_> = lessons.collect();
+    lessons.sort_by_key(|(_, start, end)| (*start, *end));
+
+    let mut placed: Vec<Placed> = Vec::new();
+    let mut group_start = 0;
+    let mut group_end = NaiveTime::MIN;
+    let mut lane_ends: Vec<NaiveTime> = Vec::new();
+    for (info, start, end) in lessons {
+        if start >= group_end {
+            let lanes = lane_ends.len();
+            placed[group_start..].iter_mut().for_each(|lesson| lesson.lanes = lanes);
+            group_start = placed.len();
+            lane_ends.clear();
+        }
+        let lane = match lane_ends.iter().position(|lane_end| *lane_end <= start) {
+            Some(lane) => {
+                lane_ends[lane] = end;
+                lane
+            },
+            None => {
+                lane_ends.push(end);
+                lane_ends.len() - 1
+            },
+        };
+        group_end = group_end.max(end);
+        placed.push(Placed { info, start, end, lane, lanes: 1 });
+    }
+    let lanes = lane_ends.len();
+    placed[group_start..].iter_mut().for_each(|lesson| lesson.lanes = lanes);
+
+    placed
+}
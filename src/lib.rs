@@ -1,11 +1,81 @@
+#[macro_use]
+mod logging;
+
+pub mod analysis;
+pub mod anonymize;
+#[cfg(feature = "caldav")]
+pub mod caldav;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod csv;
 pub mod data;
+pub mod diff;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod gcal;
+#[cfg(feature = "net")]
+pub mod health;
+pub mod homeassistant;
+pub mod html;
+#[cfg(feature = "ics")]
+pub mod ics;
 #[cfg(feature = "svg")]
 pub mod image;
+pub mod json;
+#[cfg(feature = "jsonschema")]
+pub mod jsonschema;
+#[cfg(feature = "svg")]
+pub mod layout;
+pub mod markdown;
+pub mod metrics;
+pub mod model;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "notify")]
+pub mod notify;
+#[cfg(feature = "net")]
+pub mod ops;
+pub mod org;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+#[cfg(feature = "png")]
+pub mod png;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod query;
+pub mod remind;
+#[cfg(feature = "svg")]
+pub mod renderer;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "net")]
+mod search;
+pub mod statusbar;
+pub mod subjects;
+pub mod text;
+#[cfg(feature = "time")]
+pub mod timecompat;
+pub mod timetable;
+pub mod timezone;
+#[cfg(feature = "vcr")]
+pub mod vcr;
+#[cfg(feature = "net")]
+pub mod watch;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
 
-use std::{convert::TryInto, str::FromStr};
+use std::str::FromStr;
 use std::fmt;
+#[cfg(feature = "net")]
+use std::convert::TryInto;
+#[cfg(feature = "net")]
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use chrono:: {Local, NaiveTime, Datelike, Utc};
+use chrono::{NaiveTime, Weekday};
+#[cfg(feature = "net")]
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Datelike, TimeZone, Utc};
 
 macro_rules! impl_from {
     ($e:ty, $enum:tt) => {
@@ -20,50 +90,299 @@ macro_rules! impl_from {
 #[derive(Debug)]
 pub struct EmptyError {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotFoundKind {
+    Domain,
+    School,
+    Class,
+    Teacher,
+    Student,
+    Room,
+}
+
 #[derive(Debug)]
 pub enum RequestError {
+    #[cfg(feature = "net")]
     Reqwest(reqwest::Error),
     Serde(serde_json::Error),
     Schema(data::SchemaError),
+    #[cfg(feature = "net")]
     BaseDirectories(xdg::BaseDirectoriesError),
     IO(std::io::Error),
     Utf8(std::str::Utf8Error),
+    #[cfg(feature = "net")]
     Cacache(cacache::Error),
     ParseInt(std::num::ParseIntError),
     Empty(EmptyError),
+    NotFound {
+        kind: NotFoundKind,
+        query: String,
+    },
+    // The unit does not publish timetables of this kind
+    NotPublic(SelectionType),
+    // The render key response did not look as expected. Holds what went wrong and the start
+    // of the response.
+    KeyFormat(String),
+    // A lesson the API sent could not be parsed
+    Lesson(model::LessonWarning),
+    // Replaying and no fixture was recorded for the request, see `vcr`
+    #[cfg(feature = "vcr")]
+    MissingFixture(std::path::PathBuf),
+    // Where in `cache_request` the error in `source` happened
+    Context {
+        endpoint: Endpoint,
+        cache_key: String,
+        stage: RequestStage,
+        source: Box<RequestError>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    RenderTimetable,
+    Selection,
+    ViewerUnits,
+    RenderKey,
+    EncryptSignature,
+}
+
+impl Endpoint {
+    pub fn path(self) -> &'static str {
+        match self {
+            Endpoint::RenderTimetable => "/render/timetable",
+            Endpoint::Selection => "/get/timetable/selection",
+            Endpoint::ViewerUnits => "/services/skola24/get/timetable/viewer/units",
+            Endpoint::RenderKey => "/get/timetable/render/key",
+            Endpoint::EncryptSignature => "/encrypt/signature",
+        }
+    }
+
+    pub fn url(self) -> String {
+        "https://web.skola24.se/api".to_string() + self.path()
+    }
+
+    pub fn is_post(self) -> bool {
+        matches!(self, Endpoint::ViewerUnits | Endpoint::EncryptSignature)
+    }
+
+    // The render key is only valid for a while and signatures are looked up once, so only the
+    // timetable data is worth caching
+    pub fn is_cacheable(self) -> bool {
+        matches!(self, Endpoint::RenderTimetable | Endpoint::Selection | Endpoint::ViewerUnits)
+    }
+
+    // Only rendering is authorized by the key, the selection and unit lists are public
+    pub fn needs_render_key(self) -> bool {
+        matches!(self, Endpoint::RenderTimetable)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestStage {
+    CacheRead,
+    Network,
+    CacheWrite,
+}
+
+impl RequestError {
+    // The error without the context `cache_request` adds
+    pub fn root(&self) -> &RequestError {
+        match self {
+            RequestError::Context { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    // Which lookup failed, if this is a `NotFound` error
+    pub fn not_found_kind(&self) -> Option<NotFoundKind> {
+        match self.root() {
+            RequestError::NotFound { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "net")]
+    fn not_found(kind: NotFoundKind, query: &str) -> Self {
+        RequestError::NotFound {
+            kind,
+            query: query.to_string(),
+        }
+    }
 }
 
+#[cfg(feature = "net")]
 impl_from!(reqwest::Error, Reqwest);
 impl_from!(serde_json::Error, Serde);
 impl_from!(data::SchemaError, Schema);
+#[cfg(feature = "net")]
 impl_from!(xdg::BaseDirectoriesError, BaseDirectories);
 impl_from!(std::io::Error, IO);
 impl_from!(std::str::Utf8Error, Utf8);
+#[cfg(feature = "net")]
 impl_from!(cacache::Error, Cacache);
 impl_from!(std::num::ParseIntError, ParseInt);
 impl_from!(EmptyError, Empty);
+impl_from!(model::LessonWarning, Lesson);
+
+#[cfg(feature = "net")]
+const RENDER_KEY_CACHE_KEY: &str = "render-key";
+
+// Retries once, then falls back to the last key that was fetched successfully
+#[cfg(feature = "net")]
+pub async fn get_key() -> Result<String, RequestError> {
+    get_key_in(true).await
+}
+
+// Without caching the key is neither stored nor read back, and the cache directory is never resolved
+#[cfg(feature = "net")]
+async fn get_key_in(should_cache: bool) -> Result<String, RequestError> {
+    let result = match fetch_key().await {
+        Ok(key) => Ok(key),
+        Err(e) => {
+            debug!("fetching the render key failed, retrying: {:?}", e);
+            fetch_key().await
+        },
+    };
+    if !should_cache {
+        return result;
+    }
+
+    match result {
+        Ok(key) => {
+            // Only a fallback, failing to store it should not fail the request
+            let written = match cache_dir() {
+                Ok(cache) => cacache::write(&cache, RENDER_KEY_CACHE_KEY, key.as_bytes()).await.map(drop).map_err(RequestError::from),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                warn!("could not cache the render key: {:?}", e);
+            }
+            Ok(key)
+        },
+        Err(err) => {
+            let cache = match cache_dir() {
+                Ok(cache) => cache,
+                Err(_) => return Err(err),
+            };
+            match cacache::read(&cache, RENDER_KEY_CACHE_KEY).await {
+                Ok(key) => {
+                    warn!("fetching the render key failed, using the cached one: {:?}", err);
+                    Ok(String::from_utf8(key).map_err(|e| e.utf8_error())?)
+                },
+                Err(_) => Err(err),
+            }
+        },
+    }
+}
+
+#[cfg(feature = "net")]
+async fn fetch_key() -> Result<String, RequestError> {
+    let text = send::<()>(Endpoint::RenderKey, None).await?;
+    let key_format = |problem: String| RequestError::KeyFormat(format!("{}: {}", problem, text.chars().take(200).collect::<String>()));
+    let key_res: data::Response<data::RenderKey> = serde_json::from_str(&text).map_err(|e| key_format(e.to_string()))?;
+    if let Some(error) = key_res.error {
+        return Err(key_format(error.to_string()));
+    }
+    if key_res.data.key.is_empty() {
+        return Err(key_format("empty key".to_string()));
+    }
+
+    Ok(key_res.data.key)
+}
+
+#[cfg(feature = "net")]
+pub async fn get_signature(id: &str) -> Result<String, RequestError> {
+    let text = send(Endpoint::EncryptSignature, Some(&data::SignatureRequest { signature: id })).await?;
+    let signature_res: serde_json::Value = serde_json::from_str(&text)?;
+    let signature = signature_res["data"]["signature"].as_str().ok_or(EmptyError{})?.to_string();
+
+    Ok(signature)
+}
+
+#[cfg(feature = "net")]
+async fn send<B: serde::Serialize>(endpoint: Endpoint, body: Option<&B>) -> Result<String, RequestError> {
+    #[cfg(feature = "vcr")]
+    let recorded_body = body.map(serde_json::to_value).transpose()?;
+    #[cfg(feature = "vcr")]
+    if let Some(text) = vcr::replay(endpoint, recorded_body.as_ref())? {
+        return Ok(text);
+    }
 
-pub async fn get_key() -> Result<String, RequestError>{
     let client = reqwest::Client::new();
-    let res = client
-        .get("https://web.skola24.se/api/get/timetable/render/key")
-        .header("X-Scope", "8a22163c-8662-4535-9050-bc5e1923df48")
-        .send()
-        .await?
-        .error_for_status()?;
+    let mut request = if endpoint.is_post() {
+        client.post(endpoint.url())
+    }
+    else {
+        client.get(endpoint.url())
+    };
+    request = request.header("X-Scope", "8a22163c-8662-4535-9050-bc5e1923df48")
+        // The render responses are large and compress well
+        .header(reqwest::header::ACCEPT_ENCODING, "gzip, deflate");
+    if let Some(body) = body {
+        request = request.json(body);
+    }
+
+    let started = std::time::Instant::now();
+    let response = request.send().await?.error_for_status()?;
+    let status = response.status().as_u16();
+    let content_encoding = response.headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let body = response.bytes().await?;
+    let text = decode_body(content_encoding.as_deref(), &body)?;
 
-    let key_res: serde_json::Value = serde_json::from_str(&res.text().await?)?;
-    let key = key_res["data"]["key"].as_str().unwrap().to_string();
+    metrics::record(&metrics::TransferStats {
+        endpoint,
+        status,
+        content_encoding,
+        bytes_on_wire: body.len(),
+        bytes_decoded: text.len(),
+        duration: started.elapsed(),
+    });
+    #[cfg(feature = "vcr")]
+    vcr::record(endpoint, recorded_body.as_ref(), &text)?;
+
+    Ok(text)
+}
+
+// reqwest is built without its decompression features, so bodies come as sent
+#[cfg(feature = "net")]
+fn decode_body(content_encoding: Option<&str>, body: &[u8]) -> Result<String, RequestError> {
+    use std::io::Read;
+
+    let mut decoded = Vec::new();
+    let body = match content_encoding {
+        None | Some("identity") => body,
+        Some("gzip") | Some("x-gzip") => {
+            flate2::read::GzDecoder::new(body).read_to_end(&mut decoded)?;
+            &decoded
+        },
+        Some("deflate") => {
+            flate2::read::ZlibDecoder::new(body).read_to_end(&mut decoded)?;
+            &decoded
+        },
+        Some(other) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("unsupported content encoding {}", other)).into()),
+    };
 
-    Ok(key)
+    Ok(String::from_utf8_lossy(body).into_owned())
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Dimensions {
     pub width: u32,
     pub height: u32,
 }
 
+impl Dimensions {
+    // The geometry is laid out for the requested size, a tiny one keeps it from growing the
+    // response when only the lessons are used, see `fetch_lessons_only`
+    pub const MINIMAL: Dimensions = Dimensions {
+        width: 1,
+        height: 1,
+    };
+}
+
 impl Default for Dimensions {
     #[inline]
     fn default() -> Self {
@@ -104,226 +423,1028 @@ impl FromStr for Dimensions {
     }
 }
 
-pub async fn domain_exists(domain: &str, should_cache: bool) -> Result<bool, RequestError> {
-    let result = get_schools(domain, should_cache).await;
-    match result {
-        Ok(_) => Ok(true),
-        Err(RequestError::Schema(e)) => {
-            if let data::SchemaError::API(e) = e {
-                if e.validation_errors.len() == 1 && e.validation_errors[0].id == 1 {
-                    Ok(false)
-                } else {
-                    Err(RequestError::Schema(data::SchemaError::API(e)))
-                }
-            }
-            else {
-                Err(RequestError::Schema(e))
-            }
-        },
-        Err(e) => Err(e),
+#[derive(Default, Clone, Debug)]
+pub struct SchemaRequestOptions {
+    // Ask the API for monochrome colors in the render data
+    pub black_and_white: bool,
+    // Used by the official viewer for personal timetables, see `Selection::from_signature`
+    pub private_selection_mode: bool,
+    // Required by some hosted skola24 instances
+    pub customer_key: Option<String>,
+    // Let the API render the week header and `period_text` into the geometry
+    pub show_header: bool,
+    pub period_text: String,
+}
+
+impl SchemaRequestOptions {
+    #[cfg(feature = "net")]
+    fn cache_key(&self) -> String {
+        let mut parts = Vec::new();
+        if self.black_and_white {
+            parts.push("bw".to_string());
+        }
+        if self.private_selection_mode {
+            parts.push("private".to_string());
+        }
+        if let Some(customer_key) = &self.customer_key {
+            parts.push(format!("customer={}", customer_key));
+        }
+        if self.show_header {
+            parts.push(format!("header={}", self.period_text));
+        }
+        parts.join(",")
     }
 }
 
-pub async fn school_exists(domain: &str, school: &str, should_cache: bool) -> Result<bool, RequestError> {
-    let schools = get_schools(domain, should_cache).await?;
-    for s in schools {
-        if school == s.unit_id {
-            return Ok(true)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SelectionType {
+    #[default]
+    Class = 0,
+    Room = 3,
+    Signature = 4,
+    Student = 5,
+    Teacher = 7,
+}
+
+// Who or what a timetable is for, holding the GUID the API expects (e.g.
+// `data::Class::group_guid`), not the name. Passing a name results in an empty schedule.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SelectionTarget {
+    Class(String),
+    Room(String),
+    // The encrypted value returned by `get_signature`
+    Signature(String),
+    Student(String),
+    Teacher(String),
+}
+
+impl Default for SelectionTarget {
+    fn default() -> Self {
+        SelectionTarget::Class(String::new())
+    }
+}
+
+impl SelectionTarget {
+    pub fn new(selection_type: SelectionType, guid: &str) -> Self {
+        let guid = guid.to_string();
+        match selection_type {
+            SelectionType::Class => SelectionTarget::Class(guid),
+            SelectionType::Room => SelectionTarget::Room(guid),
+            SelectionType::Signature => SelectionTarget::Signature(guid),
+            SelectionType::Student => SelectionTarget::Student(guid),
+            SelectionType::Teacher => SelectionTarget::Teacher(guid),
+        }
+    }
+
+    pub fn guid(&self) -> &str {
+        match self {
+            SelectionTarget::Class(guid)
+            | SelectionTarget::Room(guid)
+            | SelectionTarget::Signature(guid)
+            | SelectionTarget::Student(guid)
+            | SelectionTarget::Teacher(guid) => guid,
+        }
+    }
+
+    pub fn selection_type(&self) -> SelectionType {
+        match self {
+            SelectionTarget::Class(_) => SelectionType::Class,
+            SelectionTarget::Room(_) => SelectionType::Room,
+            SelectionTarget::Signature(_) => SelectionType::Signature,
+            SelectionTarget::Student(_) => SelectionType::Student,
+            SelectionTarget::Teacher(_) => SelectionType::Teacher,
         }
     }
-    Ok(false)
 }
 
-pub async fn class_exists(domain: &str, school: &str, class: &str, should_cache: bool) -> Result<bool, RequestError> {
-    let classes = get_classes(domain, &get_school_guid(domain, school, should_cache).await?, should_cache).await?;
-    for c in classes {
-        if class == c.group_name {
-            return Ok(true)
+/// What to fetch a timetable for.
+///
+/// Use the async constructors to look a class, teacher, room or student up by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Selection {
+    pub host: String,
+    pub unit_guid: String,
+    pub target: SelectionTarget,
+}
+
+impl Selection {
+    pub fn new(host: &str, unit_guid: &str, target: SelectionTarget) -> Self {
+        Selection {
+            host: host.to_string(),
+            unit_guid: unit_guid.to_string(),
+            target,
+        }
+    }
+
+    pub fn from_guids(host: &str, unit_guid: &str, selection_guid: &str, selection_type: SelectionType) -> Self {
+        Self::new(host, unit_guid, SelectionTarget::new(selection_type, selection_guid))
+    }
+
+    // `signature` is the encrypted value returned by `get_signature`
+    pub fn from_signature(host: &str, unit_guid: &str, signature: &str) -> Self {
+        Self::new(host, unit_guid, SelectionTarget::Signature(signature.to_string()))
+    }
+
+    // `school` is the `unit_id` of the school, as with `get_school`
+    #[cfg(feature = "net")]
+    pub async fn class(domain: &str, school: &str, class: &str, should_cache: bool) -> Result<Self, RequestError> {
+        let unit_guid = get_school_guid(domain, school, should_cache).await?;
+        let class_guid = get_class_guid(domain, &unit_guid, class, should_cache).await?;
+
+        Ok(Self::new(domain, &unit_guid, SelectionTarget::Class(class_guid)))
+    }
+
+    #[cfg(feature = "net")]
+    pub async fn teacher(domain: &str, school: &str, signature: &str, should_cache: bool) -> Result<Self, RequestError> {
+        let unit_guid = get_school_guid(domain, school, should_cache).await?;
+        let teacher_guid = get_teacher_guid(domain, &unit_guid, signature, should_cache).await?;
+
+        Ok(Self::new(domain, &unit_guid, SelectionTarget::Teacher(teacher_guid)))
+    }
+
+    #[cfg(feature = "net")]
+    pub async fn room(domain: &str, school: &str, room: &str, should_cache: bool) -> Result<Self, RequestError> {
+        let unit_guid = get_school_guid(domain, school, should_cache).await?;
+        let room_guid = get_rooms(domain, &unit_guid, should_cache).await?
+            .into_iter()
+            .find(|r| r.name.eq_ignore_ascii_case(room.trim()))
+            .ok_or_else(|| RequestError::not_found(NotFoundKind::Room, room))?
+            .edu_guid;
+
+        Ok(Self::new(domain, &unit_guid, SelectionTarget::Room(room_guid)))
+    }
+
+    // Fails with `RequestError::NotPublic` if the school does not allow looking up students
+    #[cfg(feature = "net")]
+    pub async fn student(domain: &str, school: &str, student_name: &str, should_cache: bool) -> Result<Self, RequestError> {
+        let school = get_school(domain, school, should_cache).await?;
+        if !school.allows_student_lookup() {
+            return Err(RequestError::NotPublic(SelectionType::Student));
         }
+
+        let student = get_students(domain, &school.unit_guid, should_cache).await?
+            .into_iter()
+            .find(|student| student.full_name().eq_ignore_ascii_case(student_name.trim()))
+            .ok_or_else(|| RequestError::not_found(NotFoundKind::Student, student_name))?;
+
+        Ok(Self::new(domain, &school.unit_guid, SelectionTarget::Student(student.person_guid)))
     }
-    Ok(false)
 }
 
-pub async fn cache_request(ckey: String, reqdata: serde_json::value::Value, api: &str, post: bool, should_cache: bool) -> Result<String, RequestError> {
-    let cache = xdg::BaseDirectories::new()?
-                                    .create_cache_directory(env!("CARGO_PKG_NAME"))?
-                                    .to_str().unwrap().to_owned();
-    let data = if should_cache {
-        match cacache::read(&cache, &ckey).await {
-            Ok(data) => Ok(data),
-            Err(e) => Err(RequestError::Cacache(e))
+#[cfg(feature = "net")]
+pub async fn domain_exists(domain: &str, should_cache: bool) -> Result<bool, RequestError> {
+    exists(get_schools(domain, should_cache).await, NotFoundKind::Domain)
+}
+
+// Fails with `NotFoundKind::Domain` if the domain itself does not exist
+#[cfg(feature = "net")]
+pub async fn school_exists(domain: &str, school: &str, should_cache: bool) -> Result<bool, RequestError> {
+    exists(get_school(domain, school, should_cache).await, NotFoundKind::School)
+}
+
+// Fails with `NotFoundKind::Domain` or `NotFoundKind::School`, so `false` always means the
+// school exists but has no such class
+#[cfg(feature = "net")]
+pub async fn class_exists(domain: &str, school: &str, class: &str, should_cache: bool) -> Result<bool, RequestError> {
+    let unit_guid = get_school_guid(domain, school, should_cache).await?;
+    exists(get_class(domain, &unit_guid, class, should_cache).await, NotFoundKind::Class)
+}
+
+// `false` if the lookup failed because of `kind`, other errors are passed on
+#[cfg(feature = "net")]
+fn exists<T>(result: Result<T, RequestError>, kind: NotFoundKind) -> Result<bool, RequestError> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(e) if e.not_found_kind() == Some(kind) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(feature = "net")]
+pub(crate) fn cache_dir() -> Result<String, RequestError> {
+    Ok(xdg::BaseDirectories::new()?
+        .create_cache_directory(env!("CARGO_PKG_NAME"))?
+        .to_str().unwrap().to_owned())
+}
+
+#[cfg(feature = "net")]
+static CACHE_WRITE_BEHIND: AtomicBool = AtomicBool::new(false);
+
+// Return fetched responses before they are written to the cache, writing them on a background
+// task instead. Failed writes are only logged, and writes still running when the process exits
+// are lost, so this suits long running services rather than one-shot commands.
+#[cfg(feature = "net")]
+pub fn set_cache_write_behind(enabled: bool) {
+    CACHE_WRITE_BEHIND.store(enabled, Ordering::Relaxed);
+}
+
+#[cfg(feature = "net")]
+static CACHE_REFRESH: AtomicBool = AtomicBool::new(false);
+
+// Skip cache reads but still write responses to the cache, so later cached requests see what
+// the API returns now. Applies to the whole process, e.g. for a timer that keeps the cache
+// warm; `ops::refresh` turns it on while it fetches.
+#[cfg(feature = "net")]
+pub fn set_cache_refresh(enabled: bool) {
+    CACHE_REFRESH.store(enabled, Ordering::Relaxed);
+}
+
+#[cfg(feature = "net")]
+static STALE_FALLBACK: AtomicBool = AtomicBool::new(false);
+
+// When a request fails on the network, answer with the cached response for it instead, however
+// old and even if `should_cache` is false, e.g. for status displays that would rather show an
+// old schedule than nothing. `data::Response::stale_age` tells how old it was.
+#[cfg(feature = "net")]
+pub fn set_stale_fallback(enabled: bool) {
+    STALE_FALLBACK.store(enabled, Ordering::Relaxed);
+}
+
+#[cfg(feature = "net")]
+pub async fn cache_request<B: serde::Serialize + Sync>(endpoint: Endpoint, ckey: String, body: &B, should_cache: bool) -> Result<String, RequestError> {
+    Ok(cache_request_with_age(endpoint, ckey, body, should_cache).await?.0)
+}
+
+// Also returns the age of the response if it is a stale one returned for a failed request
+#[cfg(feature = "net")]
+async fn cache_request_with_age<B: serde::Serialize + Sync>(endpoint: Endpoint, ckey: String, body: &B, should_cache: bool) -> Result<(String, Option<std::time::Duration>), RequestError> {
+    let context = |stage: RequestStage| {
+        let ckey = &ckey;
+        move |error: RequestError| RequestError::Context {
+            endpoint,
+            cache_key: ckey.clone(),
+            stage,
+            source: Box::new(error),
         }
+    };
+
+    let should_cache = should_cache && endpoint.is_cacheable();
+    let stale_fallback = STALE_FALLBACK.load(Ordering::Relaxed) && endpoint.is_cacheable();
+    // Requests that never touch the cache don't need a cache directory
+    let cache = if should_cache || stale_fallback {
+        Some(cache_dir().map_err(context(RequestStage::CacheRead))?)
     }
     else {
-        Err(RequestError::Empty(EmptyError{}))
+        None
+    };
+    let read_cache = should_cache && !CACHE_REFRESH.load(Ordering::Relaxed);
+    let data = match cache.as_deref().filter(|_| read_cache) {
+        Some(cache) => match cacache::read(cache, &ckey).await {
+            Ok(data) => Ok(data),
+            Err(e) => Err(RequestError::Cacache(e))
+        },
+        None => Err(RequestError::Empty(EmptyError{})),
     };
 
+    if read_cache {
+        metrics::record_cache_lookup(endpoint, data.is_ok());
+    }
     match data {
-        Ok(data) => Ok(std::str::from_utf8(&data)?.to_owned()),
-        Err(_) => {
-            let data = {
-                let client = reqwest::Client::new();
-                let mut reqdata = reqdata;
-                reqdata["renderKey"] = serde_json::json!(get_key().await?);
-                let client = if post {
-                    client.post("https://web.skola24.se/api".to_string() + api)
+        Ok(data) => {
+            debug!("{}: cache hit for {}", endpoint.path(), ckey);
+            Ok((std::str::from_utf8(&data).map_err(|e| context(RequestStage::CacheRead)(e.into()))?.to_owned(), None))
+        },
+        Err(e) => {
+            if read_cache {
+                debug!("{}: cache miss for {} ({:?})", endpoint.path(), ckey, e);
+            }
+            else if should_cache {
+                debug!("{}: refreshing {}", endpoint.path(), ckey);
+            }
+            else {
+                debug!("{}: caching disabled, requesting {}", endpoint.path(), ckey);
+            }
+            let data = async {
+                if endpoint.needs_render_key() {
+                    let render_key = get_key_in(should_cache).await?;
+                    send(endpoint, Some(&WithRenderKey { body, render_key: &render_key })).await
                 }
                 else {
-                    client.get("https://web.skola24.se/api".to_string() + api)
-                };
-
-                client
-                    .header("Content-Type", "application/json")
-                    .header("X-Scope", "8a22163c-8662-4535-9050-bc5e1923df48")
-                    .json(&reqdata)
-                    .send()
-                    .await?
-                    .error_for_status()?
-                    .text().await?
+                    send(endpoint, Some(body)).await
+                }
+            }.await;
+            let data = match data {
+                Ok(data) => data,
+                Err(e) => {
+                    if let Some(cache) = cache.as_deref().filter(|_| stale_fallback) {
+                        if let Some((data, age)) = read_stale(cache, &ckey).await {
+                            warn!("{}: request failed, using the cached response from {:?} ago: {:?}", endpoint.path(), age, e);
+                            return Ok((data, Some(age)));
+                        }
+                    }
+                    return Err(context(RequestStage::Network)(e));
+                },
             };
 
-            cacache::write(&cache, &ckey, &data).await?;
-            Ok(data)
+            let cache = match cache.filter(|_| should_cache) {
+                Some(cache) => cache,
+                None => return Ok((data, None)),
+            };
+            if CACHE_WRITE_BEHIND.load(Ordering::Relaxed) {
+                let (ckey, data) = (ckey.clone(), data.clone());
+                async_std::task::spawn(async move {
+                    if let Err(e) = cacache::write(&cache, &ckey, &data).await {
+                        warn!("{}: could not cache {} ({:?})", endpoint.path(), ckey, e);
+                    }
+                });
+            }
+            else {
+                cacache::write(&cache, &ckey, &data).await.map_err(|e| context(RequestStage::CacheWrite)(e.into()))?;
+            }
+            Ok((data, None))
         }
     }
 }
 
-pub async fn get_schema(selection: (String, String, String), day_of_week: i32, week: i32, dimensions: Option<Dimensions>, should_cache: bool) -> Result<data::Response<data::Schema>, RequestError> {
-    let ckey = (&selection.0).to_string() + &selection.1 + &selection.2 + &week.to_string() + &day_of_week.to_string();
+#[cfg(feature = "net")]
+async fn read_stale(cache: &str, ckey: &str) -> Option<(String, std::time::Duration)> {
+    let metadata = cacache::metadata(cache, ckey).await.ok()??;
+    let data = cacache::read(cache, ckey).await.ok()?;
+    let written = Utc.timestamp_millis_opt(metadata.time.try_into().ok()?).single()?;
+    let age = (timezone::now() - written).to_std().unwrap_or_default();
+    Some((String::from_utf8(data).ok()?, age))
+}
+
+#[cfg(feature = "net")]
+#[derive(serde::Serialize)]
+struct WithRenderKey<'a, B> {
+    #[serde(flatten)]
+    body: &'a B,
+    #[serde(rename = "renderKey")]
+    render_key: &'a str,
+}
+
+#[cfg(feature = "net")]
+pub async fn get_schema(selection: impl Into<Selection>, day_of_week: i32, week: i32, dimensions: Option<Dimensions>, options: &SchemaRequestOptions, should_cache: bool) -> Result<data::Response<data::Schema>, RequestError> {
+    get_schema_in(selection, day_of_week, request_year(), week, dimensions, options, should_cache).await
+}
+
+// The schema of the day `date` falls on. Asks for the ISO week and its year, which differ from
+// the calendar year around new year, e.g. 2024-12-30 is Monday of week 1 of 2025.
+#[cfg(feature = "net")]
+pub async fn get_schema_for_date(selection: impl Into<Selection>, date: NaiveDate, dimensions: Option<Dimensions>, options: &SchemaRequestOptions, should_cache: bool) -> Result<data::Response<data::Schema>, RequestError> {
+    let iso_week = date.iso_week();
+    let day = date.weekday().number_from_monday() as i32;
+    get_schema_in(selection, day, iso_week.year(), iso_week.week() as i32, dimensions, options, should_cache).await
+}
+
+#[cfg(feature = "net")]
+async fn get_schema_in(selection: impl Into<Selection>, day_of_week: i32, year: i32, week: i32, dimensions: Option<Dimensions>, options: &SchemaRequestOptions, should_cache: bool) -> Result<data::Response<data::Schema>, RequestError> {
+    let (data, stale_age) = get_schema_raw_with_age(selection, day_of_week, year, week, dimensions, options, should_cache).await?;
+    let mut schema = data::parse_interned::<data::Response<data::Schema>>(&data)?;
+    schema.raw = Some(data);
+    schema.stale_age = stale_age;
+
+    Ok(schema)
+}
+
+// The response body of `get_schema` as is, to be parsed without copying, e.g.
+// `serde_json::from_str::<data::Response<data::SchemaRef>>(&raw)`
+#[cfg(feature = "net")]
+pub async fn get_schema_raw(selection: impl Into<Selection>, day_of_week: i32, week: i32, dimensions: Option<Dimensions>, options: &SchemaRequestOptions, should_cache: bool) -> Result<String, RequestError> {
+    Ok(get_schema_raw_with_age(selection, day_of_week, request_year(), week, dimensions, options, should_cache).await?.0)
+}
+
+#[cfg(feature = "net")]
+async fn get_schema_raw_with_age(selection: impl Into<Selection>, day_of_week: i32, year: i32, week: i32, dimensions: Option<Dimensions>, options: &SchemaRequestOptions, should_cache: bool) -> Result<(String, Option<std::time::Duration>), RequestError> {
+    let selection = selection.into();
     let dimensions = dimensions.unwrap_or_default();
-    let now = Local::now();
-    let data = serde_json::json!({
-        "host": selection.0,
-        "unitGuid": selection.1,
-        "scheduleDay": day_of_week,
-        "blackAndWhite": false,
-        "width": dimensions.width,
-        "height": dimensions.height,
-        "selectionType": 0,
-        "selection": selection.2,
-        "showHeader": false,
-        "periodText": "",
-        "week": week,
-        "year": now.year(),
-        "privateSelectionMode": false,
-        "customerKey": "",
-    });
+    // Delimited so that neighbouring parts can't run into each other
+    let mut ckey = format!("{}|{}|{}|{}|{}|{}", selection.host, selection.unit_guid, selection.target.guid(), week, day_of_week, options.cache_key());
+    // Keeps the keys of schemas at the default size short
+    if dimensions != Dimensions::default() {
+        ckey += &format!("|{}x{}", dimensions.width, dimensions.height);
+    }
+    // Likewise for schemas of the current year
+    if year != request_year() {
+        ckey += &format!("|y{}", year);
+    }
+    let body = data::RenderTimetableRequest {
+        host: &selection.host,
+        unit_guid: &selection.unit_guid,
+        schedule_day: day_of_week,
+        black_and_white: options.black_and_white,
+        width: dimensions.width,
+        height: dimensions.height,
+        selection_type: selection.target.selection_type() as i32,
+        selection: selection.target.guid(),
+        show_header: options.show_header,
+        period_text: &options.period_text,
+        week,
+        year,
+        private_selection_mode: options.private_selection_mode,
+        customer_key: options.customer_key.as_deref().unwrap_or_default(),
+    };
+
+    cache_request_with_age(Endpoint::RenderTimetable, ckey, &body, should_cache).await
+}
+
+// Collects the render parameters of `get_schema`, e.g.
+// `SchemaRequest::builder(selection).week(45).day(Weekday::Tue).black_and_white(true).fetch().await`
+#[derive(Clone, Debug)]
+pub struct SchemaRequest {
+    pub selection: Selection,
+    // The current ISO week by default
+    pub week: Option<i32>,
+    // The whole week by default
+    pub day: Option<Weekday>,
+    pub dimensions: Option<Dimensions>,
+    pub options: SchemaRequestOptions,
+    pub should_cache: bool,
+}
+
+impl SchemaRequest {
+    pub fn builder(selection: impl Into<Selection>) -> Self {
+        SchemaRequest {
+            selection: selection.into(),
+            week: None,
+            day: None,
+            dimensions: None,
+            options: SchemaRequestOptions::default(),
+            should_cache: true,
+        }
+    }
+
+    pub fn week(mut self, week: i32) -> Self {
+        self.week = Some(week);
+        self
+    }
+
+    pub fn day(mut self, day: Weekday) -> Self {
+        self.day = Some(day);
+        self
+    }
+
+    pub fn dimensions(mut self, dimensions: Dimensions) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    pub fn black_and_white(mut self, black_and_white: bool) -> Self {
+        self.options.black_and_white = black_and_white;
+        self
+    }
+
+    pub fn private_selection_mode(mut self, private_selection_mode: bool) -> Self {
+        self.options.private_selection_mode = private_selection_mode;
+        self
+    }
+
+    pub fn customer_key<S: Into<String>>(mut self, customer_key: S) -> Self {
+        self.options.customer_key = Some(customer_key.into());
+        self
+    }
+
+    pub fn header<S: Into<String>>(mut self, period_text: S) -> Self {
+        self.options.show_header = true;
+        self.options.period_text = period_text.into();
+        self
+    }
+
+    pub fn cache(mut self, should_cache: bool) -> Self {
+        self.should_cache = should_cache;
+        self
+    }
+
+    #[cfg(feature = "net")]
+    pub async fn fetch(&self) -> Result<data::Response<data::Schema>, RequestError> {
+        // The current ISO week is asked for in its own year, which around new year is not the
+        // calendar year `get_schema` uses
+        let (year, week) = match self.week {
+            Some(week) => (request_year(), week),
+            None => {
+                let iso_week = timezone::now_in(timezone::SCHEDULE_TIMEZONE).date().iso_week();
+                (iso_week.year(), iso_week.week() as i32)
+            },
+        };
+        // Day 0 makes the API return the whole week
+        let day = self.day.map_or(0, |day| day.number_from_monday() as i32);
 
-    let data = cache_request(ckey, data, "/render/timetable", false, should_cache).await?;
-    match serde_json::from_str::<data::Response<data::Schema>>(&data) {
-        Ok(data) => Ok(data),
-        Err(err) => Err(RequestError::Serde(err))
+        get_schema_in(self.selection.clone(), day, year, week, self.dimensions, &self.options, self.should_cache).await
     }
 }
 
+// The response keeps the raw JSON
+#[cfg(feature = "net")]
+pub async fn get_selection_list(domain: &str, unit_guid: &str, filters: impl Into<data::SelectionFilters>, should_cache: bool) -> Result<data::Response<data::ClassList>, RequestError> {
+    let filters = filters.into();
+    let mut ckey = format!("{}|{}|{}", timezone::now().format("%Y%m%d"), domain, unit_guid);
+    // Class lists are cached without a suffix, as before other categories could be requested
+    if filters != data::SelectionFilter::Class.into() {
+        for filter in filters.iter() {
+            ckey += "|";
+            ckey += filter.as_str();
+        }
+    }
+
+    let body = data::SelectionRequest {
+        host_name: domain,
+        unit_guid,
+        filters,
+    };
+
+    let (data, stale_age) = cache_request_with_age(Endpoint::Selection, ckey, &body, should_cache).await?;
+    let mut result: data::Response<data::ClassList> = serde_json::from_str::<data::Response<data::APIResult<data::ClassList>>>(&data)?
+        .try_into()
+        .map_err(|e| validation_not_found(e, data::ValidationErrorKind::UnitNotFound, NotFoundKind::School, unit_guid))?;
+    result.raw = Some(data);
+    result.stale_age = stale_age;
+
+    Ok(result)
+}
+
+// Every requested category in one round trip, the others are left empty
+#[cfg(feature = "net")]
+pub async fn get_selection(domain: &str, unit_guid: &str, filters: data::SelectionFilters, should_cache: bool) -> Result<data::ClassList, RequestError> {
+    Ok(get_selection_list(domain, unit_guid, filters, should_cache).await?.data)
+}
+
+#[cfg(feature = "net")]
 pub async fn get_classes(domain: &str, unit_guid: &str, should_cache: bool) -> Result<Vec<data::Class>, RequestError> {
-    let ckey = Utc::now().format("%Y%m%d").to_string() + domain + unit_guid;
+    Ok(get_selection_list(domain, unit_guid, data::SelectionFilter::Class, should_cache).await?.data.classes)
+}
 
-    let data = serde_json::json!({
-        "hostName": domain,
-        "unitGuid": unit_guid,
-        "filters": {"class":true}
-    });
+#[cfg(feature = "net")]
+pub async fn get_teachers(domain: &str, unit_guid: &str, should_cache: bool) -> Result<Vec<data::Teacher>, RequestError> {
+    Ok(get_selection_list(domain, unit_guid, data::SelectionFilter::Teacher, should_cache).await?.data.teachers)
+}
 
-    let data = cache_request(ckey, data, "/get/timetable/selection", false, should_cache).await?;
-    let result: data::Response<data::ClassList> = serde_json::from_str::<data::Response<data::APIResult<data::ClassList>>>(&data)?.try_into()?;
+#[cfg(feature = "net")]
+pub async fn get_domain_info(domain: &str, should_cache: bool) -> Result<data::Response<data::DomainInfo>, RequestError> {
+    let ckey = format!("{}|{}", timezone::now().format("%Y%m%d"), domain);
+    let body = data::UnitsRequest {
+        request: data::UnitsRequestHost {
+            host_name: domain,
+        },
+    };
+
+    let (data, stale_age) = cache_request_with_age(Endpoint::ViewerUnits, ckey, &body, should_cache).await?;
+    let mut result: data::Response<data::DomainInfo> = serde_json::from_str::<data::Response<data::APIResult<data::DomainInfo>>>(&data)?
+        .try_into()
+        .map_err(|e| validation_not_found(e, data::ValidationErrorKind::HostNotFound, NotFoundKind::Domain, domain))?;
+    result.raw = Some(data);
+    result.stale_age = stale_age;
 
-    Ok(result.data.classes)
+    Ok(result)
 }
 
+#[cfg(feature = "net")]
 pub async fn get_schools(domain: &str, should_cache: bool) -> Result<Vec<data::School>, RequestError> {
-    let ckey = Utc::now().format("%Y%m%d").to_string() + domain;
-    let data: serde_json::Value = serde_json::json!({
-        "getTimetableViewerUnitsRequest": {"hostName": domain}
-    });
+    Ok(get_domain_info(domain, should_cache).await?.data.domain_school_list.units)
+}
+
+// Turns the API's validation error for a missing host or unit into a typed `NotFound`
+#[cfg(feature = "net")]
+fn validation_not_found(error: data::SchemaError, validation: data::ValidationErrorKind, kind: NotFoundKind, query: &str) -> RequestError {
+    if error.validation_kind() == Some(validation) {
+        RequestError::not_found(kind, query)
+    }
+    else {
+        error.into()
+    }
+}
 
-    let data = cache_request(ckey, data, "/services/skola24/get/timetable/viewer/units", true, should_cache).await?;
-    let result: data::Response<data::DomainInfo> = serde_json::from_str::<data::Response<data::APIResult<data::DomainInfo>>>(&data)?.try_into()?;
+// Case-insensitive fuzzy search over school names, best matches first
+#[cfg(feature = "net")]
+pub async fn find_schools(domain: &str, query: &str, should_cache: bool) -> Result<Vec<data::School>, RequestError> {
+    let schools = get_schools(domain, should_cache).await?;
 
-    Ok(result.data.domain_school_list.units)
+    Ok(search::rank(schools, query, |s| vec![&s.unit_id]))
 }
 
-pub async fn get_class_guid(domain: &str, unit_guid: &str, name: &str, should_cache: bool) -> Result<String, RequestError> {
+// Case-insensitive prefix/fuzzy search over class names, so "7a" finds "7A"
+#[cfg(feature = "net")]
+pub async fn find_classes(domain: &str, unit_guid: &str, query: &str, should_cache: bool) -> Result<Vec<data::Class>, RequestError> {
     let classes = get_classes(domain, unit_guid, should_cache).await?;
 
-    for class in classes {
-        if class.group_name == name {
-            return Ok(class.group_guid);
+    Ok(search::rank(classes, query, |c| vec![&c.group_name]))
+}
+
+#[cfg(feature = "net")]
+pub async fn get_class(domain: &str, unit_guid: &str, name: &str, should_cache: bool) -> Result<data::Class, RequestError> {
+    get_classes(domain, unit_guid, should_cache).await?
+        .into_iter()
+        .find(|class| class.group_name == name)
+        .ok_or_else(|| RequestError::not_found(NotFoundKind::Class, name))
+}
+
+#[cfg(feature = "net")]
+pub async fn get_class_guid(domain: &str, unit_guid: &str, name: &str, should_cache: bool) -> Result<String, RequestError> {
+    Ok(get_class(domain, unit_guid, name, should_cache).await?.group_guid)
+}
+
+#[cfg(feature = "net")]
+pub async fn get_teacher_guid(domain: &str, unit_guid: &str, signature: &str, should_cache: bool) -> Result<String, RequestError> {
+    let teachers = get_teachers(domain, unit_guid, should_cache).await?;
+
+    for teacher in teachers {
+        if teacher.id.eq_ignore_ascii_case(signature) {
+            return Ok(teacher.person_guid);
         }
     }
-    Ok(String::new())
+    Err(RequestError::not_found(NotFoundKind::Teacher, signature))
 }
 
-pub async fn get_school_guid(domain: &str, name: &str, should_cache: bool) -> Result<String, RequestError> {
-    let schools = get_schools(domain, should_cache).await?;
+#[cfg(feature = "net")]
+pub async fn get_rooms(domain: &str, unit_guid: &str, should_cache: bool) -> Result<Vec<data::Room>, RequestError> {
+    Ok(get_selection_list(domain, unit_guid, data::SelectionFilter::Room, should_cache).await?.data.rooms)
+}
+
+// Rooms without any lesson overlapping `start..end` on the given day
+#[cfg(feature = "net")]
+pub async fn find_free_rooms(domain: &str, unit_guid: &str, week: i32, weekday: Weekday, start: NaiveTime, end: NaiveTime, should_cache: bool) -> Result<Vec<data::Room>, RequestError> {
+    let mut free = Vec::new();
 
-    for school in schools {
-        if school.unit_id == name {
-            return Ok(school.unit_guid);
+    for room in get_rooms(domain, unit_guid, should_cache).await? {
+        let selection = Selection::from_guids(domain, unit_guid, &room.edu_guid, SelectionType::Room);
+        let schedule = fetch_schedule(selection, weekday.number_from_monday() as i32, request_year(), week as u32, should_cache).await?;
+        if query::is_free(schedule.lessons_on(weekday), weekday, start, end) {
+            free.push(room);
         }
     }
-    Ok(String::new())
+
+    Ok(free)
 }
 
-pub fn print_lessons(lesson_info: &[data::LessonInfo], next_day: bool) -> Result<(), reqwest::Error> {
-    let now = if next_day {NaiveTime::from_hms(0,0,0)}
-    else {Local::now().time()};
+#[cfg(feature = "net")]
+pub async fn get_students(domain: &str, unit_guid: &str, should_cache: bool) -> Result<Vec<data::Student>, RequestError> {
+    Ok(get_selection_list(domain, unit_guid, data::SelectionFilter::Student, should_cache).await?.data.students)
+}
 
-    let mut next_lesson_time = NaiveTime::from_hms(23, 59, 59);
-    let mut next_lesson = 0;
+#[cfg(feature = "net")]
+pub async fn get_student_lessons(domain: &str, school: &str, student_name: &str, week: i32, should_cache: bool) -> Result<model::WeekSchedule, RequestError> {
+    let selection = Selection::student(domain, school, student_name, should_cache).await?;
 
-    let mut current_lesson_bool = false;
-    let mut next_lesson_bool = false;
+    get_week_schedule(selection, week, should_cache).await
+}
 
-    for (i, lesson) in lesson_info.iter().enumerate() {
-        let time_start = NaiveTime::parse_from_str(&lesson.time_start.to_string(), "%H:%M:%S").unwrap_or_else(|_| panic!("Failed to parse time!"));
-        let time_end = NaiveTime::parse_from_str(&lesson.time_end.to_string(), "%H:%M:%S").unwrap_or_else(|_| panic!("Failed to parse time!"));
+#[cfg(feature = "net")]
+pub async fn get_school(domain: &str, name: &str, should_cache: bool) -> Result<data::School, RequestError> {
+    let schools = get_schools(domain, should_cache).await?;
 
-        if time_start > now {
-            if time_start < next_lesson_time {
-                next_lesson_bool = true;
-                next_lesson_time = time_start;
-                next_lesson = i;
-            }
+    schools.into_iter()
+        .find(|school| school.unit_id == name)
+        .ok_or_else(|| RequestError::not_found(NotFoundKind::School, name))
+}
+
+#[cfg(feature = "net")]
+pub async fn get_school_guid(domain: &str, name: &str, should_cache: bool) -> Result<String, RequestError> {
+    Ok(get_school(domain, name, should_cache).await?.unit_guid)
+}
+
+#[derive(Debug, Clone)]
+pub struct LessonFormat {
+    // Characters of the subject to show, all of them when `None`
+    pub name_length: Option<usize>,
+    // Between the name and the time of a lesson
+    pub time_separator: String,
+    // Between the current and the next lesson
+    pub separator: String,
+    pub show_current: bool,
+}
+
+impl Default for LessonFormat {
+    fn default() -> Self {
+        LessonFormat {
+            name_length: Some(3),
+            time_separator: "-".to_string(),
+            separator: ", ".to_string(),
+            show_current: true,
         }
-        else if time_end > now {
-            current_lesson_bool = true;
-            print!("{}-{}", &lesson.texts[0].to_string()[..3], time_end.format("%H:%M"));
-        };
     }
+}
 
-    if next_lesson_bool {
-        if current_lesson_bool {
-            print!(", ");
-        }
-        println!("{}-{}", next_lesson_time.format("%H:%M"), &lesson_info[next_lesson].texts[0].to_string()[..3]);
+// The lesson going on at `now` with its end time, followed by the start time of the next one,
+// e.g. "Mat-10:30, 10:40-Sve"
+pub fn format_lessons(lessons: &[model::Lesson], now: NaiveTime, options: &LessonFormat) -> String {
+    let name = |lesson: &model::Lesson| match options.name_length {
+        Some(length) => lesson.subject.chars().take(length).collect(),
+        None => lesson.subject.clone(),
+    };
+
+    let mut parts = Vec::new();
+    if let Some(lesson) = query::current_lesson(lessons, now).filter(|_| options.show_current) {
+        parts.push(format!("{}{}{}", name(lesson), options.time_separator, lesson.end.format("%H:%M")));
     }
-    else {
-        println!();
+    if let Some(lesson) = query::next_lesson(lessons, now) {
+        parts.push(format!("{}{}{}", lesson.start.format("%H:%M"), options.time_separator, name(lesson)));
     }
+    parts.join(&options.separator)
+}
+
+pub fn print_lessons(lessons: &[model::Lesson], next_day: bool) -> Result<(), RequestError> {
+    print_lessons_with(lessons, next_day, &timezone::FixedClock(timezone::now()))
+}
+
+// Like `print_lessons` at the time of `clock` instead of the one set with `timezone::set_clock`
+pub fn print_lessons_with(lessons: &[model::Lesson], next_day: bool, clock: &dyn timezone::Clock) -> Result<(), RequestError> {
+    let now = if next_day {NaiveTime::MIN}
+    else {clock.now_in(timezone::SCHEDULE_TIMEZONE).time()};
+
+    println!("{}", format_lessons(lessons, now, &LessonFormat::default()));
 
     Ok(())
 }
 
-pub async fn get_lesson_info(selection: (String, String, String), day: i32, week: i32, should_cache: bool) -> Result<Vec<data::LessonInfo>, RequestError> {
-    let schema = get_schema(selection, day, week, None, should_cache).await?;
-    let lesson_info = add_box_info(&schema.data)?;
+#[cfg(feature = "net")]
+pub async fn get_lesson_info(selection: impl Into<Selection>, day: i32, week: i32, should_cache: bool) -> Result<Vec<data::LessonInfo>, RequestError> {
+    let data = get_schema_raw(selection, day, week, None, &SchemaRequestOptions::default(), should_cache).await?;
+    let schema = data::parse_interned::<data::Response<data::LessonBoxSchema>>(&data)?;
+    let mut lesson_info = into_lesson_info(schema.data.into());
+
+    let year = request_year();
+    for lesson in &mut lesson_info {
+        lesson.resolve_date(year, week as u32);
+    }
 
     Ok(lesson_info)
 }
 
-fn add_box_info(data: &data::Schema) -> Result<Vec<data::LessonInfo>, RequestError> {
-    let mut lesson_info = data.lesson_info.clone();
-    for i in 0..data.lesson_info.len() {
-        for j in 0..data.box_list.len() {
-            if data.box_list[j].type_field != "Lesson" {continue;}
-            for k in 0..data.box_list[j].lesson_guids.as_ref().unwrap().len() {
-                if data.lesson_info[i].guid_id == data.box_list[j].lesson_guids.as_ref().unwrap()[k] {
-                    lesson_info[i].block = data.box_list[j].clone();
-                }
+// Like `get_lesson_info` for callers that don't use the boxes, e.g. to list or export lessons.
+// Asks for the least geometry possible, so `LessonInfo::block` is left empty.
+#[cfg(feature = "net")]
+pub async fn fetch_lessons_only(selection: impl Into<Selection>, day: i32, week: i32, should_cache: bool) -> Result<Vec<data::LessonInfo>, RequestError> {
+    let options = SchemaRequestOptions {
+        black_and_white: true,
+        ..SchemaRequestOptions::default()
+    };
+    let data = get_schema_raw(selection, day, week, Some(Dimensions::MINIMAL), &options, should_cache).await?;
+    let mut lesson_info = serde_json::from_str::<data::Response<data::LessonSchema>>(&data)?.data.lesson_info;
+
+    let year = request_year();
+    for lesson in &mut lesson_info {
+        lesson.resolve_date(year, week as u32);
+    }
+
+    Ok(lesson_info)
+}
+
+// Why the given day has no lessons, `None` if it has any
+#[cfg(feature = "net")]
+pub async fn get_empty_reason(selection: impl Into<Selection>, day: i32, week: i32, should_cache: bool) -> Result<Option<analysis::EmptyReason>, RequestError> {
+    let schema = get_schema(selection, day, week, None, &SchemaRequestOptions::default(), should_cache).await?;
+
+    Ok(analysis::empty_reason(&schema))
+}
+
+// Lessons that can't be parsed are left out, see `model::from_schema`
+#[cfg(feature = "net")]
+async fn fetch_schedule(selection: impl Into<Selection>, day: i32, year: i32, week: u32, should_cache: bool) -> Result<model::WeekSchedule, RequestError> {
+    let (schedule, warnings) = fetch_schedule_with_warnings(selection, day, year, week, should_cache).await?;
+    for warning in warnings {
+        warn!("week {}: {}", week, warning);
+    }
+
+    Ok(schedule)
+}
+
+#[cfg(feature = "net")]
+async fn fetch_schedule_with_warnings(selection: impl Into<Selection>, day: i32, year: i32, week: u32, should_cache: bool) -> Result<(model::WeekSchedule, Vec<model::LessonWarning>), RequestError> {
+    let schema = get_schema_in(selection, day, year, week as i32, None, &SchemaRequestOptions::default(), should_cache).await?;
+
+    Ok(model::from_schema(&schema.data, year, week))
+}
+
+#[cfg(feature = "net")]
+pub async fn get_week_schedule(selection: impl Into<Selection>, week: i32, should_cache: bool) -> Result<model::WeekSchedule, RequestError> {
+    // Day 0 makes the API return the whole week
+    fetch_schedule(selection, 0, request_year(), week as u32, should_cache).await
+}
+
+// Also reports the lessons that were left out because their times or weekday could not be
+// parsed, and the duplicates that were dropped
+#[cfg(feature = "net")]
+pub async fn get_week_schedule_with_warnings(selection: impl Into<Selection>, week: i32, should_cache: bool) -> Result<(model::WeekSchedule, Vec<model::LessonWarning>), RequestError> {
+    fetch_schedule_with_warnings(selection, 0, request_year(), week as u32, should_cache).await
+}
+
+// Fails on the first lesson that could not be parsed instead of leaving it out
+#[cfg(feature = "net")]
+pub async fn get_week_schedule_strict(selection: impl Into<Selection>, week: i32, should_cache: bool) -> Result<model::WeekSchedule, RequestError> {
+    let (schedule, warnings) = get_week_schedule_with_warnings(selection, week, should_cache).await?;
+    match warnings.into_iter().find(|warning| !matches!(warning, model::LessonWarning::Duplicate { .. })) {
+        Some(warning) => Err(warning.into()),
+        None => Ok(schedule),
+    }
+}
+
+#[cfg(feature = "net")]
+pub async fn get_teacher_lessons(domain: &str, school: &str, teacher_signature: &str, week: i32, should_cache: bool) -> Result<model::WeekSchedule, RequestError> {
+    let selection = Selection::teacher(domain, school, teacher_signature, should_cache).await?;
+
+    get_week_schedule(selection, week, should_cache).await
+}
+
+#[cfg(feature = "net")]
+pub async fn get_lessons_for_date(selection: impl Into<Selection>, date: NaiveDate, should_cache: bool) -> Result<model::DaySchedule, RequestError> {
+    let iso_week = date.iso_week();
+    let weekday = date.weekday();
+
+    let schedule = fetch_schedule(selection, weekday.number_from_monday() as i32, iso_week.year(), iso_week.week(), should_cache).await?;
+
+    Ok(schedule.days
+        .into_iter()
+        .find(|day| day.weekday == weekday)
+        .unwrap_or_else(|| model::DaySchedule::new(weekday, Some(date))))
+}
+
+#[cfg(feature = "net")]
+pub async fn get_today(selection: impl Into<Selection>, should_cache: bool) -> Result<model::DaySchedule, RequestError> {
+    let today = timezone::now_in(timezone::SCHEDULE_TIMEZONE).date();
+
+    get_lessons_for_date(selection, today, should_cache).await
+}
+
+// On Sundays this is Monday of the next ISO week
+#[cfg(feature = "net")]
+pub async fn get_tomorrow(selection: impl Into<Selection>, should_cache: bool) -> Result<model::DaySchedule, RequestError> {
+    let tomorrow = timezone::now_in(timezone::SCHEDULE_TIMEZONE).date() + Duration::days(1);
+
+    get_lessons_for_date(selection, tomorrow, should_cache).await
+}
+
+// How far ahead `next_n_lessons` looks before giving up, enough to cover the summer break
+#[cfg(feature = "net")]
+const MAX_WEEKS_AHEAD: i64 = 12;
+
+// The next `n` lessons starting at or after `from`, fetching following weeks as needed
+#[cfg(feature = "net")]
+pub async fn next_n_lessons<T: TimeZone>(selection: impl Into<Selection>, n: usize, from: &DateTime<T>, should_cache: bool) -> Result<Vec<model::Lesson>, RequestError> {
+    let selection = selection.into();
+    let from = timezone::wall_clock(from, timezone::SCHEDULE_TIMEZONE);
+
+    let mut lessons = Vec::new();
+    for weeks_ahead in 0..MAX_WEEKS_AHEAD {
+        if lessons.len() >= n {
+            break;
+        }
+
+        let iso_week = (from.date() + Duration::weeks(weeks_ahead)).iso_week();
+        let week = fetch_schedule(selection.clone(), 0, iso_week.year(), iso_week.week(), should_cache).await?;
+
+        let mut upcoming: Vec<(NaiveDateTime, model::Lesson)> = week.days.into_iter()
+            .flat_map(|day| day.lessons)
+            .filter_map(|lesson| Some((lesson.start_datetime()?, lesson)))
+            .filter(|(start, _)| *start >= from)
+            .collect();
+        upcoming.sort_by_key(|(start, _)| *start);
+        lessons.extend(upcoming.into_iter().map(|(_, lesson)| lesson));
+    }
+
+    lessons.truncate(n);
+    Ok(lessons)
+}
+
+// The first weekday after `from_date` that has lessons, skipping weekends and holidays
+#[cfg(feature = "net")]
+pub async fn next_school_day(selection: impl Into<Selection>, from_date: NaiveDate, should_cache: bool) -> Result<Option<model::DaySchedule>, RequestError> {
+    let selection = selection.into();
+
+    let mut schedule = model::WeekSchedule::default();
+    let mut fetched_week = None;
+    for days_ahead in 1..=MAX_WEEKS_AHEAD * 7 {
+        let date = from_date + Duration::days(days_ahead);
+        if date.weekday() == Weekday::Sat || date.weekday() == Weekday::Sun {
+            continue;
+        }
+
+        let iso_week = date.iso_week();
+        if fetched_week != Some(iso_week) {
+            schedule = fetch_schedule(selection.clone(), 0, iso_week.year(), iso_week.week(), should_cache).await?;
+            fetched_week = Some(iso_week);
+        }
+
+        if let Some(day) = schedule.day(date.weekday()).filter(|day| !day.is_empty()) {
+            return Ok(Some(day.clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(feature = "net")]
+pub async fn get_term_schedule(selection: impl Into<Selection>, weeks: impl IntoIterator<Item = i32>, should_cache: bool) -> Result<model::TermSchedule, RequestError> {
+    get_term_schedule_in(selection, request_year(), weeks, should_cache).await
+}
+
+// The weeks of the ISO year `year`
+#[cfg(feature = "net")]
+async fn get_term_schedule_in(selection: impl Into<Selection>, year: i32, weeks: impl IntoIterator<Item = i32>, should_cache: bool) -> Result<model::TermSchedule, RequestError> {
+    let selection = selection.into();
+
+    let mut term = model::TermSchedule::default();
+    for week in weeks {
+        // Day 0 makes the API return the whole week
+        term.weeks.push(fetch_schedule(selection.clone(), 0, year, week as u32, should_cache).await?);
+    }
+
+    Ok(term)
+}
+
+#[cfg(feature = "net")]
+pub async fn get_merged_schedule(selections: &[Selection], week: i32, should_cache: bool) -> Result<model::WeekSchedule, RequestError> {
+    let mut merged = model::WeekSchedule::new(request_year(), week as u32, Vec::new());
+
+    for selection in selections {
+        let schedule = get_week_schedule(selection.clone(), week, should_cache).await?;
+        merged.merge(schedule, selection.target.guid());
+    }
+
+    Ok(merged)
+}
+
+// Like `get_merged_schedule` with a tag of the caller's choice as the source of each lesson,
+// e.g. `[("Alice", alice), ("Bob", bob)]` for siblings at schools on different hosts. Lessons
+// that appear in several selections are kept once with all of their tags.
+#[cfg(feature = "net")]
+pub async fn get_tagged_schedule<T: AsRef<str>>(selections: &[(T, Selection)], week: i32, should_cache: bool) -> Result<model::WeekSchedule, RequestError> {
+    let mut merged = model::WeekSchedule::new(request_year(), week as u32, Vec::new());
+
+    for (tag, selection) in selections {
+        let schedule = get_week_schedule(selection.clone(), week, should_cache).await?;
+        merged.merge(schedule, tag.as_ref());
+    }
+
+    Ok(merged)
+}
+
+#[cfg(feature = "net")]
+fn request_year() -> i32 {
+    timezone::now_in(timezone::SCHEDULE_TIMEZONE).year()
+}
+
+// The ISO year of the current week if `week` is it, which around new year is not the calendar
+// year, otherwise the calendar year like `request_year`
+#[cfg(feature = "net")]
+fn year_of_week(week: i32) -> i32 {
+    let today = timezone::now_in(timezone::SCHEDULE_TIMEZONE).date();
+    match today.iso_week() {
+        iso_week if iso_week.week() as i32 == week => iso_week.year(),
+        _ => today.year(),
+    }
+}
+
+// The lessons with `block` set to their Lesson box, see `data::Schema::lesson_boxes`.
+// `data::Schema::lessons_with_boxes` gives the same without copying anything.
+pub fn add_box_info(data: &data::Schema) -> Vec<data::LessonInfo> {
+    data.lessons_with_boxes()
+        .map(|(info, lesson_box)| {
+            let mut info = info.clone();
+            if let Some(lesson_box) = lesson_box {
+                info.block = lesson_box.clone();
             }
+            info
+        })
+        .collect()
+}
+
+// Like `add_box_info`, but reuses the lessons of `data` instead of copying them
+pub fn into_lesson_info(data: data::Schema) -> Vec<data::LessonInfo> {
+    let mut blocks: Vec<Option<data::Box>> = data.lessons_with_boxes().map(|(_, lesson_box)| lesson_box.cloned()).collect();
+    let mut lesson_info = data.lesson_info;
+    for (info, block) in lesson_info.iter_mut().zip(blocks.iter_mut()) {
+        if let Some(block) = block.take() {
+            info.block = block;
         }
     }
 
-    Ok(lesson_info)
+    lesson_info
 }
+
+// Everything is meant to be shared across threads and tasks, e.g. by bots serving many users
+// from one runtime. cacache writes entries atomically, so concurrent requests for the same key
+// at worst fetch it twice. These fail to compile if a type or future stops being thread safe.
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[cfg(feature = "net")]
+fn assert_send<T: Send>(_: T) {}
+
+const _: fn() = || {
+    assert_send_sync::<Selection>();
+    assert_send_sync::<SchemaRequest>();
+    assert_send_sync::<SchemaRequestOptions>();
+    assert_send_sync::<RequestError>();
+    assert_send_sync::<data::Schema>();
+    assert_send_sync::<data::Response<data::Schema>>();
+    assert_send_sync::<data::SchemaRef<'static>>();
+    assert_send_sync::<model::WeekSchedule>();
+    assert_send_sync::<timetable::Timetable>();
+};
+
+#[cfg(feature = "net")]
+const _: fn() = || {
+    let options = SchemaRequestOptions::default();
+    let request = SchemaRequest::builder(Selection::default());
+    assert_send(get_key());
+    assert_send(cache_request(Endpoint::Selection, String::new(), &(), true));
+    assert_send(get_schema(Selection::default(), 0, 1, None, &options, true));
+    assert_send(get_selection("", "", data::SelectionFilters::all(), true));
+    assert_send(fetch_lessons_only(Selection::default(), 0, 1, true));
+    assert_send(get_schema_raw(Selection::default(), 0, 1, None, &options, true));
+    assert_send(request.fetch());
+    assert_send(get_schools("", true));
+    assert_send(get_week_schedule(Selection::default(), 1, true));
+    assert_send(next_n_lessons(Selection::default(), 1, &Utc::now(), true));
+    assert_send(timetable::Timetable::fetch(Selection::default(), 1, None, &options, true));
+    assert_send(watch::watch(Selection::default(), std::time::Duration::from_secs(60)));
+};
@@ -0,0 +1,23 @@
+// Forward to the `log` facade when the `log` feature is enabled. Without it the arguments
+// are still type checked but nothing is formatted or printed.
+
+// Only the networking code logs so far
+#![allow(unused_macros)]
+
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::warn!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
@@ -0,0 +1,71 @@
+use chrono::{NaiveTime, Weekday};
+
+use crate::analysis::WEEKDAYS;
+use crate::model::{Lesson, WeekSchedule};
+
+pub fn day_to_markdown(day: &[Lesson]) -> String {
+    let mut lessons: Vec<&Lesson> = day.iter().collect();
+    lessons.sort_by_key(|lesson| (lesson.start, lesson.end));
+
+    let mut md = String::from("| Time | Subject | Teacher | Room |\n|---|---|---|---|\n");
+    for lesson in lessons {
+        md += &format!(
+            "| {} | {} | {} | {} |\n",
+            time_slot(lesson.start, lesson.end),
+            escape(&lesson.subject),
+            escape(&lesson.teacher),
+            escape(&lesson.room),
+        );
+    }
+
+    md
+}
+
+// One column per day and one row per distinct time slot. Weekends only get a column if they have lessons.
+pub fn week_to_markdown(week: &WeekSchedule) -> String {
+    let days: Vec<Weekday> = WEEKDAYS.iter()
+        .copied()
+        .filter(|&day| !matches!(day, Weekday::Sat | Weekday::Sun) || !week.lessons_on(day).is_empty())
+        .collect();
+
+    let mut slots: Vec<(NaiveTime, NaiveTime)> = week.lessons()
+        .map(|lesson| (lesson.start, lesson.end))
+        .collect();
+    slots.sort_unstable();
+    slots.dedup();
+
+    let mut md = String::from("| Time |");
+    for day in &days {
+        md += &format!(" {} |", day);
+    }
+    md += "\n|---|";
+    md += &"---|".repeat(days.len());
+    md += "\n";
+
+    for (start, end) in slots {
+        md += &format!("| {} |", time_slot(start, end));
+        for &day in &days {
+            let cell: Vec<String> = week.lessons_on(day).iter()
+                .filter(|lesson| lesson.start == start && lesson.end == end)
+                .map(|lesson| if lesson.room.is_empty() {
+                    escape(&lesson.subject)
+                }
+                else {
+                    format!("{} ({})", escape(&lesson.subject), escape(&lesson.room))
+                })
+                .collect();
+            md += &format!(" {} |", cell.join("<br>"));
+        }
+        md += "\n";
+    }
+
+    md
+}
+
+fn time_slot(start: NaiveTime, end: NaiveTime) -> String {
+    format!("{}–{}", start.format("%H:%M"), end.format("%H:%M"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|")
+}
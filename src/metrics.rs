@@ -0,0 +1,208 @@
+#[cfg(feature = "metrics")]
+use std::collections::BTreeMap;
+#[cfg(feature = "metrics")]
+use std::fmt::Write;
+#[cfg(feature = "metrics")]
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::Endpoint;
+
+// What a request to skola24 cost, reported after every response that made it over the network.
+// Cache hits are not reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferStats {
+    pub endpoint: Endpoint,
+    pub status: u16,
+    // As sent in `Content-Encoding`, `None` if the body was not compressed
+    pub content_encoding: Option<String>,
+    pub bytes_on_wire: usize,
+    pub bytes_decoded: usize,
+    // From sending the request until the body was decoded
+    pub duration: Duration,
+}
+
+impl TransferStats {
+    // Decoded bytes per byte on the wire, 1 for uncompressed bodies
+    pub fn compression_ratio(&self) -> f64 {
+        if self.bytes_on_wire == 0 {
+            return 1.0;
+        }
+        self.bytes_decoded as f64 / self.bytes_on_wire as f64
+    }
+}
+
+type TransferHook = Box<dyn Fn(&TransferStats) + Send + Sync>;
+
+static TRANSFER_HOOK: RwLock<Option<TransferHook>> = RwLock::new(None);
+
+// Called from whatever task made the request, so it should be quick, e.g. add to counters
+pub fn set_transfer_hook(hook: impl Fn(&TransferStats) + Send + Sync + 'static) {
+    if let Ok(mut current) = TRANSFER_HOOK.write() {
+        *current = Some(Box::new(hook));
+    }
+}
+
+pub fn clear_transfer_hook() {
+    if let Ok(mut current) = TRANSFER_HOOK.write() {
+        *current = None;
+    }
+}
+
+#[cfg(feature = "net")]
+pub(crate) fn record(stats: &TransferStats) {
+    debug!("{}: {} bytes on the wire, {} decoded ({:?}) in {:?}", stats.endpoint.path(), stats.bytes_on_wire, stats.bytes_decoded, stats.content_encoding, stats.duration);
+    #[cfg(feature = "metrics")]
+    if let Ok(mut registry) = REGISTRY.lock() {
+        let path = stats.endpoint.path();
+        *registry.requests.entry((path, stats.status)).or_default() += 1;
+        *registry.bytes.entry((path, "wire")).or_default() += stats.bytes_on_wire as u64;
+        *registry.bytes.entry((path, "decoded")).or_default() += stats.bytes_decoded as u64;
+        registry.upstream.entry(path).or_insert_with(|| Histogram::new(UPSTREAM_BUCKETS)).observe(stats.duration);
+    }
+    if let Ok(hook) = TRANSFER_HOOK.read() {
+        if let Some(hook) = hook.as_ref() {
+            hook(stats);
+        }
+    }
+}
+
+// No-ops without the metrics feature, so the call sites need no cfg
+#[cfg(feature = "net")]
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_cache_lookup(endpoint: Endpoint, hit: bool) {
+    #[cfg(feature = "metrics")]
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.cache.entry((endpoint.path(), if hit {"hit"} else {"miss"})).or_default() += 1;
+    }
+}
+
+#[cfg(feature = "svg")]
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_render(format: &'static str, duration: Duration) {
+    #[cfg(feature = "metrics")]
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.render.entry(format).or_insert_with(|| Histogram::new(RENDER_BUCKETS)).observe(duration);
+    }
+}
+
+// Upper bounds in seconds
+#[cfg(all(feature = "metrics", feature = "net"))]
+const UPSTREAM_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+#[cfg(all(feature = "metrics", feature = "svg"))]
+const RENDER_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0];
+
+#[cfg(feature = "metrics")]
+struct Histogram {
+    buckets: &'static [f64],
+    // Not cumulative, one per bucket and the last for +Inf
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[cfg(feature = "metrics")]
+impl Histogram {
+    #[cfg(any(feature = "net", feature = "svg"))]
+    fn new(buckets: &'static [f64]) -> Self {
+        Histogram {
+            buckets,
+            counts: vec![0; buckets.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    #[cfg(any(feature = "net", feature = "svg"))]
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = self.buckets.iter().position(|bound| seconds <= *bound).unwrap_or(self.buckets.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn write(&self, out: &mut String, name: &str, label: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.buckets.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, label, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, label, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, label, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, label, self.count);
+    }
+}
+
+#[cfg(feature = "metrics")]
+struct Registry {
+    requests: BTreeMap<(&'static str, u16), u64>,
+    cache: BTreeMap<(&'static str, &'static str), u64>,
+    bytes: BTreeMap<(&'static str, &'static str), u64>,
+    upstream: BTreeMap<&'static str, Histogram>,
+    render: BTreeMap<&'static str, Histogram>,
+}
+
+#[cfg(feature = "metrics")]
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    requests: BTreeMap::new(),
+    cache: BTreeMap::new(),
+    bytes: BTreeMap::new(),
+    upstream: BTreeMap::new(),
+    render: BTreeMap::new(),
+});
+
+// Everything counted since the start of the process or the last `reset`, in the Prometheus
+// text exposition format, to be served from e.g. `/metrics`
+#[cfg(feature = "metrics")]
+pub fn prometheus() -> String {
+    let registry = match REGISTRY.lock() {
+        Ok(registry) => registry,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut out = String::new();
+
+    out += "# HELP skola24_requests_total Responses from skola24 by endpoint and HTTP status.\n";
+    out += "# TYPE skola24_requests_total counter\n";
+    for ((endpoint, status), count) in &registry.requests {
+        let _ = writeln!(out, "skola24_requests_total{{endpoint=\"{}\",status=\"{}\"}} {}", endpoint, status, count);
+    }
+
+    out += "# HELP skola24_cache_lookups_total Cache lookups by endpoint and result.\n";
+    out += "# TYPE skola24_cache_lookups_total counter\n";
+    for ((endpoint, result), count) in &registry.cache {
+        let _ = writeln!(out, "skola24_cache_lookups_total{{endpoint=\"{}\",result=\"{}\"}} {}", endpoint, result, count);
+    }
+
+    out += "# HELP skola24_transfer_bytes_total Response bytes by endpoint, as sent and after decompression.\n";
+    out += "# TYPE skola24_transfer_bytes_total counter\n";
+    for ((endpoint, kind), bytes) in &registry.bytes {
+        let _ = writeln!(out, "skola24_transfer_bytes_total{{endpoint=\"{}\",kind=\"{}\"}} {}", endpoint, kind, bytes);
+    }
+
+    out += "# HELP skola24_upstream_seconds Time from sending a request to skola24 until its body was read.\n";
+    out += "# TYPE skola24_upstream_seconds histogram\n";
+    for (endpoint, histogram) in &registry.upstream {
+        histogram.write(&mut out, "skola24_upstream_seconds", &format!("endpoint=\"{}\"", endpoint));
+    }
+
+    out += "# HELP libschedule24_render_seconds Time spent rendering schedules by output format.\n";
+    out += "# TYPE libschedule24_render_seconds histogram\n";
+    for (format, histogram) in &registry.render {
+        histogram.write(&mut out, "libschedule24_render_seconds", &format!("format=\"{}\"", format));
+    }
+
+    out
+}
+
+#[cfg(feature = "metrics")]
+pub fn reset() {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.requests.clear();
+        registry.cache.clear();
+        registry.bytes.clear();
+        registry.upstream.clear();
+        registry.render.clear();
+    }
+}
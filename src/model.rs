@@ -0,0 +1,383 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::RwLock;
+
+use crate::data::{LessonId, LessonInfo, Schema};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeRange {
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        TimeRange { start, end }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        self.start <= time && time < self.end
+    }
+
+    pub fn overlaps(&self, other: &TimeRange) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+// A lesson as parsed from the API, independent of how the API laid it out
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Lesson {
+    pub id: LessonId,
+    pub weekday: Weekday,
+    pub date: Option<NaiveDate>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    // The API lists the subject first, followed by teacher and room. Missing ones are empty.
+    pub subject: String,
+    pub teacher: String,
+    pub room: String,
+    // All texts the API sent, some schools add more than the three above
+    pub texts: Vec<String>,
+    pub block: Option<String>,
+    // Background color of the lesson's box, e.g. "#FFC0CB"
+    pub color: Option<String>,
+    // Selection GUIDs this lesson was fetched for, filled in for merged schedules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+}
+
+// By day and start time, so sorting gives the order the lessons take place in
+impl Ord for Lesson {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.sort_key().cmp(&other.sort_key())
+            .then_with(|| (&self.subject, &self.teacher, &self.room, &self.texts, &self.block, &self.color, &self.sources)
+                .cmp(&(&other.subject, &other.teacher, &other.room, &other.texts, &other.block, &other.color, &other.sources)))
+    }
+}
+
+impl PartialOrd for Lesson {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Lesson {
+    fn sort_key(&self) -> (Option<NaiveDate>, u32, NaiveTime, NaiveTime, &LessonId) {
+        (self.date, self.weekday.num_days_from_monday(), self.start, self.end, &self.id)
+    }
+
+    // Uses the color of `info.block`, so associate the lesson with its box first.
+    // The date is left unresolved.
+    pub fn from_info(info: &LessonInfo) -> Result<Lesson, LessonWarning> {
+        let invalid_time = |value: &str| LessonWarning::InvalidTime {
+            guid: info.guid_id.clone(),
+            value: value.to_string(),
+        };
+        let start = info.start_time().map_err(|_| invalid_time(&info.time_start))?;
+        let end = info.end_time().map_err(|_| invalid_time(&info.time_end))?;
+        let weekday = info.weekday().ok_or_else(|| LessonWarning::InvalidWeekday {
+            guid: info.guid_id.clone(),
+            value: info.day_of_week_number,
+        })?;
+        let text = |i: usize| info.texts.get(i).cloned().unwrap_or_default();
+
+        Ok(Lesson {
+            id: info.guid_id.clone(),
+            weekday,
+            date: info.date,
+            start,
+            end,
+            subject: text(0),
+            teacher: text(1),
+            room: text(2),
+            texts: info.texts.clone(),
+            block: Some(info.block_name.clone()).filter(|block| !block.is_empty()),
+            color: Some(info.block.b_color.to_string()).filter(|color| !color.is_empty()),
+            sources: info.sources.clone(),
+        })
+    }
+
+    pub fn time_range(&self) -> TimeRange {
+        TimeRange::new(self.start, self.end)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    pub fn start_datetime(&self) -> Option<NaiveDateTime> {
+        Some(self.date?.and_time(self.start))
+    }
+
+    pub fn end_datetime(&self) -> Option<NaiveDateTime> {
+        Some(self.date?.and_time(self.end))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LessonWarning {
+    // A second entry with the same GUID, day and start time was dropped
+    Duplicate { guid: LessonId },
+    InvalidTime { guid: LessonId, value: String },
+    InvalidWeekday { guid: LessonId, value: i64 },
+    EndBeforeStart { guid: LessonId },
+}
+
+impl fmt::Display for LessonWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for LessonWarning {}
+
+// Sorts lessons by day and start time, drops duplicates and reports reversed time ranges
+pub fn normalize(lessons: &mut Vec<Lesson>) -> Vec<LessonWarning> {
+    let mut warnings = Vec::new();
+
+    let mut seen = HashSet::new();
+    lessons.retain(|lesson| {
+        let unique = seen.insert((lesson.id.clone(), lesson.weekday.num_days_from_monday(), lesson.start));
+        if !unique {
+            warnings.push(LessonWarning::Duplicate { guid: lesson.id.clone() });
+        }
+        unique
+    });
+
+    for lesson in lessons.iter().filter(|lesson| lesson.end <= lesson.start) {
+        warnings.push(LessonWarning::EndBeforeStart { guid: lesson.id.clone() });
+    }
+
+    lessons.sort_by_key(|lesson| (lesson.weekday.num_days_from_monday(), lesson.start, lesson.end));
+
+    warnings
+}
+
+// Converts a rendered schema for the given ISO week. Lessons that can't be parsed are left
+// out and reported next to the duplicates `normalize` drops.
+pub fn from_schema(schema: &Schema, year: i32, week: u32) -> (WeekSchedule, Vec<LessonWarning>) {
+    let mut warnings = Vec::new();
+    let mut lessons = Vec::new();
+    for (info, lesson_box) in schema.lessons_with_boxes() {
+        match Lesson::from_info(info) {
+            Ok(mut lesson) => {
+                if let Some(b) = lesson_box {
+                    lesson.color = Some(b.b_color.to_string()).filter(|color| !color.is_empty());
+                }
+                lessons.push(lesson);
+            },
+            Err(warning) => warnings.push(warning),
+        }
+    }
+
+    apply_lesson_hooks(&mut lessons);
+    warnings.extend(normalize(&mut lessons));
+
+    (WeekSchedule::new(year, week, lessons), warnings)
+}
+
+type LessonTransform = Box<dyn Fn(&mut Lesson) + Send + Sync>;
+type LessonFilter = Box<dyn Fn(&Lesson) -> bool + Send + Sync>;
+
+#[derive(Default)]
+struct LessonHooks {
+    transforms: Vec<LessonTransform>,
+    filters: Vec<LessonFilter>,
+}
+
+static LESSON_HOOKS: RwLock<LessonHooks> = RwLock::new(LessonHooks {
+    transforms: Vec::new(),
+    filters: Vec::new(),
+});
+
+// Runs on every lesson `from_schema` parses, before duplicates are dropped and the lessons are
+// sorted, e.g. to rename subjects or tag lessons. Transforms run in the order they were added.
+pub fn add_lesson_transform(transform: impl Fn(&mut Lesson) + Send + Sync + 'static) {
+    if let Ok(mut hooks) = LESSON_HOOKS.write() {
+        hooks.transforms.push(Box::new(transform));
+    }
+}
+
+// Drops the lessons `keep` returns false for, e.g. mentor time. Runs after the transforms.
+pub fn add_lesson_filter(keep: impl Fn(&Lesson) -> bool + Send + Sync + 'static) {
+    if let Ok(mut hooks) = LESSON_HOOKS.write() {
+        hooks.filters.push(Box::new(keep));
+    }
+}
+
+pub fn clear_lesson_hooks() {
+    if let Ok(mut hooks) = LESSON_HOOKS.write() {
+        *hooks = LessonHooks::default();
+    }
+}
+
+// What `from_schema` does with the added transforms and filters, for lessons built elsewhere
+pub fn apply_lesson_hooks(lessons: &mut Vec<Lesson>) {
+    if let Ok(hooks) = LESSON_HOOKS.read() {
+        for lesson in lessons.iter_mut() {
+            for transform in &hooks.transforms {
+                transform(lesson);
+            }
+        }
+        lessons.retain(|lesson| hooks.filters.iter().all(|keep| keep(lesson)));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DaySchedule {
+    pub weekday: Weekday,
+    pub date: Option<NaiveDate>,
+    // Sorted by start time
+    pub lessons: Vec<Lesson>,
+}
+
+impl DaySchedule {
+    pub fn new(weekday: Weekday, date: Option<NaiveDate>) -> Self {
+        DaySchedule {
+            weekday,
+            date,
+            lessons: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lessons.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a DaySchedule {
+    type Item = &'a Lesson;
+    type IntoIter = std::slice::Iter<'a, Lesson>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.lessons.iter()
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WeekSchedule {
+    pub year: i32,
+    pub week: u32,
+    // Only days with lessons, Monday first
+    pub days: Vec<DaySchedule>,
+}
+
+impl WeekSchedule {
+    // Groups the lessons into days and resolves their dates
+    pub fn new(year: i32, week: u32, lessons: Vec<Lesson>) -> Self {
+        let mut schedule = WeekSchedule {
+            year,
+            week,
+            days: Vec::new(),
+        };
+        schedule.extend(lessons);
+        schedule
+    }
+
+    pub fn day(&self, weekday: Weekday) -> Option<&DaySchedule> {
+        self.days.iter().find(|day| day.weekday == weekday)
+    }
+
+    // The lessons of `weekday`, empty if it has none
+    pub fn lessons_on(&self, weekday: Weekday) -> &[Lesson] {
+        self.day(weekday).map_or(&[], |day| day.lessons.as_slice())
+    }
+
+    pub fn lessons(&self) -> impl Iterator<Item = &Lesson> {
+        self.into_iter()
+    }
+
+    // The lessons `merge` added for `source`, e.g. one child of a merged family schedule
+    pub fn lessons_from<'a>(&'a self, source: &'a str) -> impl Iterator<Item = &'a Lesson> {
+        self.lessons().filter(move |lesson| lesson.sources.iter().any(|s| s == source))
+    }
+
+    pub fn date_of(&self, weekday: Weekday) -> Option<NaiveDate> {
+        NaiveDate::from_isoywd_opt(self.year, self.week, weekday)
+    }
+
+    pub fn normalize(&mut self) -> Vec<LessonWarning> {
+        let mut warnings = Vec::new();
+        for day in &mut self.days {
+            warnings.extend(normalize(&mut day.lessons));
+        }
+        warnings
+    }
+
+    // Adds lessons to their days, keeping days and lessons sorted
+    pub fn extend(&mut self, lessons: impl IntoIterator<Item = Lesson>) {
+        for mut lesson in lessons {
+            let date = self.date_of(lesson.weekday);
+            lesson.date = date;
+            match self.days.iter_mut().find(|day| day.weekday == lesson.weekday) {
+                Some(day) => day.lessons.push(lesson),
+                None => {
+                    let mut day = DaySchedule::new(lesson.weekday, date);
+                    day.lessons.push(lesson);
+                    self.days.push(day);
+                },
+            }
+        }
+
+        self.days.sort_by_key(|day| day.weekday.num_days_from_monday());
+        for day in &mut self.days {
+            day.lessons.sort_by_key(|lesson| (lesson.start, lesson.end));
+        }
+    }
+
+    // Adds lessons from another selection, merging lessons both selections share
+    pub fn merge(&mut self, other: WeekSchedule, source: &str) {
+        let mut added = Vec::new();
+        for mut lesson in other.days.into_iter().flat_map(|day| day.lessons) {
+            let existing = self.days.iter_mut()
+                .flat_map(|day| day.lessons.iter_mut())
+                .find(|l| l.id == lesson.id && l.weekday == lesson.weekday && l.start == lesson.start);
+
+            match existing {
+                Some(existing) => existing.sources.push(source.to_string()),
+                None => {
+                    lesson.sources.push(source.to_string());
+                    added.push(lesson);
+                },
+            }
+        }
+
+        self.extend(added);
+    }
+}
+
+impl<'a> IntoIterator for &'a WeekSchedule {
+    type Item = &'a Lesson;
+    type IntoIter = std::iter::Flatten<std::slice::Iter<'a, DaySchedule>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.days.iter().flatten()
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TermSchedule {
+    pub weeks: Vec<WeekSchedule>,
+}
+
+impl TermSchedule {
+    pub fn lessons(&self) -> impl Iterator<Item = &Lesson> {
+        self.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TermSchedule {
+    type Item = &'a Lesson;
+    type IntoIter = std::iter::Flatten<std::slice::Iter<'a, WeekSchedule>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.weeks.iter().flatten()
+    }
+}
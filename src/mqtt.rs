@@ -0,0 +1,259 @@
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::TcpStream;
+use chrono::{Datelike, NaiveTime};
+use serde::Serialize;
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+
+use crate::diff::{self, ScheduleChange};
+use crate::homeassistant::SensorLesson;
+use crate::model::{Lesson, WeekSchedule};
+use crate::timezone::{self, SCHEDULE_TIMEZONE};
+use crate::{fetch_schedule, query, RequestError, Selection};
+
+// Publishes to an MQTT 3.1.1 broker with QoS 0, which is all dashboards need. Only the
+// packets for that are implemented, so nothing beyond the standard library and async-std is
+// pulled in.
+
+#[derive(Debug)]
+pub enum MqttError {
+    Io(std::io::Error),
+    Request(RequestError),
+    Serde(serde_json::Error),
+    // The CONNACK return code, e.g. 5 for bad credentials
+    Refused(u8),
+    Protocol(String),
+}
+
+impl fmt::Display for MqttError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for MqttError {}
+
+impl From<std::io::Error> for MqttError {
+    fn from(v: std::io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+impl From<RequestError> for MqttError {
+    fn from(v: RequestError) -> Self {
+        Self::Request(v)
+    }
+}
+
+impl From<serde_json::Error> for MqttError {
+    fn from(v: serde_json::Error) -> Self {
+        Self::Serde(v)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttTopics {
+    // The lesson in progress or `null`, retained
+    pub current: String,
+    // The next lesson today or `null`, retained
+    pub next: String,
+    // Arrays of `diff::ScheduleChange`, not retained
+    pub changes: String,
+}
+
+impl MqttTopics {
+    pub fn with_prefix(prefix: &str) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        MqttTopics {
+            current: format!("{}/current", prefix),
+            next: format!("{}/next", prefix),
+            changes: format!("{}/changes", prefix),
+        }
+    }
+}
+
+impl Default for MqttTopics {
+    fn default() -> Self {
+        MqttTopics::with_prefix(env!("CARGO_PKG_NAME"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MqttOptions {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub credentials: Option<(String, String)>,
+    // Seconds, 0 turns the broker's keep alive check off since no pings are sent
+    pub keep_alive: u16,
+    pub topics: MqttTopics,
+}
+
+impl Default for MqttOptions {
+    fn default() -> Self {
+        MqttOptions {
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: env!("CARGO_PKG_NAME").to_string(),
+            credentials: None,
+            keep_alive: 0,
+            topics: MqttTopics::default(),
+        }
+    }
+}
+
+impl MqttOptions {
+    pub fn host<S: Into<String>>(mut self, host: S, port: u16) -> Self {
+        self.host = host.into();
+        self.port = port;
+        self
+    }
+
+    pub fn client_id<S: Into<String>>(mut self, client_id: S) -> Self {
+        self.client_id = client_id.into();
+        self
+    }
+
+    pub fn credentials<S: Into<String>, T: Into<String>>(mut self, username: S, password: T) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    pub fn topics(mut self, topics: MqttTopics) -> Self {
+        self.topics = topics;
+        self
+    }
+}
+
+pub struct MqttPublisher {
+    stream: TcpStream,
+    topics: MqttTopics,
+}
+
+impl MqttPublisher {
+    pub async fn connect(options: &MqttOptions) -> Result<Self, MqttError> {
+        let mut stream = TcpStream::connect((options.host.as_str(), options.port)).await?;
+
+        // Clean session, with username and password if given
+        let mut flags = 0x02;
+        let mut packet = Vec::new();
+        put_string(&mut packet, "MQTT")?;
+        packet.push(4);
+        if options.credentials.is_some() {
+            flags |= 0xc0;
+        }
+        packet.push(flags);
+        packet.extend_from_slice(&options.keep_alive.to_be_bytes());
+        put_string(&mut packet, &options.client_id)?;
+        if let Some((username, password)) = &options.credentials {
+            put_string(&mut packet, username)?;
+            put_string(&mut packet, password)?;
+        }
+        stream.write_all(&frame(0x10, &packet)?).await?;
+
+        let mut connack = [0; 4];
+        stream.read_exact(&mut connack).await?;
+        if connack[0] != 0x20 || connack[1] != 2 {
+            return Err(MqttError::Protocol(format!("expected CONNACK, got {:02x?}", connack)));
+        }
+        if connack[3] != 0 {
+            return Err(MqttError::Refused(connack[3]));
+        }
+
+        Ok(MqttPublisher {
+            stream,
+            topics: options.topics.clone(),
+        })
+    }
+
+    pub async fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), MqttError> {
+        let mut packet = Vec::new();
+        put_string(&mut packet, topic)?;
+        packet.extend_from_slice(payload);
+        self.stream.write_all(&frame(if retain {0x31} else {0x30}, &packet)?).await?;
+        Ok(())
+    }
+
+    async fn publish_json<T: Serialize>(&mut self, topic: &str, value: &T, retain: bool) -> Result<(), MqttError> {
+        let payload = serde_json::to_vec(value)?;
+        self.publish(topic, &payload, retain).await
+    }
+
+    // `lessons` of one day, `at` in `SCHEDULE_TIMEZONE`
+    pub async fn publish_lessons(&mut self, lessons: &[Lesson], at: NaiveTime) -> Result<(), MqttError> {
+        let current = query::current_lesson(lessons, at).map(SensorLesson::from_lesson);
+        let next = query::next_lesson(lessons, at).map(SensorLesson::from_lesson);
+        let topics = self.topics.clone();
+
+        self.publish_json(&topics.current, &current, true).await?;
+        self.publish_json(&topics.next, &next, true).await
+    }
+
+    pub async fn publish_changes(&mut self, changes: &[ScheduleChange]) -> Result<(), MqttError> {
+        let topic = self.topics.changes.clone();
+        self.publish_json(&topic, &changes, false).await
+    }
+
+    pub async fn disconnect(mut self) -> Result<(), MqttError> {
+        self.stream.write_all(&[0xe0, 0]).await?;
+        Ok(())
+    }
+}
+
+// Republishes the current and next lesson every `interval` and any changes to the week since
+// the previous poll, like `watch::watch`. Only returns on errors.
+pub async fn publish_loop(selection: Selection, options: &MqttOptions, interval: Duration) -> Result<(), MqttError> {
+    let mut publisher = MqttPublisher::connect(options).await?;
+    let mut previous: Option<WeekSchedule> = None;
+
+    loop {
+        let now = timezone::now_in(SCHEDULE_TIMEZONE);
+        let (year, week) = (now.iso_week().year(), now.iso_week().week());
+        let previous_week = previous.as_ref().filter(|previous| (previous.year, previous.week) == (year, week));
+        // Starts from the cached week, later polls ask the API. Day 0 makes the API return the whole week.
+        let schedule = fetch_schedule(selection.clone(), 0, year, week, previous_week.is_none()).await?;
+
+        if let Some(previous) = previous_week {
+            let changes = diff::diff_schedules(previous, &schedule);
+            if !changes.is_empty() {
+                publisher.publish_changes(&changes).await?;
+            }
+        }
+        publisher.publish_lessons(schedule.lessons_on(now.weekday()), now.time()).await?;
+
+        previous = Some(schedule);
+        async_std::task::sleep(interval).await;
+    }
+}
+
+fn put_string(packet: &mut Vec<u8>, value: &str) -> Result<(), MqttError> {
+    let len = u16::try_from(value.len()).map_err(|_| MqttError::Protocol(format!("string of {} bytes is too long", value.len())))?;
+    packet.extend_from_slice(&len.to_be_bytes());
+    packet.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+// Prepends the fixed header, the remaining length is a base 128 varint of at most 4 bytes
+fn frame(header: u8, packet: &[u8]) -> Result<Vec<u8>, MqttError> {
+    if packet.len() > 268_435_455 {
+        return Err(MqttError::Protocol(format!("packet of {} bytes is too long", packet.len())));
+    }
+
+    let mut out = vec![header];
+    let mut len = packet.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(packet);
+    Ok(out)
+}
@@ -0,0 +1,154 @@
+use chrono::{Datelike, Duration as ChronoDuration};
+
+use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
+
+use crate::diff::{self, ScheduleChange};
+use crate::model::{Lesson, WeekSchedule};
+use crate::timezone::{self, SCHEDULE_TIMEZONE};
+use crate::{fetch_schedule, RequestError, Selection};
+
+#[derive(Debug)]
+pub enum NotifyError {
+    Request(RequestError),
+    Notification(notify_rust::error::Error),
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for NotifyError {}
+
+impl From<RequestError> for NotifyError {
+    fn from(v: RequestError) -> Self {
+        Self::Request(v)
+    }
+}
+
+impl From<notify_rust::error::Error> for NotifyError {
+    fn from(v: notify_rust::error::Error) -> Self {
+        Self::Notification(v)
+    }
+}
+
+// Desktop notifications through notify-rust, over D-Bus on Linux and the BSDs and with the
+// system's notification centers on macOS and Windows
+pub fn send_notification(summary: &str, body: &str) -> Result<(), NotifyError> {
+    notify_rust::Notification::new()
+        .appname(env!("CARGO_PKG_NAME"))
+        .summary(summary)
+        .body(body)
+        .show()?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotifyOptions {
+    // Minutes before a lesson starts to notify about it
+    pub minutes_before: u32,
+    // How often the schedule is fetched to look for changes
+    pub poll_interval: Duration,
+    pub notify_changes: bool,
+}
+
+impl Default for NotifyOptions {
+    fn default() -> Self {
+        NotifyOptions {
+            minutes_before: 5,
+            poll_interval: Duration::from_secs(15 * 60),
+            notify_changes: true,
+        }
+    }
+}
+
+impl NotifyOptions {
+    pub fn minutes_before(mut self, minutes: u32) -> Self {
+        self.minutes_before = minutes;
+        self
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn notify_changes(mut self, notify_changes: bool) -> Self {
+        self.notify_changes = notify_changes;
+        self
+    }
+}
+
+pub fn lesson_notification(lesson: &Lesson) -> (String, String) {
+    let summary = format!("{} at {}", lesson.subject, lesson.start.format("%H:%M"));
+    let body = [lesson.room.as_str(), lesson.teacher.as_str()].iter()
+        .filter(|text| !text.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ");
+    (summary, body)
+}
+
+pub fn change_notification(change: &ScheduleChange) -> (String, String) {
+    let when = |lesson: &Lesson| format!("{} {}", lesson.weekday, lesson.start.format("%H:%M"));
+    match change {
+        ScheduleChange::Added(lesson) => (format!("New lesson: {}", lesson.subject), when(lesson)),
+        ScheduleChange::Removed(lesson) => (format!("Cancelled: {}", lesson.subject), when(lesson)),
+        ScheduleChange::Moved { old, new } => (format!("Moved: {}", new.subject), format!("{} → {}", when(old), when(new))),
+        ScheduleChange::RoomChanged { old, new } => (format!("Room changed: {}", new.subject), format!("{} → {}", old.room, new.room)),
+    }
+}
+
+// Notifies `options.minutes_before` every lesson of the selection and, if enabled, about
+// changes to the current week. Checks the clock every minute and only returns on errors,
+// also when a notification can't be shown.
+pub async fn notify_loop(selection: Selection, options: &NotifyOptions) -> Result<(), NotifyError> {
+    let tick = Duration::from_secs(60);
+    let mut schedule: Option<WeekSchedule> = None;
+    let mut since_poll = options.poll_interval;
+    // Lessons by ID and date that were already announced
+    let mut notified: HashSet<(String, chrono::NaiveDate)> = HashSet::new();
+
+    loop {
+        let now = timezone::now_in(SCHEDULE_TIMEZONE);
+        let (year, week) = (now.iso_week().year(), now.iso_week().week());
+        let is_current = |schedule: &WeekSchedule| (schedule.year, schedule.week) == (year, week);
+
+        if since_poll >= options.poll_interval || !schedule.as_ref().is_some_and(is_current) {
+            let previous = schedule.take().filter(is_current);
+            // Starts from the cached week, later polls ask the API. Day 0 makes the API return the whole week.
+            let fresh = fetch_schedule(selection.clone(), 0, year, week, previous.is_none()).await?;
+            if let (Some(previous), true) = (&previous, options.notify_changes) {
+                for change in diff::diff_schedules(previous, &fresh) {
+                    let (summary, body) = change_notification(&change);
+                    send_notification(&summary, &body)?;
+                }
+            }
+            schedule = Some(fresh);
+            since_poll = Duration::from_secs(0);
+        }
+
+        if let Some(schedule) = &schedule {
+            let lead = ChronoDuration::minutes(options.minutes_before.into());
+            for lesson in schedule.lessons_on(now.weekday()) {
+                let start = match lesson.start_datetime() {
+                    Some(start) => start,
+                    None => continue,
+                };
+                let due = start - lead <= now && now < start;
+                if due && notified.insert((lesson.id.to_string(), start.date())) {
+                    let (summary, body) = lesson_notification(lesson);
+                    send_notification(&summary, &body)?;
+                }
+            }
+        }
+        // Lessons of past days can't be due again
+        notified.retain(|(_, date)| *date >= now.date());
+
+        async_std::task::sleep(tick).await;
+        since_poll += tick;
+    }
+}
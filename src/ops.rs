@@ -0,0 +1,536 @@
+use chrono::{Datelike, NaiveDate};
+use futures::stream::{self, StreamExt};
+
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::timezone::{self, SCHEDULE_TIMEZONE};
+use crate::diff::{diff_schedules, ScheduleChange};
+use crate::{fetch_schedule, get_classes, get_school, get_term_schedule_in, model, request_year, RequestError, Selection, SelectionTarget};
+
+// One call end-to-end operations for binaries, e.g.
+// `ops::render_week_to_file(selection, 45, "week.svg", ops::ExportFormat::Svg).await`.
+// Everything goes through the cache.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    #[cfg(feature = "svg")]
+    Svg,
+    #[cfg(feature = "png")]
+    Png,
+    #[cfg(feature = "pdf")]
+    Pdf,
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+    #[cfg(feature = "ics")]
+    Ics,
+    Json,
+    Csv,
+    Html,
+    Markdown,
+    Text,
+}
+
+impl ExportFormat {
+    // By file extension, `None` for unknown ones and formats whose feature is disabled
+    pub fn from_path(path: &Path) -> Option<Self> {
+        ExportFormat::from_extension(path.extension()?.to_str()?)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "svg")]
+            ExportFormat::Svg => "svg",
+            #[cfg(feature = "png")]
+            ExportFormat::Png => "png",
+            #[cfg(feature = "pdf")]
+            ExportFormat::Pdf => "pdf",
+            #[cfg(feature = "xlsx")]
+            ExportFormat::Xlsx => "xlsx",
+            #[cfg(feature = "ics")]
+            ExportFormat::Ics => "ics",
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Html => "html",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Text => "txt",
+        }
+    }
+
+    // E.g. "svg", in any case
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Some(match extension.to_ascii_lowercase().as_str() {
+            #[cfg(feature = "svg")]
+            "svg" => ExportFormat::Svg,
+            #[cfg(feature = "png")]
+            "png" => ExportFormat::Png,
+            #[cfg(feature = "pdf")]
+            "pdf" => ExportFormat::Pdf,
+            #[cfg(feature = "xlsx")]
+            "xlsx" => ExportFormat::Xlsx,
+            #[cfg(feature = "ics")]
+            "ics" => ExportFormat::Ics,
+            "json" => ExportFormat::Json,
+            "csv" => ExportFormat::Csv,
+            "html" | "htm" => ExportFormat::Html,
+            "md" => ExportFormat::Markdown,
+            "txt" => ExportFormat::Text,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum OpsError {
+    Request(RequestError),
+    Io(std::io::Error),
+    // A renderer or exporter failed, with its error
+    Export(String),
+    // No format given and none known for the file extension
+    UnknownFormat(PathBuf),
+    #[cfg(feature = "config")]
+    Config(crate::config::ConfigError),
+    #[cfg(feature = "archive")]
+    Zip(zip::result::ZipError),
+}
+
+impl fmt::Display for OpsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for OpsError {}
+
+impl From<RequestError> for OpsError {
+    fn from(v: RequestError) -> Self {
+        Self::Request(v)
+    }
+}
+
+impl From<std::io::Error> for OpsError {
+    fn from(v: std::io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+#[cfg(feature = "config")]
+impl From<crate::config::ConfigError> for OpsError {
+    fn from(v: crate::config::ConfigError) -> Self {
+        Self::Config(v)
+    }
+}
+
+#[cfg(feature = "archive")]
+impl From<zip::result::ZipError> for OpsError {
+    fn from(v: zip::result::ZipError) -> Self {
+        Self::Zip(v)
+    }
+}
+
+fn export_error(error: impl fmt::Debug) -> OpsError {
+    OpsError::Export(format!("{:?}", error))
+}
+
+// The week in `format`, drawn at the default dimensions for images
+pub async fn render_week(selection: impl Into<Selection>, week: i32, format: ExportFormat) -> Result<Vec<u8>, OpsError> {
+    render_week_in(selection.into(), request_year(), week, format).await
+}
+
+// The week of the ISO year `year`
+async fn render_week_in(selection: Selection, year: i32, week: i32, format: ExportFormat) -> Result<Vec<u8>, OpsError> {
+    #[cfg(feature = "svg")]
+    {
+        let image = matches!(format, ExportFormat::Svg);
+        #[cfg(feature = "png")]
+        let image = image || matches!(format, ExportFormat::Png);
+        if image {
+            return render_week_image(selection, year, week, format).await;
+        }
+    }
+
+    // Day 0 makes the API return the whole week
+    let schedule = fetch_schedule(selection, 0, year, week as u32, true).await?;
+    export_weeks(std::slice::from_ref(&schedule), format)
+}
+
+#[cfg(feature = "svg")]
+async fn render_week_image(selection: Selection, year: i32, week: i32, format: ExportFormat) -> Result<Vec<u8>, OpsError> {
+    use crate::image::SvgOptions;
+    use crate::{get_schema_in, Dimensions, SchemaRequestOptions};
+
+    // Day 0 makes the API return the whole week
+    let schema = get_schema_in(selection, 0, year, week, None, &SchemaRequestOptions::default(), true).await?;
+    match format {
+        #[cfg(feature = "png")]
+        ExportFormat::Png => crate::png::generate_png(&schema.data, Dimensions::default(), &SvgOptions::default(), 96.0).map_err(export_error),
+        _ => crate::image::generate_svg(&schema.data, Dimensions::default(), &SvgOptions::default())
+            .map(|doc| doc.to_string().into_bytes())
+            .map_err(export_error),
+    }
+}
+
+// Formats that only need the lessons, in order of the weeks
+fn export_weeks(weeks: &[model::WeekSchedule], format: ExportFormat) -> Result<Vec<u8>, OpsError> {
+    let lessons = || weeks.iter().flatten();
+    Ok(match format {
+        #[cfg(feature = "svg")]
+        ExportFormat::Svg => return Err(OpsError::Export("SVG is drawn from a schema, not lessons".to_string())),
+        #[cfg(feature = "png")]
+        ExportFormat::Png => return Err(OpsError::Export("PNG is drawn from a schema, not lessons".to_string())),
+        #[cfg(feature = "pdf")]
+        ExportFormat::Pdf => crate::pdf::to_pdf(weeks, &crate::pdf::PdfOptions::default()),
+        #[cfg(feature = "xlsx")]
+        ExportFormat::Xlsx => crate::xlsx::to_xlsx(weeks).map_err(export_error)?,
+        #[cfg(feature = "ics")]
+        ExportFormat::Ics => crate::ics::to_ical(lessons()).into_bytes(),
+        ExportFormat::Json => {
+            let weeks: Vec<crate::json::SimpleSchedule> = weeks.iter().map(Into::into).collect();
+            match weeks.as_slice() {
+                [week] => serde_json::to_vec(week),
+                weeks => serde_json::to_vec(weeks),
+            }.map_err(export_error)?
+        },
+        ExportFormat::Csv => crate::csv::to_csv(lessons()).into_bytes(),
+        ExportFormat::Html => weeks.iter().map(crate::html::to_html).collect::<String>().into_bytes(),
+        ExportFormat::Markdown => weeks.iter().map(crate::markdown::week_to_markdown).collect::<Vec<_>>().join("\n").into_bytes(),
+        ExportFormat::Text => weeks.iter()
+            .map(|week| crate::text::week_to_text(week, &crate::text::TextGridOptions::default()))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into_bytes(),
+    })
+}
+
+// `format` `None` picks it by the extension of `path`
+pub async fn render_week_to_file(selection: impl Into<Selection>, week: i32, path: impl AsRef<Path>, format: impl Into<Option<ExportFormat>>) -> Result<(), OpsError> {
+    let path = path.as_ref();
+    let format = format.into().or_else(|| ExportFormat::from_path(path)).ok_or_else(|| OpsError::UnknownFormat(path.to_path_buf()))?;
+    let bytes = render_week(selection, week, format).await?;
+    async_std::fs::write(path, bytes).await?;
+    Ok(())
+}
+
+// The ISO year and weeks of the Swedish school term `date` falls in: the autumn term from
+// week 33 to 51 and the spring term from week 2 to 24. The summer counts to the coming autumn
+// term, the holidays around new year to the term of the same ISO year, so the last days of
+// December can belong to the spring term of the next year.
+pub fn term_weeks(date: NaiveDate) -> (i32, std::ops::RangeInclusive<i32>) {
+    let week = date.iso_week();
+    let weeks = match week.week() {
+        1..=24 => 2..=24,
+        _ => 33..=51,
+    };
+    (week.year(), weeks)
+}
+
+// The current term as one calendar. Weeks already past are included so the file can replace
+// an earlier export.
+#[cfg(feature = "ics")]
+pub async fn export_term_ics(selection: impl Into<Selection>, path: impl AsRef<Path>) -> Result<(), OpsError> {
+    let (year, weeks) = term_weeks(timezone::now_in(SCHEDULE_TIMEZONE).date());
+    let term = get_term_schedule_in(selection, year, weeks, true).await?;
+    async_std::fs::write(path.as_ref(), crate::ics::to_ical(&term)).await?;
+    Ok(())
+}
+
+// Like `export_term_ics` in any format that works from lessons
+pub async fn export_term(selection: impl Into<Selection>, path: impl AsRef<Path>, format: impl Into<Option<ExportFormat>>) -> Result<(), OpsError> {
+    let path = path.as_ref();
+    let format = format.into().or_else(|| ExportFormat::from_path(path)).ok_or_else(|| OpsError::UnknownFormat(path.to_path_buf()))?;
+    let (year, weeks) = term_weeks(timezone::now_in(SCHEDULE_TIMEZONE).date());
+    let term = get_term_schedule_in(selection, year, weeks, true).await?;
+    async_std::fs::write(path, export_weeks(&term.weeks, format)?).await?;
+    Ok(())
+}
+
+// A file kept up to date by `refresh`. ICS files get the whole current term, so calendars
+// subscribed to them keep the past weeks, the other formats the current week.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub path: PathBuf,
+    pub format: ExportFormat,
+}
+
+impl Output {
+    // `format` `None` picks it by the extension of `path`
+    pub fn new(path: impl Into<PathBuf>, format: impl Into<Option<ExportFormat>>) -> Result<Self, OpsError> {
+        let path = path.into();
+        match format.into().or_else(|| ExportFormat::from_path(&path)) {
+            Some(format) => Ok(Output { path, format }),
+            None => Err(OpsError::UnknownFormat(path)),
+        }
+    }
+
+    fn is_term(&self) -> bool {
+        #[cfg(feature = "ics")]
+        return self.format == ExportFormat::Ics;
+        #[cfg(not(feature = "ics"))]
+        return false;
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    // The ISO years and weeks fetched again, in order
+    pub weeks: Vec<(i32, i32)>,
+    // From the lessons cached before to the ones fetched now
+    pub changes: Vec<ScheduleChange>,
+    // Outputs whose content changed, or that did not exist, and were written
+    pub written: Vec<PathBuf>,
+    pub unchanged: Vec<PathBuf>,
+}
+
+impl Report {
+    pub fn is_changed(&self) -> bool {
+        !self.changes.is_empty() || !self.written.is_empty()
+    }
+}
+
+// Fetches the weeks needed for `outputs` past the cache, stores them in it and rewrites the
+// outputs whose content differs. Meant for timers, e.g. a systemd unit running every hour.
+// Weeks that were not cached yet are fetched twice and report no changes.
+pub async fn refresh(selection: impl Into<Selection>, outputs: &[Output]) -> Result<Report, OpsError> {
+    let selection = selection.into();
+    let today = timezone::now_in(SCHEDULE_TIMEZONE).date();
+    let (year, week) = (today.iso_week().year(), today.iso_week().week() as i32);
+    let (term_year, term) = term_weeks(today);
+
+    let mut report = Report::default();
+    if outputs.iter().any(Output::is_term) {
+        report.weeks.extend(term.clone().map(|week| (term_year, week)));
+    }
+    if !report.weeks.contains(&(year, week)) {
+        report.weeks.push((year, week));
+        report.weeks.sort_unstable();
+    }
+
+    for &(year, week) in &report.weeks {
+        // Day 0 makes the API return the whole week
+        let old = fetch_schedule(selection.clone(), 0, year, week as u32, true).await?;
+        // Keeps refreshing if it was turned on before, e.g. by the config
+        let refreshing = crate::CACHE_REFRESH.swap(true, std::sync::atomic::Ordering::Relaxed);
+        let new = fetch_schedule(selection.clone(), 0, year, week as u32, true).await;
+        crate::set_cache_refresh(refreshing);
+        report.changes.extend(diff_schedules(&old, &new?));
+    }
+
+    // Everything below is read from the cache just refreshed
+    for output in outputs {
+        let bytes = if output.is_term() {
+            let term = get_term_schedule_in(selection.clone(), term_year, term.clone(), true).await?;
+            export_weeks(&term.weeks, output.format)?
+        }
+        else {
+            render_week_in(selection.clone(), year, week, output.format).await?
+        };
+
+        if write_if_changed(&output.path, &bytes).await? {
+            report.written.push(output.path.clone());
+        }
+        else {
+            report.unchanged.push(output.path.clone());
+        }
+    }
+
+    Ok(report)
+}
+
+// `refresh` of the selection and `outputs` in the config. The cache is refreshed even when
+// the config disables it.
+#[cfg(feature = "config")]
+pub async fn refresh_and_export(config: &crate::config::Config) -> Result<Report, OpsError> {
+    config.apply();
+    let outputs = config.outputs.iter()
+        .map(|(format, path)| match ExportFormat::from_extension(format) {
+            Some(format) => Ok(Output { path: path.clone(), format }),
+            None => Err(OpsError::UnknownFormat(path.clone())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let selection = config.selection().await?;
+    refresh(selection, &outputs).await
+}
+
+// Writes to a file next to `path` and renames it over `path`, so readers never see a partial
+// file. Returns whether anything was written.
+async fn write_if_changed(path: &Path, bytes: &[u8]) -> Result<bool, OpsError> {
+    match async_std::fs::read(path).await {
+        Ok(existing) if existing == bytes => return Ok(false),
+        Ok(_) => {},
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+        Err(e) => return Err(e.into()),
+    }
+
+    let name = path.file_name().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "output path has no file name"))?;
+    let mut temporary = OsString::from(".");
+    temporary.push(name);
+    temporary.push(".tmp");
+    let temporary = path.with_file_name(temporary);
+
+    async_std::fs::write(&temporary, bytes).await?;
+    if let Err(e) = async_std::fs::rename(&temporary, path).await {
+        let _ = async_std::fs::remove_file(&temporary).await;
+        return Err(e.into());
+    }
+    Ok(true)
+}
+
+// Where `export_school` puts the file of each class, named after the class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bundle {
+    Directory(PathBuf),
+    #[cfg(feature = "archive")]
+    Zip(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct SchoolExportOptions {
+    // Classes fetched at the same time
+    pub concurrency: usize,
+    // Attempts after a failed request, waiting `backoff` before the first and twice as long
+    // before each following one
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+impl Default for SchoolExportOptions {
+    fn default() -> Self {
+        SchoolExportOptions {
+            concurrency: 4,
+            retries: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl SchoolExportOptions {
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SchoolExport {
+    // Class names and the files, or zip entries, written for them
+    pub written: Vec<(String, String)>,
+    // Classes that still failed after all retries
+    pub failed: Vec<(String, OpsError)>,
+}
+
+// Renders `week` of every class of the school into `bundle`. Classes that fail are reported
+// in the result instead of failing the export, only errors listing the classes or writing the
+// bundle are returned.
+pub async fn export_school(domain: &str, school: &str, week: i32, format: ExportFormat, bundle: &Bundle, options: &SchoolExportOptions) -> Result<SchoolExport, OpsError> {
+    let unit_guid = get_school(domain, school, true).await?.unit_guid;
+    let classes = get_classes(domain, &unit_guid, true).await?;
+
+    let mut rendered: Vec<(String, Result<Vec<u8>, OpsError>)> = stream::iter(classes)
+        .map(|class| {
+            let selection = Selection::new(domain, &unit_guid, SelectionTarget::Class(class.group_guid));
+            let name = class.group_name;
+            async move {
+                let bytes = render_with_retries(selection, week, format, options).await;
+                (name, bytes)
+            }
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+    rendered.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut export = SchoolExport::default();
+    let mut files = Vec::new();
+    let mut used = std::collections::HashSet::new();
+    for (class, bytes) in rendered {
+        match bytes {
+            Ok(bytes) => {
+                // Different classes can end up with the same file name
+                let stem = file_name(&class);
+                let name = (1..)
+                    .map(|n| match n {
+                        1 => format!("{}.{}", stem, format.extension()),
+                        n => format!("{}-{}.{}", stem, n, format.extension()),
+                    })
+                    .find(|name| used.insert(name.clone()))
+                    .unwrap_or_default();
+                export.written.push((class, name.clone()));
+                files.push((name, bytes));
+            },
+            Err(e) => export.failed.push((class, e)),
+        }
+    }
+
+    match bundle {
+        Bundle::Directory(dir) => {
+            async_std::fs::create_dir_all(dir).await?;
+            for (name, bytes) in files {
+                async_std::fs::write(dir.join(name), bytes).await?;
+            }
+        },
+        #[cfg(feature = "archive")]
+        Bundle::Zip(path) => {
+            let path = path.clone();
+            async_std::task::spawn_blocking(move || write_zip(&path, files)).await?;
+        },
+    }
+
+    Ok(export)
+}
+
+async fn render_with_retries(selection: Selection, week: i32, format: ExportFormat, options: &SchoolExportOptions) -> Result<Vec<u8>, OpsError> {
+    let mut backoff = options.backoff;
+    let mut attempt = 0;
+    loop {
+        match render_week(selection.clone(), week, format).await {
+            Err(OpsError::Request(e)) if attempt < options.retries && is_transient(&e) => {
+                debug!("week {} of {}: retrying in {:?} after {:?}", week, selection.target.guid(), backoff, e);
+                async_std::task::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
+// Network errors, including rate limiting, rather than answers that would come again
+fn is_transient(error: &RequestError) -> bool {
+    matches!(error.root(), RequestError::Reqwest(_))
+}
+
+// Class names like "9A/B" are not valid file names everywhere
+fn file_name(class: &str) -> String {
+    let name: String = class.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+    if name.is_empty() || name.starts_with('.') {
+        format!("_{}", name)
+    }
+    else {
+        name
+    }
+}
+
+#[cfg(feature = "archive")]
+fn write_zip(path: &Path, files: Vec<(String, Vec<u8>)>) -> Result<(), OpsError> {
+    use std::io::Write;
+
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path)?);
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, bytes) in files {
+        zip.start_file(name, options)?;
+        zip.write_all(&bytes)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
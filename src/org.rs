@@ -0,0 +1,53 @@
+use crate::model::Lesson;
+
+// One org-mode heading per lesson with an active timestamp, so the entries show up in the agenda.
+// Lessons without a resolved date are skipped.
+pub fn to_org<'a, I>(lessons: I) -> String
+where
+    I: IntoIterator<Item = &'a Lesson>,
+{
+    let mut org = String::new();
+    for lesson in lessons {
+        let date = match lesson.date {
+            Some(date) => date,
+            None => continue,
+        };
+
+        let title = if lesson.subject.is_empty() {"Lesson"} else {lesson.subject.trim()};
+        org += &format!("* {}", title);
+        let tag = tag(&lesson.subject);
+        if !tag.is_empty() {
+            org += &format!(" :{}:", tag);
+        }
+        org += "\n";
+
+        org += &format!(
+            "  <{} {}-{}>\n",
+            date.format("%Y-%m-%d %a"),
+            lesson.start.format("%H:%M"),
+            lesson.end.format("%H:%M"),
+        );
+
+        if !lesson.teacher.is_empty() || !lesson.room.is_empty() {
+            org += "  :PROPERTIES:\n";
+            if !lesson.teacher.is_empty() {
+                org += &format!("  :TEACHER:  {}\n", lesson.teacher);
+            }
+            if !lesson.room.is_empty() {
+                org += &format!("  :LOCATION: {}\n", lesson.room);
+            }
+            org += "  :END:\n";
+        }
+    }
+
+    org
+}
+
+// Org tags may only contain letters, digits, `_` and `@`
+fn tag(subject: &str) -> String {
+    subject.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '@' {c} else {'_'})
+        .collect()
+}
@@ -0,0 +1,31 @@
+use rayon::prelude::*;
+
+use crate::Dimensions;
+use crate::data::Schema;
+use crate::image::{generate_svg, RenderError, SvgOptions};
+#[cfg(feature = "png")]
+use crate::png::{generate_png, PngError};
+
+// Rendering is CPU bound, so a batch is spread over rayon's global thread pool. The results
+// are in the order of `schemas`, one failing schema doesn't stop the others.
+pub fn render_svgs(schemas: &[Schema], dimensions: Dimensions, options: &SvgOptions) -> Vec<Result<svg::Document, RenderError>> {
+    schemas.par_iter()
+        .map(|schema| generate_svg(schema, dimensions, options))
+        .collect()
+}
+
+#[cfg(feature = "png")]
+pub fn render_pngs(schemas: &[Schema], dimensions: Dimensions, options: &SvgOptions, dpi: f32) -> Vec<Result<Vec<u8>, PngError>> {
+    schemas.par_iter()
+        .map(|schema| generate_png(schema, dimensions, options, dpi))
+        .collect()
+}
+
+// Like `render_svgs` for any per schema work, e.g. rendering with `renderer::render`
+pub fn render_with<T, F>(schemas: &[Schema], render: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&Schema) -> T + Send + Sync,
+{
+    schemas.par_iter().map(render).collect()
+}
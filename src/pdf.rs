@@ -0,0 +1,223 @@
+use chrono::{NaiveTime, Timelike};
+
+use crate::model::{Lesson, WeekSchedule};
+use crate::text::{parse_hex, week_columns};
+
+// A4 in points
+const A4: (f64, f64) = (595.28, 841.89);
+const MARGIN: f64 = 36.0;
+const AXIS_WIDTH: f64 = 32.0;
+const TITLE_HEIGHT: f64 = 28.0;
+const HEADING_HEIGHT: f64 = 18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Orientation {
+    Portrait,
+    #[default]
+    Landscape,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PdfOptions {
+    pub orientation: Orientation,
+    // Printed above every week, e.g. the class name
+    pub title: Option<String>,
+}
+
+// Renders every week on its own A4 page, e.g. `to_pdf(&term.weeks, &options)`
+pub fn to_pdf<'a, I>(weeks: I, options: &PdfOptions) -> Vec<u8>
+where
+    I: IntoIterator<Item = &'a WeekSchedule>,
+{
+    let (width, height) = match options.orientation {
+        Orientation::Portrait => A4,
+        Orientation::Landscape => (A4.1, A4.0),
+    };
+
+    let pages: Vec<Vec<u8>> = weeks.into_iter()
+        .map(|week| week_page(week, options, width, height))
+        .collect();
+
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 4 + 2 * i)).collect();
+    objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()).into_bytes());
+    objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec());
+    for (i, content) in pages.into_iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            width, height, 5 + 2 * i
+        ).into_bytes());
+
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend(b"\nendstream");
+        objects.push(stream);
+    }
+
+    write_document(&objects)
+}
+
+fn write_document(objects: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend(format!("{} 0 obj\n", i + 1).bytes());
+        out.extend(object);
+        out.extend(b"\nendobj\n");
+    }
+
+    let xref = out.len();
+    out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+    for offset in offsets {
+        out.extend(format!("{:010} 00000 n \n", offset).bytes());
+    }
+    out.extend(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).bytes());
+    out
+}
+
+fn week_page(week: &WeekSchedule, options: &PdfOptions, width: f64, height: f64) -> Vec<u8> {
+    let mut page = Page { content: Vec::new(), height };
+
+    let title = match &options.title {
+        Some(title) => format!("{} - week {} {}", title, week.week, week.year),
+        None => format!("Week {} {}", week.week, week.year),
+    };
+    page.text(MARGIN, MARGIN + 14.0, 14.0, (0, 0, 0), &title);
+
+    let columns = week_columns(week);
+    let ranges = columns.iter().flat_map(|c| c.lessons.iter().map(|(range, _)| range));
+    let first = ranges.clone().map(|range| range.start.hour()).min().unwrap_or(8);
+    let last = ranges.map(|range| hour_ceil(range.end)).max().unwrap_or(17).max(first + 1);
+
+    let top = MARGIN + TITLE_HEIGHT + HEADING_HEIGHT;
+    let grid_height = height - MARGIN - top;
+    let column_width = (width - 2.0 * MARGIN - AXIS_WIDTH) / columns.len() as f64;
+    let per_minute = grid_height / ((last - first) * 60) as f64;
+    let y_of = |time: NaiveTime| top + (time.num_seconds_from_midnight() as f64 / 60.0 - (first * 60) as f64) * per_minute;
+
+    for hour in first..=last {
+        let y = top + ((hour - first) * 60) as f64 * per_minute;
+        page.line(MARGIN + AXIS_WIDTH, y, width - MARGIN, y, 0.8);
+        if hour < last {
+            page.text(MARGIN, y + 8.0, 7.0, (0, 0, 0), &format!("{:02}:00", hour));
+        }
+    }
+
+    for (i, column) in columns.iter().enumerate() {
+        let x = MARGIN + AXIS_WIDTH + i as f64 * column_width;
+        let heading = match column.weekday.and_then(|day| week.date_of(day)) {
+            Some(date) => format!("{} {}", column.title, date.format("%d/%m")),
+            None => column.title.clone(),
+        };
+        page.text(x + 3.0, top - 5.0, 9.0, (0, 0, 0), &fit(&heading, column_width - 6.0, 9.0));
+        page.line(x, top - HEADING_HEIGHT, x, height - MARGIN, 0.6);
+
+        for (range, lesson) in &column.lessons {
+            let y = y_of(range.start);
+            let h = y_of(range.end) - y;
+            let fill = lesson.color.as_deref().and_then(parse_hex).unwrap_or((230, 230, 230));
+            page.rect(x + 1.0, y, column_width - 2.0, h, fill);
+            lesson_text(&mut page, lesson, x + 4.0, y, column_width - 8.0, h, contrast(fill));
+        }
+    }
+    page.line(width - MARGIN, top - HEADING_HEIGHT, width - MARGIN, height - MARGIN, 0.6);
+
+    page.content
+}
+
+fn lesson_text(page: &mut Page, lesson: &Lesson, x: f64, y: f64, width: f64, height: f64, color: (u8, u8, u8)) {
+    let time = format!("{}-{}", lesson.start.format("%H:%M"), lesson.end.format("%H:%M"));
+    let lines = [
+        (8.0, lesson.subject.as_str()),
+        (6.5, time.as_str()),
+        (6.5, lesson.room.as_str()),
+        (6.5, lesson.teacher.as_str()),
+    ];
+
+    let mut baseline = y + 2.0;
+    for (size, line) in lines.iter() {
+        baseline += size + 1.0;
+        if baseline > y + height - 1.0 {
+            break;
+        }
+        if !line.is_empty() {
+            page.text(x, baseline, *size, color, &fit(line, width, *size));
+        }
+    }
+}
+
+fn hour_ceil(time: NaiveTime) -> u32 {
+    if time.minute() == 0 && time.second() == 0 {
+        time.hour()
+    }
+    else {
+        time.hour() + 1
+    }
+}
+
+fn contrast((r, g, b): (u8, u8, u8)) -> (u8, u8, u8) {
+    let luminance = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+    if luminance > 140.0 {(0, 0, 0)} else {(255, 255, 255)}
+}
+
+// Truncates text to roughly fit `width`, assuming Helvetica's average glyph width
+fn fit(text: &str, width: f64, size: f64) -> String {
+    let max = (width / (size * 0.52)).max(0.0) as usize;
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut fitted: String = text.chars().take(max.saturating_sub(1)).collect();
+    fitted.push('.');
+    fitted
+}
+
+// Content stream drawing with the origin in the top left corner
+struct Page {
+    content: Vec<u8>,
+    height: f64,
+}
+
+impl Page {
+    fn rect(&mut self, x: f64, y: f64, width: f64, height: f64, (r, g, b): (u8, u8, u8)) {
+        self.content.extend(format!(
+            "{:.3} {:.3} {:.3} rg 0 G 0.5 w {:.2} {:.2} {:.2} {:.2} re B\n",
+            r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0,
+            x, self.height - y - height, width, height
+        ).bytes());
+    }
+
+    fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, gray: f64) {
+        self.content.extend(format!(
+            "{:.2} G 0.5 w {:.2} {:.2} m {:.2} {:.2} l S\n",
+            gray, x1, self.height - y1, x2, self.height - y2
+        ).bytes());
+    }
+
+    fn text(&mut self, x: f64, baseline: f64, size: f64, (r, g, b): (u8, u8, u8), text: &str) {
+        self.content.extend(format!(
+            "BT {:.3} {:.3} {:.3} rg /F1 {:.1} Tf {:.2} {:.2} Td (",
+            r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0,
+            size, x, self.height - baseline
+        ).bytes());
+        self.content.extend(encode(text));
+        self.content.extend(b") Tj ET\n");
+    }
+}
+
+// WinAnsi string literal; Latin-1 covers Swedish, anything else becomes '?'
+fn encode(text: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push(b'\\');
+                out.push(c as u8);
+            },
+            ' '..='~' | '\u{a0}'..='\u{ff}' => out.push(c as u32 as u8),
+            _ => out.push(b'?'),
+        }
+    }
+    out
+}
@@ -0,0 +1,87 @@
+use resvg::{tiny_skia, usvg};
+
+use std::fmt;
+
+use crate::{Dimensions, data::Schema, image::{generate_svg, RenderError, SvgOptions}};
+
+// The SVG is laid out in CSS pixels, which are defined at 96 DPI
+const SVG_DPI: f32 = 96.0;
+
+#[derive(Debug)]
+pub enum PngError {
+    Svg(RenderError),
+    Parse(usvg::Error),
+    // The requested DPI gives an empty or oversized pixmap
+    Size,
+    Encode(String),
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for PngError {}
+
+impl From<RenderError> for PngError {
+    fn from(v: RenderError) -> Self {
+        Self::Svg(v)
+    }
+}
+
+impl From<usvg::Error> for PngError {
+    fn from(v: usvg::Error) -> Self {
+        Self::Parse(v)
+    }
+}
+
+// `SvgOptions::scale` and `dpi` multiply, e.g. a scale of 2 at 96 DPI gives the same pixels as 192 DPI
+pub fn generate_png(schema_data: &Schema, dimensions: Dimensions, options: &SvgOptions, dpi: f32) -> Result<Vec<u8>, PngError> {
+    let doc = generate_svg(schema_data, dimensions, options)?;
+    svg_to_png(&doc, dpi)
+}
+
+// Rasterizes any SVG document, using the system fonts for text
+pub fn svg_to_png(doc: &svg::Document, dpi: f32) -> Result<Vec<u8>, PngError> {
+    let started = std::time::Instant::now();
+    let png = rasterize(doc, dpi);
+    crate::metrics::record_render("png", started.elapsed());
+    png
+}
+
+fn rasterize(doc: &svg::Document, dpi: f32) -> Result<Vec<u8>, PngError> {
+    let mut options = usvg::Options::default();
+    load_fonts(options.fontdb_mut());
+    let tree = usvg::Tree::from_str(&doc.to_string(), &options)?;
+
+    let scale = dpi / SVG_DPI;
+    let size = tree.size().to_int_size().scale_by(scale).ok_or(PngError::Size)?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height()).ok_or(PngError::Size)?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    pixmap.encode_png().map_err(|e| PngError::Encode(e.to_string()))
+}
+
+// The schedule asks for Open Sans, so make sure the generic sans-serif family
+// resolves to something installed when neither it nor Arial is available
+fn load_fonts(fontdb: &mut usvg::fontdb::Database) {
+    fontdb.load_system_fonts();
+
+    let query = usvg::fontdb::Query {
+        families: &[usvg::fontdb::Family::SansSerif],
+        ..Default::default()
+    };
+    if fontdb.query(&query).is_none() {
+        // Prefer a sans family, monospace and math faces rarely come with bold and italic variants
+        let families: Vec<String> = fontdb.faces().filter_map(|face| face.families.first()).map(|(name, _)| name.clone()).collect();
+        let fallback = families.iter()
+            .find(|name| name.contains("Sans") && !name.contains("Mono"))
+            .or_else(|| families.first())
+            .cloned();
+        if let Some(family) = fallback {
+            fontdb.set_sans_serif_family(family);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+// Python module for scripts and bots that would otherwise shell out to a Rust binary. Build
+// it with `cargo rustc --release --features python --crate-type cdylib` and put
+// liblibschedule24.so (.dylib on macOS) on the Python path as libschedule24.so (.pyd on
+// Windows), then
+//
+//     import libschedule24 as ls24
+//     client = ls24.Client("example.skola24.se")
+//     week = client.week("Example School", "9A", 45)
+//     for lesson in week.lessons_on(1):
+//         print(lesson.start, lesson.subject, lesson.room)
+//
+// Requests block the calling thread but release the GIL while they wait, so other Python
+// threads keep running. Failures raise `libschedule24.Skola24Error`.
+
+use chrono::{NaiveTime, Weekday};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use tokio::runtime::Runtime;
+
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::OnceLock;
+
+use crate::image::{generate_svg, SvgOptions};
+use crate::{data, ics, json, model, query, Dimensions, SchemaRequestOptions, Selection};
+
+create_exception!(libschedule24, Skola24Error, PyException);
+
+fn error(error: impl Debug) -> PyErr {
+    Skola24Error::new_err(format!("{:?}", error))
+}
+
+// reqwest needs a tokio runtime to make requests in, Python has none to lend
+static RUNTIME: OnceLock<Result<Runtime, String>> = OnceLock::new();
+
+// Called from Python, so the GIL is already held and attaching is cheap
+fn block_on<T: Send, E: Debug + Send>(future: impl Future<Output = Result<T, E>> + Send) -> PyResult<T> {
+    let runtime = RUNTIME.get_or_init(|| Runtime::new().map_err(|e| e.to_string()))
+        .as_ref()
+        .map_err(error)?;
+    Python::attach(|py| py.detach(|| runtime.block_on(future))).map_err(error)
+}
+
+// Monday is 1, as with `datetime.date.isoweekday`
+fn weekday(number: u32) -> PyResult<Weekday> {
+    match number {
+        1..=7 => Ok(Weekday::try_from(number as u8 - 1).expect("weekday in range")),
+        _ => Err(error(format!("weekday {} is not between 1 and 7", number))),
+    }
+}
+
+#[pyclass(module = "libschedule24", frozen)]
+pub struct School {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub guid: String,
+}
+
+#[pyclass(module = "libschedule24", frozen)]
+pub struct Class {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub guid: String,
+}
+
+impl From<data::School> for School {
+    fn from(school: data::School) -> Self {
+        School {
+            name: school.unit_id,
+            guid: school.unit_guid,
+        }
+    }
+}
+
+impl From<data::Class> for Class {
+    fn from(class: data::Class) -> Self {
+        Class {
+            name: class.group_name,
+            guid: class.group_guid,
+        }
+    }
+}
+
+// A `model::Lesson`, times and dates as `datetime` types
+#[pyclass(module = "libschedule24", frozen)]
+#[derive(Clone)]
+pub struct Lesson(pub model::Lesson);
+
+#[pymethods]
+impl Lesson {
+    // Only stable within one fetched week, see `data::LessonId`
+    #[getter]
+    fn id(&self) -> &str {
+        self.0.id.as_str()
+    }
+
+    #[getter]
+    fn weekday(&self) -> u32 {
+        self.0.weekday.number_from_monday()
+    }
+
+    #[getter]
+    fn date(&self) -> Option<chrono::NaiveDate> {
+        self.0.date
+    }
+
+    #[getter]
+    fn start(&self) -> NaiveTime {
+        self.0.start
+    }
+
+    #[getter]
+    fn end(&self) -> NaiveTime {
+        self.0.end
+    }
+
+    #[getter]
+    fn subject(&self) -> &str {
+        &self.0.subject
+    }
+
+    #[getter]
+    fn teacher(&self) -> &str {
+        &self.0.teacher
+    }
+
+    #[getter]
+    fn room(&self) -> &str {
+        &self.0.room
+    }
+
+    #[getter]
+    fn texts(&self) -> Vec<String> {
+        self.0.texts.clone()
+    }
+
+    #[getter]
+    fn block(&self) -> Option<&str> {
+        self.0.block.as_deref()
+    }
+
+    #[getter]
+    fn color(&self) -> Option<&str> {
+        self.0.color.as_deref()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Lesson({:?}, {} {}-{})", self.0.subject, self.0.weekday, self.0.start.format("%H:%M"), self.0.end.format("%H:%M"))
+    }
+}
+
+fn lessons<'a>(lessons: impl IntoIterator<Item = &'a model::Lesson>) -> Vec<Lesson> {
+    lessons.into_iter().cloned().map(Lesson).collect()
+}
+
+// A `model::WeekSchedule` with the functions of `query` and the exporters as methods
+#[pyclass(module = "libschedule24", frozen)]
+pub struct Week(pub model::WeekSchedule);
+
+#[pymethods]
+impl Week {
+    #[getter]
+    fn year(&self) -> i32 {
+        self.0.year
+    }
+
+    #[getter]
+    fn week(&self) -> u32 {
+        self.0.week
+    }
+
+    fn lessons(&self) -> Vec<Lesson> {
+        lessons(self.0.lessons())
+    }
+
+    fn lessons_on(&self, weekday: u32) -> PyResult<Vec<Lesson>> {
+        Ok(lessons(self.0.lessons_on(self::weekday(weekday)?)))
+    }
+
+    // The lesson in progress on `weekday` at `at`, a `datetime.time`
+    fn current_lesson(&self, weekday: u32, at: NaiveTime) -> PyResult<Option<Lesson>> {
+        Ok(query::current_lesson(self.0.lessons_on(self::weekday(weekday)?), at).cloned().map(Lesson))
+    }
+
+    fn next_lesson(&self, weekday: u32, at: NaiveTime) -> PyResult<Option<Lesson>> {
+        Ok(query::next_lesson(self.0.lessons_on(self::weekday(weekday)?), at).cloned().map(Lesson))
+    }
+
+    fn is_free(&self, weekday: u32, start: NaiveTime, end: NaiveTime) -> PyResult<bool> {
+        let weekday = self::weekday(weekday)?;
+        Ok(query::is_free(self.0.lessons_on(weekday), weekday, start, end))
+    }
+
+    fn lessons_by_subject(&self, subject: &str) -> Vec<Lesson> {
+        let all: Vec<model::Lesson> = self.0.lessons().cloned().collect();
+        lessons(query::lessons_by_subject(&all, subject))
+    }
+
+    fn lessons_by_teacher(&self, signature: &str) -> Vec<Lesson> {
+        let all: Vec<model::Lesson> = self.0.lessons().cloned().collect();
+        lessons(query::lessons_by_teacher(&all, signature))
+    }
+
+    fn lessons_by_room(&self, room: &str) -> Vec<Lesson> {
+        let all: Vec<model::Lesson> = self.0.lessons().cloned().collect();
+        lessons(query::lessons_by_room(&all, room))
+    }
+
+    // `json::SimpleSchedule`, for `json.loads`
+    fn to_json(&self) -> PyResult<String> {
+        json::to_simple_json(&self.0).map_err(error)
+    }
+
+    fn to_ics(&self) -> String {
+        ics::to_ical(&self.0)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Week({}-W{:02}, {} lessons)", self.0.year, self.0.week, self.0.lessons().count())
+    }
+}
+
+// The schools and classes of one skola24 domain
+#[pyclass(module = "libschedule24", frozen)]
+pub struct Client {
+    #[pyo3(get)]
+    pub domain: String,
+    #[pyo3(get)]
+    pub should_cache: bool,
+}
+
+#[pymethods]
+impl Client {
+    #[new]
+    #[pyo3(signature = (domain, should_cache = true))]
+    fn new(domain: String, should_cache: bool) -> Self {
+        Client {
+            domain,
+            should_cache,
+        }
+    }
+
+    fn schools(&self) -> PyResult<Vec<School>> {
+        let schools = block_on(crate::get_schools(&self.domain, self.should_cache))?;
+        Ok(schools.into_iter().map(School::from).collect())
+    }
+
+    // `school` is the school's name, as listed by `schools`
+    fn classes(&self, school: &str) -> PyResult<Vec<Class>> {
+        let classes = block_on(async {
+            let unit_guid = crate::get_school_guid(&self.domain, school, self.should_cache).await?;
+            crate::get_classes(&self.domain, &unit_guid, self.should_cache).await
+        })?;
+        Ok(classes.into_iter().map(Class::from).collect())
+    }
+
+    // By ISO week, `year` defaults to the ISO year the week is in when it is the current one
+    #[pyo3(signature = (school, class_name, week, year = None))]
+    fn week(&self, school: &str, class_name: &str, week: u32, year: Option<i32>) -> PyResult<Week> {
+        let year = year.unwrap_or_else(|| crate::year_of_week(week as i32));
+        let schedule = block_on(async {
+            let selection = Selection::class(&self.domain, school, class_name, self.should_cache).await?;
+            crate::fetch_schedule(selection, 0, year, week, self.should_cache).await
+        })?;
+        Ok(Week(schedule))
+    }
+
+    // The week, or for `day` 1 (Monday) to 7 one day, drawn as SVG
+    #[pyo3(signature = (school, class_name, week, day = 0, width = 800, height = 600))]
+    fn render_svg(&self, school: &str, class_name: &str, week: i32, day: i32, width: u32, height: u32) -> PyResult<String> {
+        let dimensions = Dimensions { width, height };
+        let schema = block_on(async {
+            let selection = Selection::class(&self.domain, school, class_name, self.should_cache).await?;
+            crate::get_schema(selection, day, week, Some(dimensions), &SchemaRequestOptions::default(), self.should_cache).await
+        })?;
+        generate_svg(&schema.data, dimensions, &SvgOptions::default())
+            .map(|doc| doc.to_string())
+            .map_err(error)
+    }
+}
+
+#[pymodule]
+fn libschedule24(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("Skola24Error", module.py().get_type::<Skola24Error>())?;
+    module.add_class::<Client>()?;
+    module.add_class::<School>()?;
+    module.add_class::<Class>()?;
+    module.add_class::<Week>()?;
+    module.add_class::<Lesson>()?;
+    Ok(())
+}
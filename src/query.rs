@@ -0,0 +1,76 @@
+use chrono::{Duration, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::model::{Lesson, WeekSchedule};
+
+// True if no lesson on `weekday` overlaps `start..end`
+pub fn is_free(lessons: &[Lesson], weekday: Weekday, start: NaiveTime, end: NaiveTime) -> bool {
+    !lessons.iter()
+        .filter(|lesson| lesson.weekday == weekday)
+        .any(|lesson| lesson.start < end && lesson.end > start)
+}
+
+// Lessons starting after `at`, earliest first
+pub fn upcoming_lessons(lessons: &[Lesson], at: NaiveTime) -> Vec<&Lesson> {
+    let mut upcoming: Vec<&Lesson> = lessons.iter()
+        .filter(|lesson| lesson.start > at)
+        .collect();
+
+    upcoming.sort_by_key(|lesson| lesson.start);
+    upcoming
+}
+
+pub fn next_lesson(lessons: &[Lesson], at: NaiveTime) -> Option<&Lesson> {
+    upcoming_lessons(lessons, at).into_iter().next()
+}
+
+pub fn time_until_next(lessons: &[Lesson], now: NaiveTime) -> Option<Duration> {
+    Some(next_lesson(lessons, now)?.start - now)
+}
+
+// The lesson in progress at `at` (start inclusive, end exclusive). When blocks overlap,
+// the lesson that started most recently wins, then the one ending first.
+pub fn current_lesson(lessons: &[Lesson], at: NaiveTime) -> Option<&Lesson> {
+    lessons.iter()
+        .filter(|lesson| lesson.time_range().contains(at))
+        .min_by(|a, b| b.start.cmp(&a.start).then(a.end.cmp(&b.end)))
+}
+
+// Case-insensitive match against the parsed subject or any of the raw `texts`
+pub fn lessons_by_subject<'a>(lessons: &'a [Lesson], subject: &str) -> Vec<&'a Lesson> {
+    lessons.iter()
+        .filter(|lesson| same_text(&lesson.subject, subject) || lesson.texts.iter().any(|text| same_text(text, subject)))
+        .collect()
+}
+
+// Lessons with several teachers list them comma separated, e.g. "ABC, DEF"
+pub fn lessons_by_teacher<'a>(lessons: &'a [Lesson], signature: &str) -> Vec<&'a Lesson> {
+    lessons.iter()
+        .filter(|lesson| any_listed(&lesson.teacher, signature))
+        .collect()
+}
+
+pub fn lessons_by_room<'a>(lessons: &'a [Lesson], room: &str) -> Vec<&'a Lesson> {
+    lessons.iter()
+        .filter(|lesson| any_listed(&lesson.room, room))
+        .collect()
+}
+
+fn any_listed(list: &str, query: &str) -> bool {
+    list.split(',').any(|item| same_text(item, query))
+}
+
+fn same_text(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+// Lessons overlapping `start..end`, in chronological order
+pub fn lessons_between(week: &WeekSchedule, start: NaiveDateTime, end: NaiveDateTime) -> Vec<&Lesson> {
+    let mut lessons: Vec<(NaiveDateTime, &Lesson)> = week.lessons()
+        .filter_map(|lesson| Some((lesson.start_datetime()?, lesson.end_datetime()?, lesson)))
+        .filter(|(lesson_start, lesson_end, _)| *lesson_start < end && *lesson_end > start)
+        .map(|(lesson_start, _, lesson)| (lesson_start, lesson))
+        .collect();
+
+    lessons.sort_by_key(|(lesson_start, _)| *lesson_start);
+    lessons.into_iter().map(|(_, lesson)| lesson).collect()
+}
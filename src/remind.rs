@@ -0,0 +1,65 @@
+use crate::model::Lesson;
+
+// `remind` reminders, one `REM` line per lesson. Lessons without a resolved date are skipped.
+pub fn to_remind<'a, I>(lessons: I) -> String
+where
+    I: IntoIterator<Item = &'a Lesson>,
+{
+    let mut rem = String::new();
+    for lesson in lessons {
+        let date = match lesson.date {
+            Some(date) => date,
+            None => continue,
+        };
+
+        let minutes = lesson.duration().num_minutes().max(0);
+        rem += &format!(
+            "REM {} AT {} DURATION {}:{:02} MSG {}\n",
+            date.format("%-d %b %Y"),
+            lesson.start.format("%H:%M"),
+            minutes / 60,
+            minutes % 60,
+            escape_remind(&title(lesson)),
+        );
+    }
+
+    rem
+}
+
+// calcurse appointments in its `apts` file format
+pub fn to_calcurse<'a, I>(lessons: I) -> String
+where
+    I: IntoIterator<Item = &'a Lesson>,
+{
+    let mut apts = String::new();
+    for lesson in lessons {
+        let (start, end) = match (lesson.start_datetime(), lesson.end_datetime()) {
+            (Some(start), Some(end)) => (start, end),
+            _ => continue,
+        };
+
+        apts += &format!(
+            "{} -> {} |{}\n",
+            start.format("%m/%d/%Y @ %H:%M"),
+            end.format("%m/%d/%Y @ %H:%M"),
+            title(lesson).replace('\n', " "),
+        );
+    }
+
+    apts
+}
+
+fn title(lesson: &Lesson) -> String {
+    let subject = if lesson.subject.is_empty() {"Lesson"} else {lesson.subject.trim()};
+    if lesson.room.is_empty() {
+        subject.to_string()
+    }
+    else {
+        format!("{} ({})", subject, lesson.room)
+    }
+}
+
+// `%` starts a substitution and `[` an expression in remind bodies
+fn escape_remind(text: &str) -> String {
+    text.replace('\n', " ").replace('%', "%%").replace('[', "[\"[\"]")
+}
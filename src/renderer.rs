@@ -0,0 +1,388 @@
+use svg::node::element::{Line as LineElement, Rectangle, Text as TextElement};
+use svg::node::Text as TextNode;
+
+use std::{fmt, io};
+
+use crate::data::{self, Box, BoxType, Schema, Text};
+use crate::image::{self, RenderError, Rgb, SvgOptions};
+
+// Drawing backend for `render`, which resolves positions, colors and fonts the same way
+// `image::generate_svg` does. Coordinates are in pixels of the schema, times `SvgOptions::scale`.
+pub trait Renderer {
+    // Called once before anything is drawn
+    fn begin(&mut self, _width: f64, _height: f64) {}
+    fn rect(&mut self, rect: &RectShape);
+    fn line(&mut self, line: &LineShape);
+    fn text(&mut self, text: &TextShape);
+    // Called once after everything is drawn
+    fn end(&mut self) {}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RectShape<'a> {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub fill: Rgb,
+    pub fill_opacity: f64,
+    pub stroke: Rgb,
+    // 0 for boxes without a border
+    pub stroke_width: f64,
+    pub corner_radius: f64,
+    pub source: &'a Box,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineShape<'a> {
+    pub x1: f64,
+    pub y1: f64,
+    pub x2: f64,
+    pub y2: f64,
+    pub color: Rgb,
+    pub width: f64,
+    pub source: &'a data::Line,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextShape<'a> {
+    pub x: f64,
+    // Of the baseline
+    pub y: f64,
+    pub text: &'a str,
+    pub color: Rgb,
+    // A CSS font family list, e.g. `'Open Sans', sans-serif`
+    pub font_family: &'a str,
+    pub font_size: f64,
+    pub bold: bool,
+    pub italic: bool,
+    pub anchor: Anchor,
+    pub source: &'a Text,
+}
+
+// Draws the boxes, lines and texts of a schema in layering order. Only what every backend can
+// draw is covered, SVG specific options such as links, tooltips and legends are left out.
+pub fn render<R: Renderer>(schema_data: &Schema, dimensions: crate::Dimensions, options: &SvgOptions, renderer: &mut R) -> Result<(), RenderError> {
+    let remapped;
+    let options = if options.theme.colorblind_safe {
+        remapped = SvgOptions {
+            theme: options.theme.with_palette(std::slice::from_ref(schema_data)),
+            ..options.clone()
+        };
+        &remapped
+    }
+    else {
+        options
+    };
+    let cropped;
+    let crop = options.window.and_then(|(start, end)| image::Crop::new(schema_data, image::TimeScale::of(schema_data)?, start, end));
+    let schema_data = match crop {
+        Some(crop) => {
+            cropped = crop.apply(schema_data);
+            &cropped
+        },
+        None => schema_data,
+    };
+    let theme = &options.theme;
+    let scale = options.scale.filter(|scale| *scale > 0.0).unwrap_or(1.0);
+    let font_family = options.fonts.font_family();
+
+    renderer.begin(dimensions.width as f64 * scale, dimensions.height as f64 * scale);
+    let layers = image::layers(schema_data, options, None)?;
+
+    for &rect in &layers.boxes {
+        let (fill, stroke) = image::rect_colors(rect, theme)?;
+        let style = options.box_styles.get(&rect.box_type());
+        let borderless = matches!(rect.box_type(), BoxType::Footer | BoxType::ClockFrameStart | BoxType::ClockFrameEnd);
+        renderer.rect(&RectShape {
+            x: rect.x as f64 * scale,
+            y: rect.y as f64 * scale,
+            width: rect.width as f64 * scale,
+            height: rect.height as f64 * scale,
+            fill,
+            fill_opacity: style.and_then(|style| style.fill_opacity).unwrap_or(1.0),
+            stroke,
+            stroke_width: style.and_then(|style| style.stroke_width).unwrap_or(if borderless {0.0} else {1.0}) * scale,
+            corner_radius: style.and_then(|style| style.corner_radius).unwrap_or(0.0) * scale,
+            source: rect,
+        });
+    }
+
+    let draw_lines = |renderer: &mut R| -> Result<(), RenderError> {
+        for line in &layers.lines {
+            renderer.line(&LineShape {
+                x1: line.p1x as f64 * scale,
+                y1: line.p1y as f64 * scale,
+                x2: line.p2x as f64 * scale,
+                y2: line.p2y as f64 * scale,
+                color: image::line_color(line, theme)?,
+                width: scale,
+                source: line,
+            });
+        }
+        Ok(())
+    };
+    let draw_texts = |renderer: &mut R| -> Result<(), RenderError> {
+        for &(txt, parent) in &layers.texts {
+            let placed = image::place_text(txt, parent, options);
+            renderer.text(&TextShape {
+                x: placed.x as f64 * scale,
+                y: placed.y as f64 * scale,
+                text: &placed.text,
+                color: image::text_color(txt, parent, theme)?,
+                font_family: &font_family,
+                font_size: options.fonts.size(txt.fontsize) * scale,
+                bold: txt.bold,
+                italic: txt.italic,
+                anchor: if placed.anchor.is_some() {Anchor::Middle} else {Anchor::Start},
+                source: txt,
+            });
+        }
+        Ok(())
+    };
+    // Same order as `image::generate_svg`
+    if options.api_order {
+        draw_texts(renderer)?;
+        draw_lines(renderer)?;
+    }
+    else {
+        draw_lines(renderer)?;
+        draw_texts(renderer)?;
+    }
+    renderer.end();
+
+    Ok(())
+}
+
+// Plain SVG with inline presentation attributes, for consumers that do not support CSS
+#[derive(Debug, Clone)]
+pub struct SvgRenderer {
+    document: svg::Document,
+}
+
+impl Default for SvgRenderer {
+    fn default() -> Self {
+        SvgRenderer {
+            document: svg::Document::new(),
+        }
+    }
+}
+
+impl SvgRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_document(self) -> svg::Document {
+        self.document
+    }
+
+    fn add<T: svg::Node>(&mut self, node: T) {
+        self.document = std::mem::replace(&mut self.document, svg::Document::new()).add(node);
+    }
+}
+
+impl Renderer for SvgRenderer {
+    fn begin(&mut self, width: f64, height: f64) {
+        self.document = svg::Document::new()
+            .set("viewBox", (0, 0, width, height))
+            .set("width", width)
+            .set("height", height);
+    }
+
+    fn rect(&mut self, rect: &RectShape) {
+        let mut elem = Rectangle::new()
+            .set("x", rect.x)
+            .set("y", rect.y)
+            .set("width", rect.width)
+            .set("height", rect.height)
+            .set("fill", rect.fill.to_string())
+            .set("stroke", rect.stroke.to_string())
+            .set("stroke-width", rect.stroke_width);
+        if rect.fill_opacity < 1.0 {
+            elem = elem.set("fill-opacity", rect.fill_opacity);
+        }
+        if rect.corner_radius > 0.0 {
+            elem = elem
+                .set("rx", rect.corner_radius)
+                .set("ry", rect.corner_radius);
+        }
+        self.add(elem);
+    }
+
+    fn line(&mut self, line: &LineShape) {
+        self.add(LineElement::new()
+            .set("x1", line.x1)
+            .set("y1", line.y1)
+            .set("x2", line.x2)
+            .set("y2", line.y2)
+            .set("stroke", line.color.to_string())
+            .set("stroke-width", line.width));
+    }
+
+    fn text(&mut self, text: &TextShape) {
+        let mut elem = TextElement::new()
+            .set("x", text.x)
+            .set("y", text.y)
+            .set("fill", text.color.to_string())
+            .set("font-family", image::escape(text.font_family))
+            .set("font-size", text.font_size)
+            .add(TextNode::new(image::escape(text.text)));
+        if text.bold {
+            elem = elem.set("font-weight", "bold");
+        }
+        if text.italic {
+            elem = elem.set("font-style", "italic");
+        }
+        match text.anchor {
+            Anchor::Start => {},
+            Anchor::Middle => elem = elem.set("text-anchor", "middle"),
+            Anchor::End => elem = elem.set("text-anchor", "end"),
+        }
+        self.add(elem);
+    }
+}
+
+#[derive(Debug)]
+pub enum WriteError {
+    Render(RenderError),
+    Fmt(fmt::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for WriteError {}
+
+impl From<RenderError> for WriteError {
+    fn from(v: RenderError) -> Self {
+        Self::Render(v)
+    }
+}
+
+// Writes the same SVG as `SvgRenderer` straight to `sink` while drawing, without building the
+// document in memory first
+pub struct SvgWriter<W: fmt::Write> {
+    sink: W,
+    result: fmt::Result,
+}
+
+impl<W: fmt::Write> SvgWriter<W> {
+    pub fn new(sink: W) -> Self {
+        SvgWriter {
+            sink,
+            result: Ok(()),
+        }
+    }
+
+    // The sink, or the first error writing to it. Nothing is written after an error.
+    pub fn finish(self) -> Result<W, fmt::Error> {
+        self.result.map(|_| self.sink)
+    }
+
+    fn write(&mut self, args: fmt::Arguments) {
+        if self.result.is_ok() {
+            self.result = self.sink.write_fmt(args);
+        }
+    }
+}
+
+impl<W: fmt::Write> Renderer for SvgWriter<W> {
+    fn begin(&mut self, width: f64, height: f64) {
+        self.write(format_args!(r#"<svg height="{h}" viewBox="0 0 {w} {h}" width="{w}" xmlns="http://www.w3.org/2000/svg">"#, w = width, h = height));
+    }
+
+    // Attributes in alphabetical order, like the svg crate writes them
+    fn rect(&mut self, rect: &RectShape) {
+        self.write(format_args!("\n<rect fill=\"{}\"", rect.fill));
+        if rect.fill_opacity < 1.0 {
+            self.write(format_args!(" fill-opacity=\"{}\"", rect.fill_opacity));
+        }
+        self.write(format_args!(" height=\"{}\"", rect.height));
+        if rect.corner_radius > 0.0 {
+            self.write(format_args!(" rx=\"{r}\" ry=\"{r}\"", r = rect.corner_radius));
+        }
+        self.write(format_args!(
+            " stroke=\"{}\" stroke-width=\"{}\" width=\"{}\" x=\"{}\" y=\"{}\"/>",
+            rect.stroke, rect.stroke_width, rect.width, rect.x, rect.y,
+        ));
+    }
+
+    fn line(&mut self, line: &LineShape) {
+        self.write(format_args!(
+            "\n<line stroke=\"{}\" stroke-width=\"{}\" x1=\"{}\" x2=\"{}\" y1=\"{}\" y2=\"{}\"/>",
+            line.color, line.width, line.x1, line.x2, line.y1, line.y2,
+        ));
+    }
+
+    fn text(&mut self, text: &TextShape) {
+        self.write(format_args!(
+            "\n<text fill=\"{}\" font-family=\"{}\" font-size=\"{}\"",
+            text.color, image::escape(text.font_family), text.font_size,
+        ));
+        if text.italic {
+            self.write(format_args!(" font-style=\"italic\""));
+        }
+        if text.bold {
+            self.write(format_args!(" font-weight=\"bold\""));
+        }
+        match text.anchor {
+            Anchor::Start => {},
+            Anchor::Middle => self.write(format_args!(" text-anchor=\"middle\"")),
+            Anchor::End => self.write(format_args!(" text-anchor=\"end\"")),
+        }
+        self.write(format_args!(" x=\"{}\" y=\"{}\">\n{}\n</text>", text.x, text.y, image::escape(text.text)));
+    }
+
+    fn end(&mut self) {
+        self.write(format_args!("\n</svg>"));
+    }
+}
+
+pub fn write_svg<W: fmt::Write>(schema_data: &Schema, dimensions: crate::Dimensions, options: &SvgOptions, sink: W) -> Result<W, WriteError> {
+    let mut writer = SvgWriter::new(sink);
+    render(schema_data, dimensions, options, &mut writer)?;
+
+    writer.finish().map_err(WriteError::Fmt)
+}
+
+// Like `write_svg`, for files, sockets and other byte sinks
+pub fn write_svg_io<W: io::Write>(schema_data: &Schema, dimensions: crate::Dimensions, options: &SvgOptions, sink: W) -> Result<W, WriteError> {
+    let mut writer = SvgWriter::new(IoSink {
+        inner: sink,
+        error: None,
+    });
+    render(schema_data, dimensions, options, &mut writer)?;
+
+    match (writer.result, writer.sink.error) {
+        (Ok(()), _) => Ok(writer.sink.inner),
+        (Err(_), Some(e)) => Err(WriteError::Io(e)),
+        (Err(e), None) => Err(WriteError::Fmt(e)),
+    }
+}
+
+// Keeps the `io::Error` that `fmt::Write` has no room for
+struct IoSink<W: io::Write> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for IoSink<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
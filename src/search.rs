@@ -0,0 +1,55 @@
+// Lower scores are better matches, `None` means no match at all.
+pub(crate) fn match_score(candidate: &str, query: &str) -> Option<u32> {
+    let candidate = normalize(candidate);
+    let query = normalize(query);
+
+    if query.is_empty() {
+        return Some(5);
+    }
+
+    if candidate == query {
+        Some(0)
+    }
+    else if candidate.starts_with(&query) {
+        Some(1)
+    }
+    else if candidate.split(|c: char| !c.is_alphanumeric()).any(|word| word.starts_with(&query)) {
+        Some(2)
+    }
+    else if candidate.contains(&query) {
+        Some(3)
+    }
+    else if is_subsequence(&candidate, &query) {
+        Some(4)
+    }
+    else {
+        None
+    }
+}
+
+// Sorts `items` by how well `key` matches `query`, dropping those that don't match.
+pub(crate) fn rank<T, F>(items: Vec<T>, query: &str, key: F) -> Vec<T>
+where
+    F: Fn(&T) -> Vec<&str>,
+{
+    let mut scored: Vec<(u32, T)> = items.into_iter()
+        .filter_map(|item| {
+            let score = key(&item).into_iter().filter_map(|s| match_score(s, query)).min()?;
+            Some((score, item))
+        })
+        .collect();
+
+    scored.sort_by_key(|(score, _)| *score);
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+fn normalize(s: &str) -> String {
+    s.trim().to_lowercase()
+}
+
+fn is_subsequence(haystack: &str, needle: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars()
+        .filter(|c| !c.is_whitespace())
+        .all(|c| haystack.any(|h| h == c))
+}
@@ -0,0 +1,443 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use percent_encoding::percent_decode_str;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration as StdDuration, Instant};
+
+use crate::ics::IcsOptions;
+use crate::image::{generate_svg, SvgOptions};
+use crate::timezone;
+use crate::{Dimensions, RequestError, SchemaRequestOptions, Selection};
+
+// The routes of a tiny schedule web service. `Server::router` serves them with axum, e.g.
+// `axum::serve(listener, Server::new(options).router())` in a tokio runtime, and caches the
+// replies for `ServerOptions::response_max_age`. With another HTTP framework `handle` can be
+// mounted as a catch-all handler, copying `Reply` into the framework's response.
+//
+//   /svg/{domain}/{school}/{class}/{week}/{day}
+//   /json/{domain}/{school}/{class}/{week}[/{day}]
+//   /ics/{domain}/{school}/{class}/{week}[/{day}]
+//   /feed/{domain}/{school}/{class}
+//   /metrics, with the metrics feature
+//
+// School and class are names as shown by skola24, percent encoded. Days are 1 for Monday to
+// 7 for Sunday, leaving out the day serves the whole week. The feed is a calendar of the
+// weeks around the current one for calendar apps to subscribe to, served as
+// `webcal://host/feed/...`, see `ServerOptions::feed`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Svg,
+    Json,
+    Ics,
+    Feed,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Svg => "image/svg+xml",
+            Format::Json => "application/json",
+            Format::Ics | Format::Feed => "text/calendar; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Route {
+    pub format: Format,
+    pub domain: String,
+    pub school: String,
+    pub class: String,
+    // `None` for the feed
+    pub week: Option<i32>,
+    pub day: Option<Weekday>,
+}
+
+#[derive(Debug)]
+pub struct ParseRouteError;
+
+impl fmt::Display for ParseRouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown route")
+    }
+}
+
+impl std::error::Error for ParseRouteError {}
+
+impl FromStr for Route {
+    type Err = ParseRouteError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let segments: Vec<String> = path.trim_matches('/')
+            .split('/')
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+            .collect();
+
+        let (format, rest) = segments.split_first().ok_or(ParseRouteError)?;
+        let format = match format.as_str() {
+            "svg" => Format::Svg,
+            "json" => Format::Json,
+            "ics" => Format::Ics,
+            "feed" => Format::Feed,
+            _ => return Err(ParseRouteError),
+        };
+        let (domain, school, class, week, day) = match (format, rest) {
+            (Format::Feed, [domain, school, class]) => (domain, school, class, None, None),
+            (Format::Feed, _) => return Err(ParseRouteError),
+            (Format::Json | Format::Ics, [domain, school, class, week]) => (domain, school, class, Some(week), None),
+            (_, [domain, school, class, week, day]) => (domain, school, class, Some(week), Some(day)),
+            _ => return Err(ParseRouteError),
+        };
+        let day = match day {
+            Some(day) => Some(parse_weekday(day).ok_or(ParseRouteError)?),
+            None => None,
+        };
+
+        Ok(Route {
+            format,
+            domain: domain.clone(),
+            school: school.clone(),
+            class: class.clone(),
+            week: match week {
+                Some(week) => Some(week.parse().map_err(|_| ParseRouteError)?),
+                None => None,
+            },
+            day,
+        })
+    }
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day.parse::<u32>().ok()? {
+        1 => Some(Weekday::Mon),
+        2 => Some(Weekday::Tue),
+        3 => Some(Weekday::Wed),
+        4 => Some(Weekday::Thu),
+        5 => Some(Weekday::Fri),
+        6 => Some(Weekday::Sat),
+        7 => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerOptions {
+    pub dimensions: Option<Dimensions>,
+    pub svg: SvgOptions,
+    pub schema: SchemaRequestOptions,
+    pub ics: IcsOptions,
+    // Weeks before and after the current one in the feed
+    pub feed_past_weeks: u32,
+    pub feed_future_weeks: u32,
+    // Seconds clients may keep the feed before asking again
+    pub feed_max_age: u32,
+    // How long `Server` answers a path from memory before rendering it again, zero disables it
+    pub response_max_age: StdDuration,
+    pub should_cache: bool,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            dimensions: None,
+            svg: SvgOptions::default(),
+            schema: SchemaRequestOptions::default(),
+            ics: IcsOptions::default(),
+            feed_past_weeks: 1,
+            feed_future_weeks: 4,
+            feed_max_age: 3600,
+            response_max_age: StdDuration::from_secs(60),
+            should_cache: true,
+        }
+    }
+}
+
+impl ServerOptions {
+    pub fn dimensions(mut self, dimensions: Dimensions) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    pub fn svg(mut self, svg: SvgOptions) -> Self {
+        self.svg = svg;
+        self
+    }
+
+    pub fn schema(mut self, schema: SchemaRequestOptions) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    pub fn ics(mut self, ics: IcsOptions) -> Self {
+        self.ics = ics;
+        self
+    }
+
+    pub fn feed(mut self, past_weeks: u32, future_weeks: u32) -> Self {
+        self.feed_past_weeks = past_weeks;
+        self.feed_future_weeks = future_weeks;
+        self
+    }
+
+    pub fn feed_max_age(mut self, seconds: u32) -> Self {
+        self.feed_max_age = seconds;
+        self
+    }
+
+    pub fn response_max_age(mut self, max_age: StdDuration) -> Self {
+        self.response_max_age = max_age;
+        self
+    }
+
+    pub fn cache(mut self, should_cache: bool) -> Self {
+        self.should_cache = should_cache;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub status: u16,
+    pub content_type: &'static str,
+    // Besides `Content-Type`
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Reply {
+    fn ok(format: Format, body: impl Into<Vec<u8>>) -> Self {
+        Reply {
+            status: 200,
+            content_type: format.content_type(),
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn error(status: u16, message: impl fmt::Display) -> Self {
+        Reply {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            headers: Vec::new(),
+            body: message.to_string().into_bytes(),
+        }
+    }
+}
+
+// Never fails, errors are turned into replies with a matching status. `headers` are those of
+// the request, of which `If-None-Match` is used to answer 304 for an unchanged feed.
+pub async fn handle(path: &str, headers: &[(&str, &str)], options: &ServerOptions) -> Reply {
+    conditional(headers, reply(path, options).await)
+}
+
+async fn reply(path: &str, options: &ServerOptions) -> Reply {
+    #[cfg(feature = "metrics")]
+    if path == "/metrics" {
+        return Reply {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            headers: Vec::new(),
+            body: crate::metrics::prometheus().into_bytes(),
+        };
+    }
+
+    let route = match path.parse::<Route>() {
+        Ok(route) => route,
+        Err(e) => return Reply::error(404, e),
+    };
+
+    match render(&route, options).await {
+        Ok(reply) => reply,
+        Err(e) => error_reply(&e),
+    }
+}
+
+// 304 without a body if the client has the reply already
+fn conditional(headers: &[(&str, &str)], reply: Reply) -> Reply {
+    let if_none_match = headers.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("If-None-Match"))
+        .map(|(_, value)| *value);
+    match (if_none_match, reply.header("ETag")) {
+        (Some(if_none_match), Some(etag)) if etag_matches(if_none_match, etag) => Reply {
+            status: 304,
+            content_type: reply.content_type,
+            headers: reply.headers.clone(),
+            body: Vec::new(),
+        },
+        _ => reply,
+    }
+}
+
+// `If-None-Match` is a list of tags or `*`, compared weakly as the RFC asks for GET
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if_none_match.split(',').any(|tag| tag.trim() == "*" || weak(tag) == weak(etag))
+}
+
+// `handle` with the successful replies kept in memory for `ServerOptions::response_max_age`,
+// so clients polling the same schedule don't each parse and render it again
+#[derive(Debug)]
+pub struct Server {
+    options: ServerOptions,
+    replies: Mutex<HashMap<String, (Instant, Reply)>>,
+}
+
+impl Server {
+    pub fn new(options: ServerOptions) -> Self {
+        Server {
+            options,
+            replies: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn options(&self) -> &ServerOptions {
+        &self.options
+    }
+
+    pub async fn handle(&self, path: &str, headers: &[(&str, &str)]) -> Reply {
+        let max_age = self.options.response_max_age;
+        // The metrics change on every request
+        let cached = max_age > StdDuration::from_secs(0) && path != "/metrics";
+
+        if cached {
+            if let Ok(mut replies) = self.replies.lock() {
+                replies.retain(|_, (at, _)| at.elapsed() < max_age);
+                if let Some((_, reply)) = replies.get(path) {
+                    return conditional(headers, reply.clone());
+                }
+            }
+        }
+
+        let reply = reply(path, &self.options).await;
+        if cached && reply.status == 200 {
+            if let Ok(mut replies) = self.replies.lock() {
+                replies.insert(path.to_string(), (Instant::now(), reply.clone()));
+            }
+        }
+        conditional(headers, reply)
+    }
+
+    // Every path goes to `handle`, unknown ones get a 404 from it
+    pub fn router(self) -> axum::Router {
+        axum::Router::new()
+            .fallback(serve)
+            .with_state(Arc::new(self))
+    }
+}
+
+async fn serve(State(server): State<Arc<Server>>, uri: Uri, headers: HeaderMap) -> Reply {
+    let headers: Vec<(&str, &str)> = headers.iter()
+        .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+        .collect();
+    server.handle(uri.path(), &headers).await
+}
+
+impl IntoResponse for Reply {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status, self.body).into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(self.content_type));
+        for (name, value) in self.headers {
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+                headers.insert(name, value);
+            }
+        }
+        response
+    }
+}
+
+pub async fn render(route: &Route, options: &ServerOptions) -> Result<Reply, RequestError> {
+    let selection = Selection::class(&route.domain, &route.school, &route.class, options.should_cache).await?;
+    // Without a week the current ISO week is shown, asked for in its own year
+    let (year, week) = match route.week {
+        Some(week) => (crate::request_year(), week),
+        None => {
+            let iso_week = timezone::now_in(timezone::SCHEDULE_TIMEZONE).date().iso_week();
+            (iso_week.year(), iso_week.week() as i32)
+        },
+    };
+
+    match route.format {
+        Format::Svg => {
+            let day = route.day.map_or(0, |day| day.number_from_monday() as i32);
+            let schema = crate::get_schema_in(selection, day, year, week, options.dimensions, &options.schema, options.should_cache).await?;
+            match generate_svg(&schema.data, options.dimensions.unwrap_or_default(), &options.svg) {
+                Ok(svg) => Ok(Reply::ok(Format::Svg, svg.to_string())),
+                Err(e) => Ok(Reply::error(500, format!("{:?}", e))),
+            }
+        },
+        Format::Json => {
+            // Day 0 makes the API return the whole week
+            let mut schedule = crate::fetch_schedule(selection, 0, year, week as u32, options.should_cache).await?;
+            if let Some(day) = route.day {
+                schedule.days.retain(|d| d.weekday == day);
+            }
+            Ok(Reply::ok(Format::Json, crate::json::to_simple_json(&schedule)?))
+        },
+        Format::Ics => {
+            let schedule = crate::fetch_schedule(selection, 0, year, week as u32, options.should_cache).await?;
+            let ics = match route.day {
+                Some(day) => crate::ics::to_ical_with(schedule.lessons_on(day), &options.ics),
+                None => crate::ics::to_ical_with(&schedule, &options.ics),
+            };
+            Ok(Reply::ok(Format::Ics, ics))
+        },
+        Format::Feed => {
+            let today = timezone::now_in(timezone::SCHEDULE_TIMEZONE).date();
+            let mut term = crate::model::TermSchedule::default();
+            for (year, week) in feed_weeks(today, options.feed_past_weeks, options.feed_future_weeks) {
+                // Day 0 makes the API return the whole week
+                term.weeks.push(crate::fetch_schedule(selection.clone(), 0, year, week as u32, options.should_cache).await?);
+            }
+
+            let ics_options = IcsOptions {
+                refresh_interval: Some((options.feed_max_age / 60).max(1)),
+                ..options.ics.clone()
+            };
+            // The calendar itself changes on every request because of DTSTAMP, so the tag is
+            // taken from the lessons
+            let lessons: Vec<_> = term.lessons().collect();
+            let etag = fnv1a(&serde_json::to_vec(&lessons)?);
+
+            let mut reply = Reply::ok(Format::Feed, crate::ics::to_ical_with(&term, &ics_options));
+            reply.headers.push(("Cache-Control", format!("public, max-age={}", options.feed_max_age)));
+            reply.headers.push(("ETag", format!("\"{:016x}\"", etag)));
+            Ok(reply)
+        },
+    }
+}
+
+// The ISO years and weeks of the feed in order, also across new year
+pub fn feed_weeks(today: NaiveDate, past_weeks: u32, future_weeks: u32) -> Vec<(i32, i32)> {
+    (-(past_weeks as i64)..=future_weeks as i64)
+        .map(|offset| (today + Duration::weeks(offset)).iso_week())
+        .map(|week| (week.year(), week.week() as i32))
+        .collect()
+}
+
+// Stable across Rust versions, unlike `DefaultHasher`, so tags survive a rebuild of the server
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3))
+}
+
+fn error_reply(error: &RequestError) -> Reply {
+    match error.root() {
+        RequestError::NotFound { kind, query } => Reply::error(404, format!("{:?} not found: {}", kind, query)),
+        RequestError::NotPublic(selection_type) => Reply::error(403, format!("{:?} timetables are not public", selection_type)),
+        error => Reply::error(502, format!("{:?}", error)),
+    }
+}
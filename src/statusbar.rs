@@ -0,0 +1,179 @@
+use chrono::NaiveTime;
+use serde::Serialize;
+
+use crate::model::Lesson;
+use crate::query;
+
+// Templates accept {subject}, {short} (first three characters of the subject), {teacher},
+// {room}, {start}, {end} and {remaining} (minutes until the lesson ends or starts)
+#[derive(Debug, Clone)]
+pub struct StatusFormat {
+    pub current: String,
+    pub next: String,
+    // Put between the current and next lesson when both are shown
+    pub separator: String,
+    // One line per remaining lesson of the day
+    pub tooltip: String,
+    // Text when there are no lessons left
+    pub idle: String,
+}
+
+impl Default for StatusFormat {
+    fn default() -> Self {
+        StatusFormat {
+            current: "{short}-{end}".to_string(),
+            next: "{start}-{short}".to_string(),
+            separator: ", ".to_string(),
+            tooltip: "{start}–{end} {subject} {room}".to_string(),
+            idle: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusClass {
+    // A lesson is in progress
+    Lesson,
+    // Between lessons
+    Break,
+    // No lessons left
+    Free,
+}
+
+// Waybar's custom module format, which i3blocks also reads with `format=json`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Status {
+    pub text: String,
+    pub tooltip: String,
+    pub class: StatusClass,
+}
+
+pub fn status(lessons: &[Lesson], at: NaiveTime, format: &StatusFormat) -> Status {
+    let current = query::current_lesson(lessons, at);
+    let next = query::next_lesson(lessons, at);
+
+    let mut parts = Vec::new();
+    if let Some(lesson) = current {
+        parts.push(fill(&format.current, lesson, at));
+    }
+    if let Some(lesson) = next {
+        parts.push(fill(&format.next, lesson, at));
+    }
+
+    let text = if parts.is_empty() {format.idle.clone()} else {parts.join(&format.separator)};
+    let tooltip: Vec<String> = current.into_iter()
+        .chain(query::upcoming_lessons(lessons, at))
+        .map(|lesson| fill(&format.tooltip, lesson, at))
+        .collect();
+
+    let class = match (current, next) {
+        (Some(_), _) => StatusClass::Lesson,
+        (None, Some(_)) => StatusClass::Break,
+        (None, None) => StatusClass::Free,
+    };
+
+    Status {
+        text,
+        tooltip: tooltip.join("\n"),
+        class,
+    }
+}
+
+pub fn status_json(lessons: &[Lesson], at: NaiveTime, format: &StatusFormat) -> String {
+    // Serializing plain strings and a unit enum cannot fail
+    serde_json::to_string(&status(lessons, at, format)).unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+pub struct PolybarStyle {
+    // Underline color for the lesson in progress, e.g. "#ffb52a"
+    pub underline: Option<String>,
+    pub current_foreground: Option<String>,
+    pub next_foreground: Option<String>,
+}
+
+impl Default for PolybarStyle {
+    fn default() -> Self {
+        PolybarStyle {
+            underline: Some("#ffb52a".to_string()),
+            current_foreground: None,
+            next_foreground: None,
+        }
+    }
+}
+
+// Same text as `status`, wrapped in Polybar format tags
+pub fn polybar(lessons: &[Lesson], at: NaiveTime, format: &StatusFormat, style: &PolybarStyle) -> String {
+    let current = query::current_lesson(lessons, at);
+    let next = query::next_lesson(lessons, at);
+
+    let mut parts = Vec::new();
+    if let Some(lesson) = current {
+        let mut text = foreground(&polybar_escape(&fill(&format.current, lesson, at)), &style.current_foreground);
+        if let Some(color) = &style.underline {
+            text = format!("%{{u{}}}%{{+u}}{}%{{-u}}", color, text);
+        }
+        parts.push(text);
+    }
+    if let Some(lesson) = next {
+        parts.push(foreground(&polybar_escape(&fill(&format.next, lesson, at)), &style.next_foreground));
+    }
+
+    if parts.is_empty() {
+        polybar_escape(&format.idle)
+    }
+    else {
+        parts.join(&polybar_escape(&format.separator))
+    }
+}
+
+fn foreground(text: &str, color: &Option<String>) -> String {
+    match color {
+        Some(color) => format!("%{{F{}}}{}%{{F-}}", color, text),
+        None => text.to_string(),
+    }
+}
+
+// Polybar reads tags from `%{`, a literal percent sign is written twice
+fn polybar_escape(text: &str) -> String {
+    text.replace('%', "%%")
+}
+
+// Replaces the placeholders listed on `StatusFormat`, leaving unknown ones as they are
+pub(crate) fn fill(template: &str, lesson: &Lesson, at: NaiveTime) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out += &rest[..open];
+        let after = &rest[open..];
+        match after.find('}').and_then(|close| Some((placeholder(&after[1..close], lesson, at)?, close))) {
+            Some((value, close)) => {
+                out += &value;
+                rest = &after[close + 1..];
+            },
+            None => {
+                out.push('{');
+                rest = &after[1..];
+            },
+        }
+    }
+    out += rest;
+    out
+}
+
+fn placeholder(name: &str, lesson: &Lesson, at: NaiveTime) -> Option<String> {
+    Some(match name {
+        "subject" => lesson.subject.clone(),
+        "short" => lesson.subject.chars().take(3).collect(),
+        "teacher" => lesson.teacher.clone(),
+        "room" => lesson.room.clone(),
+        "start" => lesson.start.format("%H:%M").to_string(),
+        "end" => lesson.end.format("%H:%M").to_string(),
+        "remaining" => {
+            let until = if lesson.start > at {lesson.start} else {lesson.end};
+            if until > at {(until - at).num_minutes().to_string()} else {String::new()}
+        },
+        _ => return None,
+    })
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+use crate::model::{self, Lesson};
+
+// Human readable names for the subject codes schools put in the lesson texts, e.g. "IDH" for
+// "Idrott och hälsa". A code that isn't listed is looked up again without the course or
+// group suffix, so "MA2b", "MATMAT01c" and "SV-9A" find "MA", "MATMAT" and "SV". Codes match
+// in any case. Serialized as an object of codes to names.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "HashMap<String, String>", into = "HashMap<String, String>")]
+pub struct SubjectAliases {
+    aliases: HashMap<String, String>,
+}
+
+// Common codes of the Swedish compulsory school and, with the subject code written twice, of
+// the upper secondary school
+const SWEDISH: &[(&str, &str)] = &[
+    ("BI", "Biologi"),
+    ("BIOBIO", "Biologi"),
+    ("BL", "Bild"),
+    ("DA", "Dans"),
+    ("EN", "Engelska"),
+    ("ENG", "Engelska"),
+    ("ENGENG", "Engelska"),
+    ("EV", "Elevens val"),
+    ("FR", "Franska"),
+    ("FY", "Fysik"),
+    ("FYS", "Fysik"),
+    ("FYSFYS", "Fysik"),
+    ("GE", "Geografi"),
+    ("HI", "Historia"),
+    ("HIS", "Historia"),
+    ("HISHIS", "Historia"),
+    ("HKK", "Hem- och konsumentkunskap"),
+    ("IDH", "Idrott och hälsa"),
+    ("IDR", "Idrott och hälsa"),
+    ("IDRIDR", "Idrott och hälsa"),
+    ("KE", "Kemi"),
+    ("KEM", "Kemi"),
+    ("KEMKEM", "Kemi"),
+    ("MA", "Matematik"),
+    ("MAT", "Matematik"),
+    ("MATMAT", "Matematik"),
+    ("MEN", "Mentorstid"),
+    ("ML", "Moderna språk"),
+    ("MODMOD", "Moderna språk"),
+    ("MU", "Musik"),
+    ("MUS", "Musik"),
+    ("NAKNAK", "Naturkunskap"),
+    ("NO", "Naturorienterande ämnen"),
+    ("RE", "Religionskunskap"),
+    ("REL", "Religionskunskap"),
+    ("RELREL", "Religionskunskap"),
+    ("SH", "Samhällskunskap"),
+    ("SAM", "Samhällskunskap"),
+    ("SAMSAM", "Samhällskunskap"),
+    ("SL", "Slöjd"),
+    ("SO", "Samhällsorienterande ämnen"),
+    ("SP", "Spanska"),
+    ("SV", "Svenska"),
+    ("SVE", "Svenska"),
+    ("SVESVE", "Svenska"),
+    ("SVA", "Svenska som andraspråk"),
+    ("TK", "Teknik"),
+    ("TY", "Tyska"),
+];
+
+impl SubjectAliases {
+    pub fn new() -> Self {
+        SubjectAliases::default()
+    }
+
+    // The bundled table of Swedish subject codes, to extend or override with `insert`
+    pub fn swedish() -> Self {
+        SWEDISH.iter().map(|(code, name)| (*code, *name)).collect()
+    }
+
+    pub fn insert(&mut self, code: impl AsRef<str>, name: impl Into<String>) {
+        self.aliases.insert(code.as_ref().to_uppercase(), name.into());
+    }
+
+    pub fn len(&self) -> usize {
+        self.aliases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+
+    pub fn resolve(&self, code: &str) -> Option<&str> {
+        let code = code.trim().to_uppercase();
+        if let Some(name) = self.aliases.get(&code) {
+            return Some(name);
+        }
+        let prefix: String = code.chars().take_while(|c| c.is_alphabetic()).collect();
+        self.aliases.get(&prefix).map(String::as_str)
+    }
+
+    // Replaces the subject if it has an alias. The code stays in `texts`.
+    pub fn apply(&self, lesson: &mut Lesson) {
+        if let Some(name) = self.resolve(&lesson.subject) {
+            lesson.subject = name.to_string();
+        }
+    }
+
+    // Applies the aliases to every lesson parsed from now on, see `model::add_lesson_transform`
+    pub fn install(self) {
+        model::add_lesson_transform(move |lesson| self.apply(lesson));
+    }
+}
+
+impl From<HashMap<String, String>> for SubjectAliases {
+    fn from(aliases: HashMap<String, String>) -> Self {
+        aliases.into_iter().collect()
+    }
+}
+
+impl From<SubjectAliases> for HashMap<String, String> {
+    fn from(aliases: SubjectAliases) -> Self {
+        aliases.aliases
+    }
+}
+
+impl<K: AsRef<str>, V: Into<String>> FromIterator<(K, V)> for SubjectAliases {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut aliases = SubjectAliases::new();
+        aliases.extend(iter);
+        aliases
+    }
+}
+
+impl<K: AsRef<str>, V: Into<String>> Extend<(K, V)> for SubjectAliases {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (code, name) in iter {
+            self.insert(code, name);
+        }
+    }
+}
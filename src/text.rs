@@ -0,0 +1,225 @@
+use chrono::{Duration, NaiveTime, Timelike, Weekday};
+
+use crate::analysis::WEEKDAYS;
+use crate::model::{Lesson, TimeRange, WeekSchedule};
+
+#[derive(Debug, Clone)]
+pub struct TextGridOptions {
+    pub column_width: usize,
+    // Minutes per row
+    pub step: u32,
+    // Unicode box drawing characters instead of plain ASCII
+    pub unicode: bool,
+    // Color lesson cells with their `b_color` using ANSI escapes
+    pub color: Option<ColorMode>,
+}
+
+impl Default for TextGridOptions {
+    fn default() -> Self {
+        TextGridOptions {
+            column_width: 14,
+            step: 15,
+            unicode: true,
+            color: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorMode {
+    // Best mode the terminal advertises through $COLORTERM and $TERM
+    pub fn detect() -> Option<ColorMode> {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            Some(ColorMode::TrueColor)
+        }
+        else if term.contains("256color") {
+            Some(ColorMode::Ansi256)
+        }
+        else if term.is_empty() || term == "dumb" {
+            None
+        }
+        else {
+            Some(ColorMode::Ansi16)
+        }
+    }
+
+    fn escape(self, (r, g, b): (u8, u8, u8)) -> String {
+        // Dark text on light backgrounds and vice versa
+        let luminance = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+        let fg = if luminance > 140.0 {30} else {97};
+
+        match self {
+            ColorMode::TrueColor => format!("\x1b[{};48;2;{};{};{}m", fg, r, g, b),
+            ColorMode::Ansi256 => {
+                let cube = |c: u8| (c as u16 * 5 + 127) / 255;
+                format!("\x1b[{};48;5;{}m", fg, 16 + 36 * cube(r) + 6 * cube(g) + cube(b))
+            },
+            ColorMode::Ansi16 => {
+                let bit = |c: u8, b: u8| if c > 127 {b} else {0};
+                let base = if r.max(g).max(b) > 191 {100} else {40};
+                let index = bit(r, 1) | bit(g, 2) | bit(b, 4);
+                format!("\x1b[{};{}m", fg, base + index as u16)
+            },
+        }
+    }
+}
+
+pub(crate) fn parse_hex(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#')?;
+    Some((
+        u8::from_str_radix(hex.get(0..2)?, 16).ok()?,
+        u8::from_str_radix(hex.get(2..4)?, 16).ok()?,
+        u8::from_str_radix(hex.get(4..6)?, 16).ok()?,
+    ))
+}
+
+fn colorize(mode: Option<ColorMode>, lesson: Option<&Lesson>, cell: String) -> String {
+    match (mode, lesson.and_then(|lesson| parse_hex(lesson.color.as_deref()?))) {
+        (Some(mode), Some(rgb)) => format!("{}{}\x1b[0m", mode.escape(rgb), cell),
+        _ => cell,
+    }
+}
+
+struct Glyphs {
+    vertical: char,
+    horizontal: char,
+    cross: char,
+    lesson_start: char,
+}
+
+impl Glyphs {
+    fn new(unicode: bool) -> Self {
+        if unicode {
+            Glyphs { vertical: '│', horizontal: '─', cross: '┼', lesson_start: '┌' }
+        }
+        else {
+            Glyphs { vertical: '|', horizontal: '-', cross: '+', lesson_start: '+' }
+        }
+    }
+}
+
+pub(crate) struct Column<'a> {
+    pub weekday: Option<Weekday>,
+    pub title: String,
+    pub lessons: Vec<(TimeRange, &'a Lesson)>,
+}
+
+pub fn day_to_text(day: &[Lesson], options: &TextGridOptions) -> String {
+    let weekday = day.first().map(|lesson| lesson.weekday);
+    let title = weekday.map(|weekday| weekday.to_string()).unwrap_or_default();
+
+    render(&[column(weekday, title, day)], options, |lesson, cell| colorize(options.color, lesson, cell))
+}
+
+pub fn week_to_text(week: &WeekSchedule, options: &TextGridOptions) -> String {
+    render(&week_columns(week), options, |lesson, cell| colorize(options.color, lesson, cell))
+}
+
+pub(crate) fn week_columns(week: &WeekSchedule) -> Vec<Column<'_>> {
+    WEEKDAYS.iter()
+        .copied()
+        .filter(|&day| !matches!(day, Weekday::Sat | Weekday::Sun) || !week.lessons_on(day).is_empty())
+        .map(|day| column(Some(day), day.to_string(), week.lessons_on(day)))
+        .collect()
+}
+
+fn column<'a>(weekday: Option<Weekday>, title: String, lessons: &'a [Lesson]) -> Column<'a> {
+    let mut lessons: Vec<(TimeRange, &Lesson)> = lessons.iter()
+        .map(|lesson| (lesson.time_range(), lesson))
+        .collect();
+    lessons.sort_by_key(|(range, _)| *range);
+
+    Column { weekday, title, lessons }
+}
+
+// Lays out the columns on a shared time axis. `style` gets the lesson covering a cell, if any,
+// and the cell's text padded to the column width.
+pub(crate) fn render<F>(columns: &[Column], options: &TextGridOptions, style: F) -> String
+where
+    F: Fn(Option<&Lesson>, String) -> String,
+{
+    let glyphs = Glyphs::new(options.unicode);
+    let width = options.column_width.max(1);
+    let step = Duration::minutes(options.step.max(1) as i64);
+
+    let first = columns.iter().flat_map(|c| c.lessons.iter().map(|(range, _)| range.start)).min();
+    let last = columns.iter().flat_map(|c| c.lessons.iter().map(|(range, _)| range.end)).max();
+    let (first, last) = match (first, last) {
+        (Some(first), Some(last)) => (round_down(first, options.step.max(1)), last),
+        _ => return String::new(),
+    };
+
+    let mut out = format!("{:6}", "");
+    for column in columns {
+        out.push(glyphs.vertical);
+        out += &pad(&format!(" {}", column.title), width);
+    }
+    out.push('\n');
+    out += &glyphs.horizontal.to_string().repeat(6);
+    for _ in columns {
+        out.push(glyphs.cross);
+        out += &glyphs.horizontal.to_string().repeat(width);
+    }
+    out.push('\n');
+
+    let mut time = first;
+    while time < last {
+        let row_end = time + step;
+        out += &format!("{:6}", time.format("%H:%M"));
+        for column in columns {
+            out.push(glyphs.vertical);
+
+            let covering = column.lessons.iter().find(|(range, _)| range.start < row_end && range.end > time);
+            let text = match covering {
+                None => String::new(),
+                Some((range, lesson)) => {
+                    let first_row = range.start >= time;
+                    let second_row = !first_row && range.start >= time - step;
+                    if first_row {
+                        format!("{} {}", glyphs.lesson_start, lesson.subject)
+                    }
+                    else if second_row {
+                        format!("{} {}", glyphs.vertical, lesson.room)
+                    }
+                    else {
+                        glyphs.vertical.to_string()
+                    }
+                },
+            };
+
+            out += &style(covering.map(|(_, lesson)| *lesson), pad(&text, width));
+        }
+        out.push('\n');
+
+        if row_end <= time {
+            // Wrapped past midnight
+            break;
+        }
+        time = row_end;
+    }
+
+    out
+}
+
+fn round_down(time: NaiveTime, step: u32) -> NaiveTime {
+    let minutes = time.num_seconds_from_midnight() / 60;
+    let rounded = minutes - minutes % step;
+    NaiveTime::from_hms_opt(rounded / 60, rounded % 60, 0).unwrap_or(time)
+}
+
+// Truncates or pads to exactly `width` characters
+fn pad(text: &str, width: usize) -> String {
+    let mut padded: String = text.chars().take(width).collect();
+    let len = padded.chars().count();
+    padded += &" ".repeat(width - len);
+    padded
+}
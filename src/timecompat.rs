@@ -0,0 +1,59 @@
+use chrono::{Datelike, NaiveDate, NaiveTime, Timelike};
+
+use std::convert::TryFrom;
+
+use crate::data::LessonInfo;
+use crate::model::Lesson;
+
+// The lesson types with their times and dates as `time` crate types, for code that uses it
+// instead of chrono, e.g. `lesson.start_as_time()`. The chrono fields stay the source.
+
+pub fn time(time: NaiveTime) -> time::Time {
+    // chrono puts leap seconds into the nanoseconds, `time` has none
+    let nanosecond = time.nanosecond().min(999_999_999);
+    time::Time::from_hms_nano(time.hour() as u8, time.minute() as u8, time.second() as u8, nanosecond)
+        .unwrap_or(time::Time::MIDNIGHT)
+}
+
+// `None` for years `time` can't represent
+pub fn date(date: NaiveDate) -> Option<time::Date> {
+    let month = time::Month::try_from(date.month() as u8).ok()?;
+    time::Date::from_calendar_date(date.year(), month, date.day() as u8).ok()
+}
+
+impl Lesson {
+    pub fn start_as_time(&self) -> time::Time {
+        time(self.start)
+    }
+
+    pub fn end_as_time(&self) -> time::Time {
+        time(self.end)
+    }
+
+    pub fn date_as_time(&self) -> Option<time::Date> {
+        date(self.date?)
+    }
+
+    pub fn start_as_primitive_datetime(&self) -> Option<time::PrimitiveDateTime> {
+        Some(self.date_as_time()?.with_time(self.start_as_time()))
+    }
+
+    pub fn end_as_primitive_datetime(&self) -> Option<time::PrimitiveDateTime> {
+        Some(self.date_as_time()?.with_time(self.end_as_time()))
+    }
+}
+
+impl LessonInfo {
+    pub fn start_as_time(&self) -> Result<time::Time, chrono::ParseError> {
+        self.start_time().map(time)
+    }
+
+    pub fn end_as_time(&self) -> Result<time::Time, chrono::ParseError> {
+        self.end_time().map(time)
+    }
+
+    // Only available once the date has been resolved, see `resolve_date`
+    pub fn date_as_time(&self) -> Option<time::Date> {
+        date(self.date?)
+    }
+}
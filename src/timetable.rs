@@ -0,0 +1,89 @@
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+
+use crate::{Dimensions, Selection};
+#[cfg(feature = "net")]
+use crate::{RequestError, SchemaRequestOptions};
+use crate::{data, diff, model, query, timezone};
+
+// A fetched week together with what it was fetched for, so it can be queried, rendered and
+// compared without passing the selection, week and raw schema around separately
+#[derive(Debug, Clone)]
+pub struct Timetable {
+    pub selection: Selection,
+    pub year: i32,
+    pub week: u32,
+    pub dimensions: Dimensions,
+    pub fetched_at: DateTime<Utc>,
+    pub schema: data::Schema,
+    pub schedule: model::WeekSchedule,
+    // Lessons left out of `schedule`, see `model::from_schema`
+    pub warnings: Vec<model::LessonWarning>,
+}
+
+impl Timetable {
+    #[cfg(feature = "net")]
+    pub async fn fetch(selection: impl Into<Selection>, week: i32, dimensions: Option<Dimensions>, options: &SchemaRequestOptions, should_cache: bool) -> Result<Timetable, RequestError> {
+        let selection = selection.into();
+        // The current week is asked for and stored in its ISO year, which `current_lesson`
+        // compares with
+        let year = crate::year_of_week(week);
+        // Day 0 makes the API return the whole week
+        let schema = crate::get_schema_in(selection.clone(), 0, year, week, dimensions, options, should_cache).await?;
+
+        let mut timetable = Timetable::from_schema(selection, year, week as u32, dimensions.unwrap_or_default(), schema.data);
+        if let Some(age) = schema.stale_age.and_then(|age| chrono::Duration::from_std(age).ok()) {
+            timetable.fetched_at -= age;
+        }
+        Ok(timetable)
+    }
+
+    pub fn from_schema(selection: Selection, year: i32, week: u32, dimensions: Dimensions, schema: data::Schema) -> Timetable {
+        let (schedule, warnings) = model::from_schema(&schema, year, week);
+
+        Timetable {
+            selection,
+            year,
+            week,
+            dimensions,
+            fetched_at: timezone::now(),
+            schema,
+            schedule,
+            warnings,
+        }
+    }
+
+    // `now` is wall clock time in `timezone::SCHEDULE_TIMEZONE`. `None` outside of this week.
+    pub fn current_lesson(&self, now: NaiveDateTime) -> Option<&model::Lesson> {
+        let iso_week = now.date().iso_week();
+        if (iso_week.year(), iso_week.week()) != (self.year, self.week) {
+            return None;
+        }
+
+        query::current_lesson(self.schedule.lessons_on(now.weekday()), now.time())
+    }
+
+    // The first lesson starting after `now` in this week, on the same or a later day
+    pub fn next_lesson(&self, now: NaiveDateTime) -> Option<&model::Lesson> {
+        self.schedule.lessons()
+            .filter_map(|lesson| Some((lesson.start_datetime()?, lesson)))
+            .filter(|(start, _)| *start > now)
+            .min_by_key(|(start, _)| *start)
+            .map(|(_, lesson)| lesson)
+    }
+
+    // Drawn at the dimensions the schema was fetched with
+    #[cfg(feature = "svg")]
+    pub fn render_svg(&self, options: &crate::image::SvgOptions) -> Result<svg::Document, crate::image::RenderError> {
+        crate::image::generate_svg(&self.schema, self.dimensions, options)
+    }
+
+    #[cfg(feature = "ics")]
+    pub fn to_ics(&self) -> String {
+        crate::ics::to_ical(&self.schedule)
+    }
+
+    // What changed from `self` to `newer`, e.g. a fresh fetch of the same week
+    pub fn diff(&self, newer: &Timetable) -> Vec<diff::ScheduleChange> {
+        diff::diff_schedules(&self.schedule, &newer.schedule)
+    }
+}
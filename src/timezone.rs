@@ -0,0 +1,95 @@
+use chrono::{DateTime, LocalResult, NaiveDateTime, TimeZone, Utc};
+pub use chrono_tz::Tz;
+
+use std::sync::RwLock;
+
+use crate::model::Lesson;
+
+// skola24 renders lesson times as Swedish wall clock time
+pub const SCHEDULE_TIMEZONE: Tz = chrono_tz::Europe::Stockholm;
+
+// Where the helpers that depend on the current time, e.g. `now_in`, `get_today` and
+// `print_lessons`, get it from
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    fn now_in(&self, tz: Tz) -> NaiveDateTime {
+        wall_clock(&self.now(), tz)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+// Always returns the same instant, e.g. to test what is shown at a given time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl FixedClock {
+    // At a wall clock time in `tz`, `None` for times skipped at the start of DST
+    pub fn at(time: NaiveDateTime, tz: Tz) -> Option<Self> {
+        Some(FixedClock(localize(time, tz)?.with_timezone(&Utc)))
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+static CLOCK: RwLock<Option<Box<dyn Clock>>> = RwLock::new(None);
+
+// Replaces the system clock for the whole process
+pub fn set_clock(clock: impl Clock + 'static) {
+    if let Ok(mut current) = CLOCK.write() {
+        *current = Some(Box::new(clock));
+    }
+}
+
+pub fn reset_clock() {
+    if let Ok(mut current) = CLOCK.write() {
+        *current = None;
+    }
+}
+
+// The time of the clock set with `set_clock`, the system clock by default
+pub fn now() -> DateTime<Utc> {
+    match CLOCK.read() {
+        Ok(clock) => clock.as_ref().map_or_else(Utc::now, |clock| clock.now()),
+        Err(_) => Utc::now(),
+    }
+}
+
+// The current wall clock time in `tz`, regardless of the host's local timezone
+pub fn now_in(tz: Tz) -> NaiveDateTime {
+    wall_clock(&now(), tz)
+}
+
+pub fn wall_clock<T: TimeZone>(time: &DateTime<T>, tz: Tz) -> NaiveDateTime {
+    time.with_timezone(&tz).naive_local()
+}
+
+// Attaches `tz` to a wall clock time. Ambiguous times at the end of DST resolve to the
+// earlier instant, times skipped at the start of DST to `None`.
+pub fn localize(time: NaiveDateTime, tz: Tz) -> Option<DateTime<Tz>> {
+    match tz.from_local_datetime(&time) {
+        LocalResult::Single(time) => Some(time),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest),
+        LocalResult::None => None,
+    }
+}
+
+pub fn lesson_start(lesson: &Lesson, tz: Tz) -> Option<DateTime<Tz>> {
+    localize(lesson.start_datetime()?, tz)
+}
+
+pub fn lesson_end(lesson: &Lesson, tz: Tz) -> Option<DateTime<Tz>> {
+    localize(lesson.end_datetime()?, tz)
+}
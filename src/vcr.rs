@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::{Endpoint, RequestError};
+
+// Records the responses of skola24 to fixture files and plays them back instead of sending
+// requests, so code using this crate can be tested without the live API:
+//
+//   vcr::set_mode(VcrMode::Record("tests/fixtures".into()));   // once, against the API
+//   vcr::set_mode(VcrMode::Replay("tests/fixtures".into()));   // in the tests
+//
+// Requests are matched by endpoint and body, without the render key. Bodies hold the current
+// year, so replays should fix the clock with `timezone::set_clock`, and pass
+// `should_cache = false` or use a fresh cache directory to reach the transport at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VcrMode {
+    Off,
+    // Sends requests and writes every response to the directory
+    Record(PathBuf),
+    // Answers from the directory only, failing with `RequestError::MissingFixture`
+    Replay(PathBuf),
+}
+
+static MODE: RwLock<VcrMode> = RwLock::new(VcrMode::Off);
+
+pub fn set_mode(mode: VcrMode) {
+    if let Ok(mut current) = MODE.write() {
+        *current = mode;
+    }
+}
+
+pub fn mode() -> VcrMode {
+    MODE.read().map_or(VcrMode::Off, |mode| mode.clone())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    pub endpoint: String,
+    pub request: Option<Value>,
+    pub response: String,
+}
+
+// The fixture file of a request in `dir`, e.g. `render-timetable-3f2a….json`
+pub fn fixture_path(dir: &Path, endpoint: Endpoint, request: Option<&Value>) -> PathBuf {
+    let name = endpoint.path().trim_start_matches('/').replace('/', "-");
+    let request = request.map(|request| without_render_key(request).to_string()).unwrap_or_default();
+    dir.join(format!("{}-{:016x}.json", name, fnv1a(request.as_bytes())))
+}
+
+fn without_render_key(request: &Value) -> Value {
+    let mut request = request.clone();
+    if let Value::Object(object) = &mut request {
+        object.remove("renderKey");
+    }
+    request
+}
+
+// Stable across Rust versions, unlike `DefaultHasher`, so fixture names don't change
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3))
+}
+
+// `None` unless replaying
+pub(crate) fn replay(endpoint: Endpoint, request: Option<&Value>) -> Result<Option<String>, RequestError> {
+    let dir = match mode() {
+        VcrMode::Replay(dir) => dir,
+        _ => return Ok(None),
+    };
+    let path = fixture_path(&dir, endpoint, request);
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(RequestError::MissingFixture(path)),
+        Err(e) => return Err(e.into()),
+    };
+    debug!("{}: replaying {}", endpoint.path(), path.display());
+    Ok(Some(serde_json::from_slice::<Fixture>(&data)?.response))
+}
+
+pub(crate) fn record(endpoint: Endpoint, request: Option<&Value>, response: &str) -> Result<(), RequestError> {
+    let dir = match mode() {
+        VcrMode::Record(dir) => dir,
+        _ => return Ok(()),
+    };
+    std::fs::create_dir_all(&dir)?;
+    let path = fixture_path(&dir, endpoint, request);
+    let fixture = Fixture {
+        endpoint: endpoint.path().to_string(),
+        request: request.map(without_render_key),
+        response: response.to_string(),
+    };
+    std::fs::write(&path, serde_json::to_vec_pretty(&fixture)?)?;
+    debug!("{}: recorded {}", endpoint.path(), path.display());
+    Ok(())
+}
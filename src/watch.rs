@@ -0,0 +1,232 @@
+use chrono::{Datelike, Duration as ChronoDuration, NaiveDateTime};
+use futures::stream::{self, Stream};
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::data::LessonId;
+use crate::model::{Lesson, WeekSchedule};
+use crate::{diff::{self, ScheduleChange}, fetch_schedule, timezone, RequestError, Selection};
+
+struct WatchState {
+    selection: Selection,
+    interval: Duration,
+    // ISO year and week being watched
+    year: i32,
+    week: u32,
+    previous: Option<WeekSchedule>,
+    // Set after the first fetch of a week failed, so the next one waits for the interval
+    retry: bool,
+}
+
+// Polls the current week's schedule every `interval` and yields the changes compared
+// to the previous fetch, starting from the cached version. Polls without changes yield nothing.
+pub fn watch(selection: Selection, interval: Duration) -> impl Stream<Item = Result<Vec<ScheduleChange>, RequestError>> {
+    let (year, week) = current_week();
+    let state = WatchState {
+        selection,
+        interval,
+        year,
+        week,
+        previous: None,
+        retry: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if state.previous.is_none() {
+                if state.retry {
+                    async_std::task::sleep(state.interval).await;
+                }
+                match fetch_schedule(state.selection.clone(), 0, state.year, state.week, true).await {
+                    Ok(schedule) => {
+                        state.previous = Some(schedule);
+                        state.retry = false;
+                    },
+                    Err(e) => {
+                        state.retry = true;
+                        return Some((Err(e), state));
+                    },
+                }
+            }
+
+            async_std::task::sleep(state.interval).await;
+
+            let (year, week) = current_week();
+            if (year, week) != (state.year, state.week) {
+                // Comparing against another week would report every lesson as changed
+                state.year = year;
+                state.week = week;
+                state.previous = None;
+                continue;
+            }
+
+            let schedule = match fetch_schedule(state.selection.clone(), 0, state.year, state.week, false).await {
+                Ok(schedule) => schedule,
+                Err(e) => return Some((Err(e), state)),
+            };
+
+            let changes = match &state.previous {
+                Some(previous) => diff::diff_schedules(previous, &schedule),
+                None => Vec::new(),
+            };
+            state.previous = Some(schedule);
+            if !changes.is_empty() {
+                return Some((Ok(changes), state));
+            }
+        }
+    })
+}
+
+fn current_week() -> (i32, u32) {
+    let week = timezone::now_in(timezone::SCHEDULE_TIMEZONE).iso_week();
+    (week.year(), week.week())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reminder {
+    pub lesson: Lesson,
+    // Wall clock time in `timezone::SCHEDULE_TIMEZONE`
+    pub starts_at: NaiveDateTime,
+    // The offset of `ReminderOptions::offsets` this reminder is for
+    pub before: ChronoDuration,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReminderOptions {
+    // How long before each lesson to remind, one reminder per offset
+    pub offsets: Vec<ChronoDuration>,
+    // How often to fetch the schedule again to pick up changes
+    pub poll_interval: Duration,
+}
+
+impl Default for ReminderOptions {
+    fn default() -> Self {
+        ReminderOptions {
+            offsets: vec![ChronoDuration::minutes(10)],
+            poll_interval: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+impl ReminderOptions {
+    pub fn offsets(mut self, offsets: impl IntoIterator<Item = ChronoDuration>) -> Self {
+        self.offsets = offsets.into_iter().collect();
+        self
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+}
+
+struct ReminderState {
+    selection: Selection,
+    options: ReminderOptions,
+    // ISO year and week of the fetched lessons
+    weeks: Vec<(i32, u32)>,
+    lessons: Vec<Lesson>,
+    polled: Option<NaiveDateTime>,
+    sent: HashSet<(LessonId, NaiveDateTime, ChronoDuration)>,
+}
+
+impl ReminderState {
+    // The week of now and, if the largest offset reaches into it, the next one
+    fn weeks_needed(&self, now: NaiveDateTime) -> Vec<(i32, u32)> {
+        let ahead = self.options.offsets.iter().copied().max().unwrap_or_else(ChronoDuration::zero);
+        let mut weeks = Vec::new();
+        for time in [now, now + ahead].iter() {
+            let week = time.iso_week();
+            if !weeks.contains(&(week.year(), week.week())) {
+                weeks.push((week.year(), week.week()));
+            }
+        }
+        weeks
+    }
+
+    // The earliest reminder due at `now` that was not sent yet, otherwise when the next one is due
+    fn next(&self, now: NaiveDateTime) -> Result<Reminder, Option<NaiveDateTime>> {
+        let mut due_now: Option<(NaiveDateTime, Reminder)> = None;
+        let mut next_due: Option<NaiveDateTime> = None;
+        for lesson in &self.lessons {
+            let start = match lesson.start_datetime() {
+                Some(start) => start,
+                None => continue,
+            };
+            for &before in &self.options.offsets {
+                let due = start - before;
+                if self.sent.contains(&(lesson.id.clone(), start, before)) {
+                    continue;
+                }
+                // Late reminders still go out until the lesson starts, reminders at the start
+                // for a minute
+                if due <= now && now < start.max(due + ChronoDuration::minutes(1)) {
+                    if due_now.as_ref().is_none_or(|(earliest, _)| due < *earliest) {
+                        due_now = Some((due, Reminder { lesson: lesson.clone(), starts_at: start, before }));
+                    }
+                }
+                else if due > now && next_due.is_none_or(|next| due < next) {
+                    next_due = Some(due);
+                }
+            }
+        }
+        due_now.map(|(_, reminder)| reminder).ok_or(next_due)
+    }
+}
+
+// Yields a `Reminder` at each of `options.offsets` before every lesson of the selection,
+// following the schedule into the next week and picking up changes every
+// `options.poll_interval`. Moved lessons are reminded of at their new time, cancelled ones no
+// more. Errors are yielded and retried at the next poll.
+pub fn reminders(selection: Selection, options: ReminderOptions) -> impl Stream<Item = Result<Reminder, RequestError>> {
+    let state = ReminderState {
+        selection,
+        options,
+        weeks: Vec::new(),
+        lessons: Vec::new(),
+        polled: None,
+        sent: HashSet::new(),
+    };
+
+    stream::unfold(state, |mut state| async move {
+        let poll_interval = ChronoDuration::from_std(state.options.poll_interval).unwrap_or_else(|_| ChronoDuration::minutes(15));
+        loop {
+            let now = timezone::now_in(timezone::SCHEDULE_TIMEZONE);
+            let weeks = state.weeks_needed(now);
+            if weeks != state.weeks || state.polled.is_none_or(|polled| now - polled >= poll_interval) {
+                state.polled = Some(now);
+                let mut lessons = Vec::new();
+                for &(year, week) in &weeks {
+                    // Weeks seen for the first time start from the cache, later polls ask the API
+                    let should_cache = !state.weeks.contains(&(year, week));
+                    match fetch_schedule(state.selection.clone(), 0, year, week, should_cache).await {
+                        Ok(schedule) => lessons.extend(schedule.days.into_iter().flat_map(|day| day.lessons)),
+                        Err(e) => {
+                            // Counts as a poll, so the weeks are asked for again only after
+                            // `poll_interval` and not right away
+                            state.weeks = weeks;
+                            return Some((Err(e), state));
+                        },
+                    }
+                }
+                state.weeks = weeks;
+                state.lessons = lessons;
+                state.sent.retain(|(_, start, _)| *start >= now);
+            }
+
+            let next_due = match state.next(now) {
+                Ok(reminder) => {
+                    state.sent.insert((reminder.lesson.id.clone(), reminder.starts_at, reminder.before));
+                    return Some((Ok(reminder), state));
+                },
+                Err(next_due) => next_due,
+            };
+
+            let next_poll = state.polled.unwrap_or(now) + poll_interval;
+            let wake = next_due.map_or(next_poll, |due| due.min(next_poll));
+            let wait = (wake - now).to_std().unwrap_or_default().max(Duration::from_secs(1));
+            async_std::task::sleep(wait).await;
+        }
+    })
+}
@@ -0,0 +1,125 @@
+use chrono::NaiveTime;
+use rust_xlsxwriter::{Color, ColNum, Format, FormatAlign, FormatBorder, RowNum, Workbook, Worksheet};
+pub use rust_xlsxwriter::XlsxError;
+
+use crate::model::{Lesson, TimeRange, WeekSchedule};
+use crate::text::{parse_hex, week_columns};
+
+// Points per minute of lesson time
+const ROW_SCALE: f64 = 0.6;
+
+// One sheet per week, e.g. `to_xlsx(&term.weeks)`. Rows are the week's distinct lesson
+// boundaries, and a lesson is merged across the rows it spans. Overlapping lessons on the
+// same day are put in extra columns under the day's heading.
+pub fn to_xlsx<'a, I>(weeks: I) -> Result<Vec<u8>, XlsxError>
+where
+    I: IntoIterator<Item = &'a WeekSchedule>,
+{
+    let mut workbook = Workbook::new();
+    for week in weeks {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(format!("Week {} {}", week.week, week.year))?;
+        write_week(sheet, week)?;
+    }
+
+    workbook.save_to_buffer()
+}
+
+fn write_week(sheet: &mut Worksheet, week: &WeekSchedule) -> Result<(), XlsxError> {
+    let heading = Format::new().set_bold().set_align(FormatAlign::Center).set_border(FormatBorder::Thin);
+    let axis = Format::new().set_align(FormatAlign::Top);
+
+    let columns = week_columns(week);
+    let mut times: Vec<NaiveTime> = columns.iter()
+        .flat_map(|c| c.lessons.iter().flat_map(|(range, _)| vec![range.start, range.end]))
+        .collect();
+    times.sort_unstable();
+    times.dedup();
+    let row_of = |time: NaiveTime| 1 + times.binary_search(&time).unwrap_or(0) as RowNum;
+
+    sheet.set_column_width(0, 7)?;
+    sheet.set_freeze_panes(1, 1)?;
+    for (i, pair) in times.windows(2).enumerate() {
+        let row = 1 + i as RowNum;
+        sheet.write_string_with_format(row, 0, pair[0].format("%H:%M").to_string(), &axis)?;
+        sheet.set_row_height(row, ((pair[1] - pair[0]).num_minutes() as f64 * ROW_SCALE).max(12.0))?;
+    }
+    if let Some(last) = times.last() {
+        sheet.write_string_with_format(times.len() as RowNum, 0, last.format("%H:%M").to_string(), &axis)?;
+    }
+
+    let mut col: ColNum = 1;
+    for column in &columns {
+        let lanes = lanes(&column.lessons);
+        let width = lanes.iter().copied().max().map_or(1, |lane| lane + 1) as ColNum;
+
+        let title = match column.weekday.and_then(|day| week.date_of(day)) {
+            Some(date) => format!("{} {}", column.title, date.format("%d/%m")),
+            None => column.title.clone(),
+        };
+        if width > 1 {
+            sheet.merge_range(0, col, 0, col + width - 1, &title, &heading)?;
+        }
+        else {
+            sheet.write_string_with_format(0, col, &title, &heading)?;
+        }
+        for lane in 0..width {
+            sheet.set_column_width(col + lane, 18.0 / width as f64)?;
+        }
+
+        for ((range, lesson), lane) in column.lessons.iter().zip(lanes) {
+            let first = row_of(range.start);
+            let last = row_of(range.end) - 1;
+            let lane_col = col + lane as ColNum;
+            let format = lesson_format(lesson);
+            if last > first {
+                sheet.merge_range(first, lane_col, last, lane_col, &cell_text(lesson), &format)?;
+            }
+            else {
+                sheet.write_string_with_format(first, lane_col, cell_text(lesson), &format)?;
+            }
+        }
+
+        col += width;
+    }
+
+    Ok(())
+}
+
+// Lowest free lane for each lesson, the lessons being sorted by start time
+fn lanes(lessons: &[(TimeRange, &Lesson)]) -> Vec<usize> {
+    let mut lane_ends: Vec<NaiveTime> = Vec::new();
+    lessons.iter()
+        .map(|(range, _)| match lane_ends.iter().position(|end| *end <= range.start) {
+            Some(lane) => {
+                lane_ends[lane] = range.end;
+                lane
+            },
+            None => {
+                lane_ends.push(range.end);
+                lane_ends.len() - 1
+            },
+        })
+        .collect()
+}
+
+fn lesson_format(lesson: &Lesson) -> Format {
+    let format = Format::new()
+        .set_text_wrap()
+        .set_align(FormatAlign::Top)
+        .set_border(FormatBorder::Thin);
+
+    match lesson.color.as_deref().and_then(parse_hex) {
+        Some((r, g, b)) => format.set_background_color(Color::RGB((r as u32) << 16 | (g as u32) << 8 | b as u32)),
+        None => format,
+    }
+}
+
+fn cell_text(lesson: &Lesson) -> String {
+    [&lesson.subject, &lesson.teacher, &lesson.room]
+        .iter()
+        .filter(|text| !text.is_empty())
+        .map(|text| text.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}